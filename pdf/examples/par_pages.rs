@@ -0,0 +1,41 @@
+//! Compares parsing the content of every page serially and with `PageTree::par_pages`.
+//!
+//! `cargo run --release --example par_pages --features rayon -- file.pdf`
+use std::env;
+use std::time::Instant;
+
+use pdf::error::PdfError;
+use pdf::file::FileOptions;
+use pdf::object::*;
+use rayon::iter::ParallelIterator;
+
+fn count_ops(page: &Page, resolve: &impl Resolve) -> Result<usize, PdfError> {
+    match page.contents {
+        Some(ref content) => Ok(content.operations(resolve)?.len()),
+        None => Ok(0),
+    }
+}
+
+fn main() -> Result<(), PdfError> {
+    let path = env::args().nth(1).expect("no file given");
+
+    // a fresh file for each run, so that neither run profits from the other's cache
+    let file = FileOptions::cached().open(&path)?;
+    let resolver = file.resolver();
+    let start = Instant::now();
+    let mut ops = 0;
+    for page in file.pages() {
+        ops += count_ops(&*page?, &resolver)?;
+    }
+    println!("serial:   {} pages, {} ops in {:?}", file.num_pages(), ops, start.elapsed());
+
+    let file = FileOptions::cached().open(&path)?;
+    let resolver = file.resolver();
+    let start = Instant::now();
+    let ops = file.get_root().pages.par_pages(&resolver)
+        .map(|page| count_ops(&*page?, &resolver))
+        .sum::<Result<usize, PdfError>>()?;
+    println!("parallel: {} pages, {} ops in {:?}", file.num_pages(), ops, start.elapsed());
+
+    Ok(())
+}
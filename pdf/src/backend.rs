@@ -42,8 +42,12 @@ pub trait Backend: Sized {
         // locate the xref offset at the end of the file
         // `\nPOS\n%%EOF` where POS is the position encoded as base 10 integer.
         // u64::MAX has 20 digits + \n\n(2) + %%EOF(5) = 27 bytes max.
+        // Files often have junk after `%%EOF`, so the last `startxref` within
+        // `STARTXREF_WINDOW` bytes of the end is used.
+        const STARTXREF_WINDOW: usize = 16 * 1024;
 
-        let mut lexer = Lexer::new(t!(self.read(..)));
+        let start = self.len().saturating_sub(STARTXREF_WINDOW);
+        let mut lexer = Lexer::new(t!(self.read(start ..)));
         lexer.set_pos_from_end(0);
         t!(lexer.seek_substr_back(b"startxref"));
         t!(lexer.next()).to::<usize>()
@@ -91,8 +95,9 @@ pub trait Backend: Sized {
             let mut lexer = Lexer::with_offset(t!(self.read(pos..)), pos);
             let (xref_sections, trailer) = t!(read_xref_and_trailer_at(&mut lexer, resolve));
             
+            // entries of newer sections take precedence
             for section in xref_sections {
-                refs.add_entries_from(section)?;
+                refs.add_older_entries_from(section);
             }
             
             prev_trailer = {
@@ -177,3 +182,65 @@ impl IndexRange for Range<usize> {
     #[inline]
     fn end(&self) -> Option<usize> { Some(self.end) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xref::{XRef, XRefSection};
+
+    /// Builds a file with an incremental update that replaces object 1 and frees object 3.
+    /// Also returns the position of the newest xref table.
+    fn updated_file(prev_override: Option<usize>) -> (Vec<u8>, usize) {
+        let mut file = b"%PDF-1.4\n".to_vec();
+        let obj = |file: &mut Vec<u8>, s: &str| {
+            let pos = file.len();
+            file.extend_from_slice(s.as_bytes());
+            pos
+        };
+        let old1 = obj(&mut file, "1 0 obj\n(old)\nendobj\n");
+        let two = obj(&mut file, "2 0 obj\n(two)\nendobj\n");
+        let three = obj(&mut file, "3 0 obj\n(three)\nendobj\n");
+        let xref1 = obj(&mut file, &format!(
+            "xref\n0 4\n0000000000 65535 f \n{:010} 00000 n \n{:010} 00000 n \n{:010} 00000 n \ntrailer\n<< /Size 4 >>\n",
+            old1, two, three
+        ));
+        let new1 = obj(&mut file, "1 0 obj\n(new)\nendobj\n");
+        let xref2 = file.len();
+        let prev = prev_override.unwrap_or(xref1);
+        obj(&mut file, &format!(
+            "xref\n0 2\n0000000003 65535 f \n{:010} 00000 n \n3 1\n0000000000 00001 f \n\
+            trailer\n<< /Size 4 /Prev {} /Root 9 0 R >>\nstartxref\n{}\n%%EOF",
+            new1, prev, xref2
+        ));
+        (file, xref2)
+    }
+
+    #[test]
+    fn prev_chain() {
+        let (file, _) = updated_file(None);
+        let (refs, trailer) = file.read_xref_table_and_trailer(0, &NoResolve).unwrap();
+        // the newest trailer is returned
+        assert!(trailer.get("Root").is_some());
+
+        let pos = |s: &str| file.windows(s.len()).position(|w| w == s.as_bytes()).unwrap();
+        assert!(matches!(refs.get(1).unwrap(), XRef::Raw { pos: p, .. } if p == pos("1 0 obj\n(new)")));
+        assert!(matches!(refs.get(2).unwrap(), XRef::Raw { pos: p, .. } if p == pos("2 0 obj")));
+        assert!(matches!(refs.get(3).unwrap(), XRef::Free { gen_nr: 1, .. }));
+
+        // an older section does not replace a newer entry, whatever its generation
+        let mut section = XRefSection::new(3);
+        section.add_inuse_entry(10, 5);
+        let mut refs = refs;
+        refs.add_older_entries_from(section);
+        assert!(matches!(refs.get(3).unwrap(), XRef::Free { gen_nr: 1, .. }));
+    }
+
+    #[test]
+    fn prev_loop() {
+        // the update points back at itself
+        let (_, xref2) = updated_file(None);
+        let (file, _) = updated_file(Some(xref2));
+        let err = file.read_xref_table_and_trailer(0, &NoResolve).unwrap_err();
+        assert!(err.to_string().contains("xref offsets loop"), "{}", err);
+    }
+}
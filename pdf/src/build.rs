@@ -8,7 +8,9 @@ use datasize::DataSize;
 use crate::PdfError;
 use crate::any::AnySync;
 use crate::enc::StreamFilter;
+use crate::backend::Backend;
 use crate::file::Cache;
+use crate::file::File;
 use crate::file::FileOptions;
 use crate::file::Log;
 use crate::file::Storage;
@@ -18,7 +20,9 @@ use crate::content::*;
 use crate::error::Result;
 use crate::parser::ParseFlags;
 use crate::primitive::Dictionary;
+use crate::primitive::Name;
 use crate::primitive::Primitive;
+use crate::writer::PdfWriter;
 
 #[derive(Default)]
 pub struct PageBuilder {
@@ -47,13 +51,18 @@ impl PageBuilder {
             crop_box: Some(page.crop_box()?),
             trim_box: page.trim_box,
             resources: (**page.resources()?.data()).clone(),
-            rotate: page.rotate,
+            rotate: page.effective_rotation(resolve)?,
             metadata: page.metadata.clone(),
             lgi: page.lgi.clone(),
             vp: page.vp.clone(),
             other: page.other.clone(),
         })
     }
+    /// Copies `page` and everything it refers to with `cloner`.
+    ///
+    /// Annotations are not copied: they point back to their page with `/P`, and links and
+    /// form fields point to other pages and fields of the original file, which would be
+    /// copied along with them.
     pub fn clone_page(page: &Page, cloner: &mut impl Cloner) -> Result<PageBuilder> {
         let old_resources = &**page.resources()?.data();
 
@@ -74,7 +83,7 @@ impl PageBuilder {
             crop_box: Some(page.crop_box()?),
             trim_box: page.trim_box,
             resources,
-            rotate: page.rotate,
+            rotate: page.effective_rotation(cloner)?,
             metadata: page.metadata.deep_clone(cloner)?,
             lgi: page.lgi.deep_clone(cloner)?,
             vp: page.vp.deep_clone(cloner)?,
@@ -127,7 +136,8 @@ impl CatalogBuilder {
                 crop_box: page.crop_box,
                 trim_box: page.trim_box,
                 resources: Some(resources),
-                rotate: page.rotate,
+                // the page tree doesn't set `/Rotate`
+                rotate: Some(page.rotate).filter(|&r| r != 0),
                 metadata: page.metadata,
                 lgi: page.lgi,
                 vp: page.vp,
@@ -147,10 +157,42 @@ impl CatalogBuilder {
             struct_tree_root: None,
             forms: None,
             page_labels: None,
+            optional_content: None,
         })
     }
 }
 
+/// Builds a `Dictionary` from typed values.
+///
+/// Every value is converted with `ObjectWrite::to_primitive` when it is inserted.
+pub struct DictionaryBuilder<'a, U> {
+    update: &'a mut U,
+    dict: Dictionary,
+}
+impl<'a, U: Updater> DictionaryBuilder<'a, U> {
+    pub fn new(update: &'a mut U) -> Self {
+        DictionaryBuilder {
+            update,
+            dict: Dictionary::new(),
+        }
+    }
+    pub fn insert(mut self, key: impl Into<Name>, value: impl ObjectWrite) -> Result<Self> {
+        let value = value.to_primitive(self.update)?;
+        self.dict.insert(key, value);
+        Ok(self)
+    }
+    /// like `insert`, but does nothing if `value` is `None`
+    pub fn insert_opt(self, key: impl Into<Name>, value: Option<impl ObjectWrite>) -> Result<Self> {
+        match value {
+            Some(value) => self.insert(key, value),
+            None => Ok(self)
+        }
+    }
+    pub fn build(self) -> Dictionary {
+        self.dict
+    }
+}
+
 pub struct PdfBuilder<SC, OC, L> {
     pub storage: Storage<Vec<u8>, SC, OC, L>,
     pub info: Option<InfoDict>,
@@ -284,8 +326,8 @@ impl<R: Resolve> ImporterMap<R> {
                 }
                 Ok(true)
             }
-            (Primitive::Integer(a), Primitive::Number(b)) => Ok(*a as f32 == *b),
-            (Primitive::Number(a), Primitive::Integer(b)) => Ok(*a == *b as f32),
+            (Primitive::Integer(a), Primitive::Number(b)) => Ok(*a as f64 == *b),
+            (Primitive::Number(a), Primitive::Integer(b)) => Ok(*a == *b as f64),
             (Primitive::Reference(a_ref), b) => {
                 let a = self.resolver.resolve(*a_ref)?;
                 self.compare_prim(&a, b, new_resolve)
@@ -402,4 +444,163 @@ impl<'a, R: Resolve, U: Updater> Cloner for Importer<'a, R, U> {
         self.shared.insert(key, (AnySync::new_without_size(old.clone()), AnySync::new_without_size(new.clone())));
         Ok(new)
     }
-}
\ No newline at end of file
+}
+/// Concatenates the pages of `files` into a new file.
+///
+/// Every page is copied with `PageBuilder::clone_page`, together with everything it refers to.
+/// Objects get new numbers, and each page gets its own resource dictionary, so names used
+/// by different files can't collide. Like `clone_page`, this drops the annotations.
+pub fn merge<B, OC, SC, L>(files: &[File<B, OC, SC, L>]) -> Result<Vec<u8>>
+where
+    B: Backend,
+    OC: Cache<Result<AnySync, Arc<PdfError>>>,
+    SC: Cache<Result<Arc<[u8]>, Arc<PdfError>>>,
+    L: Log,
+{
+    let mut writer = PdfWriter::new(Vec::new())?;
+    let mut pages = Vec::new();
+    for file in files {
+        let mut importer = Importer::new(file.resolver(), &mut writer);
+        for page in file.pages() {
+            pages.push(PageBuilder::clone_page(&*page?, &mut importer)?);
+        }
+    }
+    finish_pages(writer, pages)
+}
+
+/// Writes every page of `file` to its own file.
+///
+/// Each file gets its own copies of the objects its page refers to, so objects shared by
+/// several pages are copied once per page.
+pub fn split<B, OC, SC, L>(file: &File<B, OC, SC, L>) -> Result<Vec<Vec<u8>>>
+where
+    B: Backend,
+    OC: Cache<Result<AnySync, Arc<PdfError>>>,
+    SC: Cache<Result<Arc<[u8]>, Arc<PdfError>>>,
+    L: Log,
+{
+    file.pages().map(|page| {
+        let mut writer = PdfWriter::new(Vec::new())?;
+        let mut importer = Importer::new(file.resolver(), &mut writer);
+        let page = PageBuilder::clone_page(&*page?, &mut importer)?;
+        finish_pages(writer, vec![page])
+    }).collect()
+}
+
+fn finish_pages(mut writer: PdfWriter<Vec<u8>>, pages: Vec<PageBuilder>) -> Result<Vec<u8>> {
+    let catalog = CatalogBuilder::from_pages(pages).build(&mut writer)?;
+    let root = writer.write_object(&catalog)?;
+    writer.finish(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::{NoCache, NoLog};
+
+    #[test]
+    fn dictionary_builder() {
+        let parent = Ref::<PagesNode>::new(PlainRef { id: 2, gen: 0 });
+        let dict = DictionaryBuilder::new(&mut NoUpdate)
+            .insert("Type", Name::from("Page")).unwrap()
+            .insert("Parent", parent).unwrap()
+            .insert("MediaBox", Rectangle::new(0., 0., 612., 792.)).unwrap()
+            .insert_opt("Rotate", None::<i32>).unwrap()
+            .insert_opt("UserUnit", Some(2.0f32)).unwrap()
+            .build();
+
+        let keys: Vec<&str> = dict.keys().map(|k| k.as_str()).collect();
+        assert_eq!(keys, ["Type", "Parent", "MediaBox", "UserUnit"]);
+        assert_eq!(dict["Parent"], Primitive::Reference(PlainRef { id: 2, gen: 0 }));
+        assert_eq!(dict["MediaBox"].as_array().unwrap().len(), 4);
+        assert_eq!(dict.get_typed::<Name>("Type", &NoResolve).unwrap().unwrap().as_str(), "Page");
+    }
+
+    /// A file with a page for each `(text, width)`, all using the same font object
+    fn text_file(pages: &[(&str, f32)]) -> File<Vec<u8>, NoCache, NoCache, NoLog> {
+        let mut writer = PdfWriter::new(Vec::new()).unwrap();
+        let font = crate::parser::parse(b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>", &NoResolve, ParseFlags::DICT).unwrap();
+        let font = writer.create(crate::font::Font::from_primitive(font, &NoResolve).unwrap()).unwrap();
+        let pages = pages.iter().map(|&(text, width)| {
+            let mut page = PageBuilder::default();
+            page.size(width, 100.);
+            page.resources.fonts.insert("F1".into(), MaybeRef::Indirect(font.clone()));
+            page.ops = vec![
+                Op::BeginText,
+                Op::TextFont { name: "F1".into(), size: 12. },
+                Op::TextDraw { text: text.into() },
+                Op::EndText,
+            ];
+            page
+        }).collect();
+        FileOptions::uncached().load(finish_pages(writer, pages).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn merge_files() {
+        let files = [text_file(&[("first", 200.)]), text_file(&[("second", 300.)])];
+        let file = FileOptions::uncached().load(merge(&files).unwrap()).unwrap();
+        let resolver = file.resolver();
+        assert_eq!(file.num_pages(), 2);
+
+        for (n, &(text, width)) in [("first", 200.), ("second", 300.)].iter().enumerate() {
+            let page = file.get_page(n as u32).unwrap();
+            assert_eq!(page.media_box().unwrap().width(), width);
+            let ops = page.contents.as_ref().unwrap().operations(&resolver).unwrap();
+            let resources = page.resources().unwrap();
+            assert!(resources.fonts.contains_key("F1"));
+            assert_eq!(crate::extract::extract_text(&ops, resources, &resolver).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn merge_without_annotations() {
+        use crate::file::tests::document;
+        let data = document("", "", &["/MediaBox [0 0 200 100] /Resources << >> /Annots [4 0 R]"], &[
+            "<< /Type /Annot /Subtype /Text /Rect [0 0 10 10] /P 3 0 R /Contents (note) >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        assert_eq!(file.get_page(0).unwrap().annotations(&file.resolver()).unwrap().len(), 1);
+
+        let merged = FileOptions::uncached().load(merge(&[file]).unwrap()).unwrap();
+        let resolver = merged.resolver();
+        let page = merged.get_page(0).unwrap();
+        assert_eq!(page.media_box().unwrap().width(), 200.);
+        assert!(page.annotations(&resolver).unwrap().is_empty());
+    }
+
+    #[test]
+    fn merge_rotated_pages() {
+        use crate::file::tests::document;
+        let data = document("", "/MediaBox [0 0 200 100] /Resources << >> /Rotate 90", &["", "/Rotate 0", "/Rotate 180"], &[]);
+        let file = FileOptions::uncached().load(data).unwrap();
+
+        // the explicit /Rotate 0 overrides the page tree
+        for (data, rotation) in split(&file).unwrap().into_iter().zip([90, 0, 180]) {
+            let file = FileOptions::uncached().load(data).unwrap();
+            assert_eq!(file.get_page(0).unwrap().effective_rotation(&file.resolver()).unwrap(), rotation);
+        }
+        let merged = FileOptions::uncached().load(merge(&[file]).unwrap()).unwrap();
+        let resolver = merged.resolver();
+        let rotations: Vec<_> = merged.pages().map(|p| p.unwrap().effective_rotation(&resolver).unwrap()).collect();
+        assert_eq!(rotations, [90, 0, 180]);
+    }
+
+    #[test]
+    fn split_file() {
+        let file = text_file(&[("first", 200.), ("second", 300.)]);
+        let files = split(&file).unwrap();
+        assert_eq!(files.len(), 2);
+
+        for (data, &(text, width)) in files.into_iter().zip(&[("first", 200.), ("second", 300.)]) {
+            let file = FileOptions::uncached().load(data).unwrap();
+            let resolver = file.resolver();
+            assert_eq!(file.num_pages(), 1);
+            let page = file.get_page(0).unwrap();
+            assert_eq!(page.media_box().unwrap().width(), width);
+            let ops = page.contents.as_ref().unwrap().operations(&resolver).unwrap();
+            assert!(page.resources().unwrap().fonts["F1"].name.is_some());
+            assert_eq!(crate::extract::extract_text(&ops, page.resources().unwrap(), &resolver).unwrap(), text);
+        }
+    }
+}
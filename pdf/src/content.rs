@@ -5,10 +5,11 @@ use itertools::Itertools;
 use istring::SmallString;
 use datasize::DataSize;
 use std::sync::Arc;
+use std::ops::Range;
 
 use crate::error::*;
 use crate::object::*;
-use crate::parser::{Lexer, parse_with_lexer, ParseFlags};
+use crate::parser::{Lexer, parse_with_lexer, ParseFlags, is_whitespace};
 use crate::primitive::*;
 use crate::enc::StreamFilter;
 use crate as pdf;
@@ -22,11 +23,102 @@ pub struct Content {
 
 impl Content {
     pub fn operations(&self, resolve: &impl Resolve) -> Result<Vec<Op>> {
+        parse_ops(&self.data(resolve)?, resolve)
+    }
+
+    /// The decoded data of all parts, separated by a newline.
+    pub fn data(&self, resolve: &impl Resolve) -> Result<Vec<u8>> {
         let mut data = vec![];
-        for part in self.parts.iter() {
+        for (i, part) in self.parts.iter().enumerate() {
+            if i > 0 {
+                data.push(b'\n');
+            }
             data.extend_from_slice(&t!(part.data(resolve)));
         }
-        parse_ops(&data, resolve)
+        Ok(data)
+    }
+}
+
+/// All operators of Table A.1.
+pub const OPERATORS: &[&str] = &[
+    "b", "B", "b*", "B*", "BDC", "BI", "BMC", "BT", "BX", "c", "cm", "CS", "cs", "d", "d0", "d1",
+    "Do", "DP", "EI", "EMC", "ET", "EX", "f", "F", "f*", "G", "g", "gs", "h", "i", "ID", "j", "J",
+    "K", "k", "l", "m", "M", "MP", "n", "q", "Q", "re", "RG", "rg", "ri", "s", "S", "SC", "SCN",
+    "sc", "scn", "sh", "T*", "Tc", "Td", "TD", "Tf", "Tj", "TJ", "TL", "Tm", "Tr", "Ts", "Tw", "Tz",
+    "v", "w", "W", "W*", "y", "'", "\"",
+];
+
+/// Splits a content stream into operators and their operands, without interpreting them.
+///
+/// For an inline image, the operator is `BI` and the operands are the image dictionary,
+/// with the abbreviated keys expanded, followed by the image data as a string.
+///
+/// Unknown operators are skipped inside `BX`/`EX`, and are an error otherwise, unless
+/// `allow_invalid_ops` is set.
+pub struct ContentParser<'a, R> {
+    lexer: Lexer<'a>,
+    resolve: &'a R,
+    compatibility_section: bool,
+    done: bool,
+}
+impl<'a, R: Resolve> ContentParser<'a, R> {
+    pub fn new(data: &'a [u8], resolve: &'a R) -> Self {
+        ContentParser {
+            lexer: Lexer::new(data),
+            resolve,
+            compatibility_section: false,
+            done: false,
+        }
+    }
+    fn next_op(&mut self) -> Result<Option<(Vec<Primitive>, SmallString)>> {
+        let mut operands = vec![];
+        loop {
+            let backup_pos = self.lexer.get_pos();
+            match parse_with_lexer(&mut self.lexer, self.resolve, ParseFlags::ANY) {
+                Ok(obj) => operands.push(obj),
+                Err(e) if e.is_eof() => return Ok(None),
+                Err(_) => {
+                    // not an operand, so it has to be an operator
+                    self.lexer.set_pos(backup_pos);
+                    let lexeme = t!(self.lexer.next());
+                    let op = t!(lexeme.as_str(), lexeme);
+                    match op {
+                        "BX" => self.compatibility_section = true,
+                        "EX" => self.compatibility_section = false,
+                        "BI" => {
+                            let (dict, range) = t!(inline_image_parts(&mut self.lexer));
+                            let data = self.lexer.new_substr(range).to_vec();
+                            let operands = vec![dict.into(), PdfString::new(data.into()).into()];
+                            return Ok(Some((operands, op.into())));
+                        }
+                        _ => {}
+                    }
+                    if OPERATORS.contains(&op) {
+                        return Ok(Some((operands, op.into())));
+                    }
+                    if !self.compatibility_section {
+                        if !self.resolve.options().allow_invalid_ops {
+                            bail!("unknown operator {:?}", op);
+                        }
+                        warn!("skipping unknown operator {:?}", op);
+                    }
+                    operands.clear();
+                }
+            }
+        }
+    }
+}
+impl<'a, R: Resolve> Iterator for ContentParser<'a, R> {
+    type Item = Result<(Vec<Primitive>, SmallString)>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let item = self.next_op().transpose();
+        if !matches!(item, Some(Ok(_))) {
+            self.done = true;
+        }
+        item
     }
 }
 
@@ -125,7 +217,8 @@ fn expand_abbr(p: Primitive, alt: &[(&str, &str)]) -> Primitive {
     }
 }
 
-fn inline_image(lexer: &mut Lexer, resolve: &impl Resolve) -> Result<Arc<ImageXObject>> {
+/// Reads the dictionary and finds the data of an inline image. `lexer` has to be after `BI`.
+fn inline_image_parts(lexer: &mut Lexer) -> Result<(Dictionary, Range<usize>)> {
     let mut dict = Dictionary::new();
     loop {
         let backup_pos = lexer.get_pos();
@@ -154,13 +247,87 @@ fn inline_image(lexer: &mut Lexer, resolve: &impl Resolve) -> Result<Arc<ImageXO
         dict.insert(key, val);
     }
     lexer.next_expect("ID")?;
+    // `ID` is followed by a single whitespace
     let data_start = lexer.get_pos() + 1;
+    let data = lexer.get_remaining_slice().get(1 ..).unwrap_or_default();
+    let (len, end) = match inline_image_len(&dict, data) {
+        Some(r) => r,
+        None => bail!("inline image exceeds expected data range"),
+    };
+    lexer.set_pos(data_start + end);
+    Ok((dict, data_start .. data_start + len))
+}
+
+/// Returns the length of the data of an inline image, and the position after `EI`.
+///
+/// For unfiltered images, the length is known from `/Width`, `/Height`, `/BitsPerComponent`
+/// and the color space. Otherwise `EI` can also appear inside the data, so it has to be
+/// surrounded by whitespace and followed by something that looks like content. If no `EI`
+/// is followed by content, like when the next operand is a binary string, the first one
+/// surrounded by whitespace is taken.
+fn inline_image_len(dict: &Dictionary, data: &[u8]) -> Option<(usize, usize)> {
+    // whitespace, `EI`, then whitespace or the end of the data
+    let ei_at = |pos: usize| -> Option<usize> {
+        let ws = data[pos ..].iter().take_while(|&&b| is_whitespace(b)).count();
+        let ei = pos + ws;
+        match data.get(ei .. ei + 2) {
+            Some(b"EI") if data.get(ei + 2).is_none_or(|&b| is_whitespace(b)) => Some(ei + 2),
+            _ => None
+        }
+    };
 
-    // find the end before try parsing.
-    if lexer.seek_substr("\nEI").is_none() {
-        bail!("inline image exceeds expected data range");
-    }    
-    let data_end = lexer.get_pos() - 3;
+    if let Some(len) = unfiltered_image_len(dict) {
+        if len <= data.len() {
+            if let Some(end) = ei_at(len) {
+                return Some((len, end));
+            }
+        }
+    }
+
+    let looks_like_content = |rest: &[u8]| rest.iter().take(32).all(|&b| is_whitespace(b) || (0x20 .. 0x7f).contains(&b));
+    let mut first = None;
+    for pos in 0 .. data.len().saturating_sub(1) {
+        if &data[pos .. pos + 2] != b"EI" || (pos > 0 && !is_whitespace(data[pos - 1])) {
+            continue;
+        }
+        let end = match ei_at(pos) {
+            Some(end) => end,
+            None => continue,
+        };
+        // one end of line before `EI` is not part of the data
+        let len = if data[.. pos].ends_with(b"\r\n") { pos - 2 } else { pos.saturating_sub(1) };
+        if looks_like_content(&data[end ..]) {
+            return Some((len, end));
+        }
+        first.get_or_insert((len, end));
+    }
+    first
+}
+
+fn unfiltered_image_len(dict: &Dictionary) -> Option<usize> {
+    if dict.get("Filter").is_some() {
+        return None;
+    }
+    let int = |key| dict.get(key)?.as_u32().ok().map(|n| n as usize);
+    let (width, height) = (int("Width")?, int("Height")?);
+    let image_mask = matches!(dict.get("ImageMask"), Some(Primitive::Boolean(true)));
+    let (bpc, components) = if image_mask {
+        (1, 1)
+    } else {
+        let components = match dict.get("ColorSpace")?.as_name().ok()? {
+            "G" | "DeviceGray" | "I" | "Indexed" => 1,
+            "RGB" | "DeviceRGB" => 3,
+            "CMYK" | "DeviceCMYK" => 4,
+            _ => return None
+        };
+        (int("BitsPerComponent")?, components)
+    };
+    let row = width.checked_mul(bpc)?.checked_mul(components)?.div_ceil(8);
+    height.checked_mul(row)
+}
+
+fn inline_image(lexer: &mut Lexer, resolve: &impl Resolve) -> Result<Arc<ImageXObject>> {
+    let (mut dict, range) = inline_image_parts(lexer)?;
 
     // ugh
     let bits_per_component = dict.get("BitsPerComponent").map(|p| p.as_integer()).transpose()?;
@@ -216,7 +383,7 @@ fn inline_image(lexer: &mut Lexer, resolve: &impl Resolve) -> Result<Arc<ImageXO
         other: dict,
     };
 
-    let data = lexer.new_substr(data_start .. data_end).to_vec();
+    let data = lexer.new_substr(range).to_vec();
 
     Ok(Arc::new(ImageXObject { inner: Stream::from_compressed(image_dict, data, filters) }))
 }
@@ -427,7 +594,7 @@ impl OpBuilder {
                 for spacing_or_text in array(&mut args)?.into_iter() {
                     let spacing_or_text = match spacing_or_text {
                         Primitive::Integer(i) => TextDrawAdjusted::Spacing(i as f32),
-                        Primitive::Number(f) => TextDrawAdjusted::Spacing(f),
+                        Primitive::Number(f) => TextDrawAdjusted::Spacing(f as f32),
                         Primitive::String(text) => TextDrawAdjusted::Text(text),
                         p => bail!("invalid primitive in TJ operator: {:?}", p)
                     };
@@ -902,6 +1069,28 @@ impl Default for Matrix {
         }
     }
 }
+impl Matrix {
+    pub fn identity() -> Matrix {
+        Matrix::default()
+    }
+    /// Concatenate two matrices, following the PDF convention of row vectors:
+    /// the result applies `self` first, then `other`.
+    /// `cm` with matrix `m` thus turns the CTM into `m.multiply(&ctm)`.
+    pub fn multiply(&self, other: &Matrix) -> Matrix {
+        Matrix {
+            a: self.a * other.a + self.b * other.c,
+            b: self.a * other.b + self.b * other.d,
+            c: self.c * other.a + self.d * other.c,
+            d: self.c * other.b + self.d * other.d,
+            e: self.e * other.a + self.f * other.c + other.e,
+            f: self.e * other.b + self.f * other.d + other.f,
+        }
+    }
+    /// Transform the point `(x, y)`.
+    pub fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (self.a * x + self.c * y + self.e, self.b * x + self.d * y + self.f)
+    }
+}
 impl Object for Matrix {
     fn from_primitive(p: Primitive, _resolve: &impl Resolve) -> Result<Self> {
         matrix(&mut p.into_array()?.into_iter())
@@ -1151,4 +1340,76 @@ EI
         let mut lexer = Lexer::new(data);
         assert!(inline_image(&mut lexer, &NoResolve).is_ok()); 
     }
+
+    #[test]
+    fn inline_image_with_ei_in_data() {
+        // unfiltered, so the length is known
+        let data = b"BI /W 6 /H 1 /BPC 8 /CS /G ID \x01 EI Q\nEI Q";
+        let ops: Vec<_> = ContentParser::new(data, &NoResolve).collect::<Result<_>>().unwrap();
+        assert_eq!(ops[0].0[1].as_string().unwrap().as_bytes(), b"\x01 EI Q");
+        assert_eq!(ops[1].1, "Q");
+
+        // filtered, the `EI` in the data is followed by binary data
+        let data = b"BI /W 4 /H 1 /BPC 8 /CS /G /F /AHx ID \x01 EI \xff\xfe\nEI\nQ";
+        let ops: Vec<_> = ContentParser::new(data, &NoResolve).collect::<Result<_>>().unwrap();
+        assert_eq!(ops[0].0[1].as_string().unwrap().as_bytes(), b"\x01 EI \xff\xfe");
+        assert_eq!(ops[1].1, "Q");
+
+        // filtered, and followed by a binary string
+        let data = b"BI /W 4 /H 1 /BPC 8 /CS /G /F /AHx ID 01ff\nEI <ff> Tj (\x80\x81\x82) Tj";
+        let ops: Vec<_> = ContentParser::new(data, &NoResolve).collect::<Result<_>>().unwrap();
+        assert_eq!(ops[0].0[1].as_string().unwrap().as_bytes(), b"01ff");
+        assert_eq!(ops[2].0[0].as_string().unwrap().as_bytes(), b"\x80\x81\x82");
+
+        let mut lexer = Lexer::new(&b"/W 1 /H 1 /BPC 8 /CS /G ID \x00"[..]);
+        assert!(inline_image_parts(&mut lexer).is_err());
+
+        // the size overflows
+        let mut dict = Dictionary::new();
+        dict.insert("Width", Primitive::Integer(u32::MAX as i64));
+        dict.insert("Height", Primitive::Integer(u32::MAX as i64));
+        dict.insert("BitsPerComponent", Primitive::Integer(u32::MAX as i64));
+        dict.insert("ColorSpace", Primitive::name("CMYK"));
+        assert_eq!(unfiltered_image_len(&dict), None);
+    }
+
+    #[test]
+    fn content_parser() {
+        let data = b"q 1 0 0 1 72 700 cm\nBT /F1 12 Tf 0 0 Td (Hello) Tj [(W) 120 (orld)] TJ ET\n\
+            BX foo EX BI /W 2 /H 1 /BPC 8 /CS /G ID \x00\xff\nEI Q";
+        let ops: Vec<_> = ContentParser::new(data, &NoResolve).collect::<Result<_>>().unwrap();
+        let names: Vec<&str> = ops.iter().map(|(_, op)| op.as_str()).collect();
+        assert_eq!(names, ["q", "cm", "BT", "Tf", "Td", "Tj", "TJ", "ET", "BX", "EX", "BI", "Q"]);
+
+        assert_eq!(ops[1].0, [1, 0, 0, 1, 72, 700].iter().map(|&n| Primitive::Integer(n)).collect::<Vec<_>>());
+        assert_eq!(ops[3].0, [Primitive::name("F1"), Primitive::Integer(12)]);
+        assert_eq!(ops[5].0, [Primitive::String(PdfString::new(b"Hello"[..].into()))]);
+        assert_eq!(ops[6].0[0].as_array().unwrap().len(), 3);
+
+        let image = &ops[10].0;
+        assert_eq!(image[0].clone().into_dictionary().unwrap()["Width"], Primitive::Integer(2));
+        assert_eq!(image[1].as_string().unwrap().as_bytes(), b"\x00\xff");
+
+        // unknown operators are dropped with their operands
+        let ops: Vec<_> = ContentParser::new(b"1 2 foo 3 w", &NoResolve).collect::<Result<_>>().unwrap();
+        assert_eq!(ops, [(vec![Primitive::Integer(3)], "w".into())]);
+    }
+
+    #[test]
+    fn matrix() {
+        let m = Matrix { a: 2.0, b: 0.5, c: -1.0, d: 3.0, e: 10.0, f: 20.0 };
+        assert_eq!(m.multiply(&Matrix::identity()), m);
+        assert_eq!(Matrix::identity().multiply(&m), m);
+
+        let translate = Matrix { e: 5.0, f: 7.0, ..Matrix::identity() };
+        let scale = Matrix { a: 2.0, d: 3.0, ..Matrix::identity() };
+        // translate first, then scale
+        let m = translate.multiply(&scale);
+        assert_eq!(m.apply((1.0, 1.0)), (12.0, 24.0));
+        assert_eq!(m.apply((1.0, 1.0)), scale.apply(translate.apply((1.0, 1.0))));
+        assert_eq!(scale.multiply(&translate).apply((1.0, 1.0)), (7.0, 10.0));
+
+        let p = m.to_primitive(&mut NoUpdate).unwrap();
+        assert_eq!(Matrix::from_primitive(p, &NoResolve).unwrap(), m);
+    }
 }
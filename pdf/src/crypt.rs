@@ -15,6 +15,8 @@ use crate::error::{PdfError, Result};
 type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
 type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
 type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+#[cfg(test)]
+type Aes256CbcEnc = cbc::Encryptor<aes::Aes256>;
 
 const PADDING: [u8; 32] = [
     0x28, 0xBF, 0x4E, 0x5E, 0x4E, 0x75, 0x8A, 0x41,
@@ -61,40 +63,63 @@ impl Rc4 {
 }
 
 /// 7.6.1 Table 20 + 7.6.3.2 Table 21
+///
+/// The `/Encrypt` dictionary of the trailer. Its strings are not encrypted themselves.
 #[derive(Object, Debug, Clone, DataSize)]
 pub struct CryptDict {
+    /// The security handler, `Standard` for password based encryption.
+    #[pdf(key="Filter", default="Name::from(\"Standard\")")]
+    pub filter: Name,
+
+    /// Owner password hash
     #[pdf(key="O")]
-    o: PdfString,
+    pub o: PdfString,
 
+    /// User password hash
     #[pdf(key="U")]
-    u: PdfString,
+    pub u: PdfString,
 
+    /// Revision of the standard security handler
     #[pdf(key="R")]
-    r: u32,
+    pub r: u32,
 
+    /// Permission flags
     #[pdf(key="P")]
-    p: i32,
+    pub p: i32,
 
+    /// Algorithm version
     #[pdf(key="V")]
-    v: i32,
+    pub v: i32,
 
+    /// Key length in bits
     #[pdf(key="Length", default="40")]
-    bits: u32,
+    pub bits: u32,
 
     #[pdf(key="CF")]
-    crypt_filters: HashMap<Name, CryptFilter>,
+    pub crypt_filters: HashMap<Name, CryptFilter>,
 
+    /// Crypt filter for streams. `Identity`, the default, means no encryption.
     #[pdf(key="StmF")]
-    default_crypt_filter: Option<Name>,
+    pub default_crypt_filter: Option<Name>,
+
+    /// Crypt filter for strings. `Identity`, the default, means no encryption.
+    #[pdf(key="StrF")]
+    pub string_crypt_filter: Option<Name>,
 
     #[pdf(key="EncryptMetadata", default="true")]
-    encrypt_metadata: bool,
+    pub encrypt_metadata: bool,
 
+    /// Encrypted owner key, for V5
     #[pdf(key = "OE")]
-    oe: Option<PdfString>,
+    pub oe: Option<PdfString>,
 
+    /// Encrypted user key, for V5
     #[pdf(key = "UE")]
-    ue: Option<PdfString>,
+    pub ue: Option<PdfString>,
+
+    /// Encrypted permissions, for V5
+    #[pdf(key = "Perms")]
+    pub perms: Option<PdfString>,
 
     #[pdf(other)]
     _other: Dictionary
@@ -134,6 +159,7 @@ pub struct Decoder {
     key_size: usize,
     key: Vec<u8>, // maximum length
     method: CryptMethod,
+    string_method: CryptMethod,
     /// A reference to the /Encrypt dictionary, if it is in an indirect
     /// object. The strings in this dictionary are not encrypted, so
     /// decryption must be skipped when accessing them.
@@ -161,12 +187,19 @@ impl Decoder {
             key_size,
             key,
             method,
+            string_method: method,
             encrypt_indirect_object: None,
             metadata_indirect_object: None,
             encrypt_metadata,
         }
     }
 
+    /// Use `method` for strings instead of the stream method.
+    pub fn with_string_method(mut self, method: CryptMethod) -> Decoder {
+        self.string_method = method;
+        self
+    }
+
     pub fn from_password(dict: &CryptDict, id: &[u8], pass: &[u8]) -> Result<Decoder> {
         fn compute_u_rev_2(key: &[u8]) -> Vec<u8> {
             // algorithm 4
@@ -295,27 +328,35 @@ impl Decoder {
             Ok(digest.to_vec())
         }
 
-        let (key_bits, method) = match dict.v {
-            1 => (40, CryptMethod::V2),
-            2 => (dict.bits, CryptMethod::V2),
-            4 ..= 6 => {
-                let default = dict
-                    .crypt_filters
-                    .get(try_opt!(dict.default_crypt_filter.as_ref()).as_str())
-                    .ok_or_else(|| other!("missing crypt filter entry {:?}", dict.default_crypt_filter.as_ref()))?;
-
-                match default.method {
-                    CryptMethod::V2 | CryptMethod::AESV2 => (
-                        default.length.map(|n| 8 * n).unwrap_or(dict.bits),
-                        default.method,
-                    ),
-                    CryptMethod::AESV3 if dict.v == 5 => (
-                        default.length.map(|n| 8 * n).unwrap_or(dict.bits),
-                        default.method,
-                    ),
-                    m => err!(other!("unimplemented crypt method {:?}", m)),
+        // 7.6.6: `Identity` is a predefined filter that leaves the data as it is
+        fn crypt_filter(dict: &CryptDict, name: Option<&Name>) -> Result<(CryptMethod, Option<u32>)> {
+            match name.map(|n| n.as_str()) {
+                None | Some("Identity") => Ok((CryptMethod::None, None)),
+                Some(name) => {
+                    let filter = dict
+                        .crypt_filters
+                        .get(name)
+                        .ok_or_else(|| other!("missing crypt filter entry {:?}", name))?;
+                    match filter.method {
+                        CryptMethod::AESV3 if dict.v != 5 => err!(other!("unimplemented crypt method {:?}", filter.method)),
+                        m => Ok((m, filter.length)),
+                    }
                 }
             }
+        }
+
+        let (key_bits, method, string_method) = match dict.v {
+            1 => (40, CryptMethod::V2, CryptMethod::V2),
+            2 => (dict.bits, CryptMethod::V2, CryptMethod::V2),
+            4 ..= 6 => {
+                let (method, length) = crypt_filter(dict, dict.default_crypt_filter.as_ref())?;
+                let (string_method, string_length) = crypt_filter(dict, dict.string_crypt_filter.as_ref())?;
+                (
+                    length.or(string_length).map(|n| 8 * n).unwrap_or(dict.bits),
+                    method,
+                    string_method,
+                )
+            }
             v => err!(other!("unsupported V value {}", v)),
         };
         let level = dict.r;
@@ -327,7 +368,8 @@ impl Decoder {
             let key = key_derivation_user_password_rc4(level, key_size, dict, id, pass);
 
             if check_password_rc4(level, dict.u.as_bytes(), id, &key[..std::cmp::min(key_size, 16)]) {
-                let decoder = Decoder::new(key, key_size, method, dict.encrypt_metadata);
+                let decoder = Decoder::new(key, key_size, method, dict.encrypt_metadata)
+                    .with_string_method(string_method);
                 Ok(decoder)
             } else {
                 let password_wrap_key = key_derivation_owner_password_rc4(level, key_size, pass)?;
@@ -351,7 +393,8 @@ impl Decoder {
                 );
 
                 if check_password_rc4(level, dict.u.as_bytes(), id, &key[..key_size]) {
-                    let decoder = Decoder::new(key, key_size, method, dict.encrypt_metadata);
+                    let decoder = Decoder::new(key, key_size, method, dict.encrypt_metadata)
+                    .with_string_method(string_method);
                     Ok(decoder)
                 } else {
                     Err(PdfError::InvalidPassword)
@@ -461,7 +504,8 @@ impl Decoder {
                 .decrypt_padded_mut::<NoPadding>(&mut wrapped_key)
                 .map_err(|_| PdfError::InvalidPassword));
 
-            let decoder = Decoder::new(key_slice.into(),  32, method, dict.encrypt_metadata);
+            let decoder = Decoder::new(key_slice.into(),  32, method, dict.encrypt_metadata)
+                    .with_string_method(string_method);
             Ok(decoder)
         } else {
             err!(format!("unsupported V value {}", level).into())
@@ -532,7 +576,17 @@ impl Decoder {
         hash
     }
 
+    /// Decrypts the data of the stream `id`.
     pub fn decrypt<'buf>(&self, id: PlainRef, data: &'buf mut [u8]) -> Result<&'buf [u8]> {
+        self.decrypt_with(self.method, id, data)
+    }
+
+    /// Decrypts a string in the object `id`.
+    pub fn decrypt_string<'buf>(&self, id: PlainRef, data: &'buf mut [u8]) -> Result<&'buf [u8]> {
+        self.decrypt_with(self.string_method, id, data)
+    }
+
+    fn decrypt_with<'buf>(&self, method: CryptMethod, id: PlainRef, data: &'buf mut [u8]) -> Result<&'buf [u8]> {
         if self.encrypt_indirect_object == Some(id) {
             // Strings inside the /Encrypt dictionary are not encrypted
             return Ok(data);
@@ -551,8 +605,8 @@ impl Decoder {
         // Algorithm 1
         // a) we have those already
 
-        match self.method {
-            CryptMethod::None => unreachable!(),
+        match method {
+            CryptMethod::None => Ok(data),
             CryptMethod::V2 => {
                 // b)
                 let mut key = [0; 16 + 5];
@@ -598,7 +652,7 @@ impl Decoder {
                 }
                 let (iv, ciphertext) = data.split_at_mut(16);
                 let cipher =
-                    t!(Aes256CbcDec::new_from_slices(self.key(), iv).map_err(|_| PdfError::DecryptionFailure));
+                    t!(Aes256CbcDec::new_from_slices(&self.key[..self.key_size], iv).map_err(|_| PdfError::DecryptionFailure));
                 Ok(t!(cipher
                     .decrypt_padded_mut::<Pkcs7>(ciphertext)
                     .map_err(|_| PdfError::DecryptionFailure)))
@@ -611,12 +665,173 @@ impl fmt::Debug for Decoder {
         f.debug_struct("Decoder")
             .field("key", &self.key())
             .field("method", &self.method)
+            .field("string_method", &self.string_method)
             .finish()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::object::{Object, NoResolve};
+    use crate::parser::{parse, ParseFlags};
+    use crate::primitive::Primitive;
+
+    #[test]
+    fn crypt_dict() {
+        let data = b"<< /Filter /Standard /V 5 /R 6 /Length 256 /P -1028
+            /O <0102> /U (user) /OE <03> /UE <04> /Perms <05>
+            /CF << /StdCF << /CFM /AESV3 /Length 32 >> >> /StmF /StdCF /StrF /StdCF >>";
+        let dict = CryptDict::from_primitive(parse(data, &NoResolve, ParseFlags::ANY).unwrap(), &NoResolve).unwrap();
+        assert_eq!(dict.filter.as_str(), "Standard");
+        assert_eq!((dict.v, dict.r, dict.p, dict.bits), (5, 6, -1028, 256));
+        assert_eq!(dict.o.as_bytes(), [1, 2]);
+        assert_eq!(dict.u.as_bytes(), b"user");
+        assert_eq!(dict.oe.unwrap().as_bytes(), [3]);
+        assert_eq!(dict.ue.unwrap().as_bytes(), [4]);
+        assert_eq!(dict.perms.unwrap().as_bytes(), [5]);
+        assert!(dict.encrypt_metadata);
+        assert!(matches!(dict.crypt_filters["StdCF"].method, CryptMethod::AESV3));
+    }
+
+    #[test]
+    fn rc4_empty_password() {
+        use crate::build::CatalogBuilder;
+        use crate::file::FileOptions;
+        use crate::object::{Resolve, Stream, ObjectWrite};
+        use crate::primitive::Primitive;
+        use crate::writer::PdfWriter;
+
+        let id = b"0123456789abcdef";
+        let o = [0x11u8; 32];
+        let plaintext = b"BT /F1 12 Tf (Hello) Tj ET";
+        for &(v, revision, bits) in &[(1, 2, 40), (2, 3, 128)] {
+            let key_size = bits / 8;
+            // Algorithm 2 with the empty password
+            let mut hash = md5::Context::new();
+            hash.consume(PADDING);
+            hash.consume(o);
+            hash.consume((-4i32).to_le_bytes());
+            hash.consume(id);
+            let mut key = *hash.compute();
+            if revision >= 3 {
+                for _ in 0..50 {
+                    key = *md5::compute(&key[..key_size]);
+                }
+            }
+            let key = &key[..key_size];
+            // Algorithm 4 and 5
+            let mut u;
+            if revision == 2 {
+                u = PADDING.to_vec();
+                Rc4::encrypt(key, &mut u);
+            } else {
+                let mut hash = md5::Context::new();
+                hash.consume(PADDING);
+                hash.consume(id);
+                u = hash.compute().to_vec();
+                for i in 0..20u8 {
+                    let round_key: Vec<u8> = key.iter().map(|b| b ^ i).collect();
+                    Rc4::encrypt(&round_key, &mut u);
+                }
+                u.resize(32, 0);
+            }
+
+            let mut writer = PdfWriter::new(Vec::new()).unwrap();
+            let catalog = CatalogBuilder::from_pages(vec![]).build(&mut writer).unwrap();
+            let root = writer.write_object(&catalog).unwrap();
+
+            let encoder = Decoder::new(key.to_vec(), key_size, CryptMethod::V2, true);
+            let stream_ref = writer.reserve();
+            let mut data = plaintext.to_vec();
+            encoder.decrypt(stream_ref, &mut data).unwrap();
+            assert_ne!(&data[..], plaintext);
+            let stream = Stream::<()>::new((), data).to_primitive(&mut writer).unwrap();
+            writer.fill(stream_ref, &stream).unwrap();
+
+            let string_ref = writer.reserve();
+            let mut data = b"secret".to_vec();
+            encoder.decrypt(string_ref, &mut data).unwrap();
+            writer.fill(string_ref, &Primitive::String(PdfString::new(data.into()))).unwrap();
+
+            let mut encrypt = Dictionary::new();
+            encrypt.insert("Filter", Primitive::name("Standard"));
+            encrypt.insert("V", v);
+            encrypt.insert("R", revision);
+            encrypt.insert("Length", bits as i32);
+            encrypt.insert("P", -4);
+            encrypt.insert("O", Primitive::String(PdfString::new(o[..].into())));
+            encrypt.insert("U", Primitive::String(PdfString::new(u.into())));
+            let encrypt_ref = writer.write_object(&Primitive::Dictionary(encrypt)).unwrap();
+
+            let mut trailer = Dictionary::new();
+            trailer.insert("Root", Primitive::Reference(root.get_inner()));
+            trailer.insert("Encrypt", Primitive::Reference(encrypt_ref.get_inner()));
+            let id = Primitive::String(PdfString::new(id[..].into()));
+            trailer.insert("ID", Primitive::Array(vec![id.clone(), id]));
+            let data = writer.finish_with_trailer(trailer).unwrap();
+
+            let file = FileOptions::uncached().load(data).unwrap();
+            let resolver = file.resolver();
+            let stream = Stream::<()>::from_primitive(resolver.resolve(stream_ref).unwrap(), &resolver).unwrap();
+            assert_eq!(&*stream.data(&resolver).unwrap(), plaintext);
+            assert_eq!(resolver.resolve(string_ref).unwrap().as_string().unwrap().as_bytes(), b"secret");
+        }
+    }
+
+    #[test]
+    fn aes256_empty_password() {
+        let file_key = [0x42u8; 32];
+        let (validation_salt, key_salt) = ([1u8; 8], [2u8; 8]);
+        let zero_iv = [0u8; 16];
+
+        // Algorithm 8 with the empty password
+        let mut u = Decoder::revision_6_kdf(b"", &validation_salt, b"").to_vec();
+        u.extend_from_slice(&validation_salt);
+        u.extend_from_slice(&key_salt);
+        let intermediate_key = Decoder::revision_6_kdf(b"", &key_salt, b"");
+        let mut ue = file_key;
+        Aes256CbcEnc::new(&intermediate_key.into(), &zero_iv.into())
+            .encrypt_padded_mut::<NoPadding>(&mut ue, 32)
+            .unwrap();
+
+        let string = |data: &[u8]| Primitive::String(PdfString::new(data.into()));
+        let mut filter = Dictionary::new();
+        filter.insert("CFM", Primitive::name("AESV3"));
+        filter.insert("Length", 32);
+        let mut filters = Dictionary::new();
+        filters.insert("StdCF", filter);
+        let mut encrypt = Dictionary::new();
+        encrypt.insert("Filter", Primitive::name("Standard"));
+        encrypt.insert("V", 5);
+        encrypt.insert("R", 6);
+        encrypt.insert("P", -4);
+        encrypt.insert("O", string(&[0; 48]));
+        encrypt.insert("OE", string(&[0; 32]));
+        encrypt.insert("U", string(&u));
+        encrypt.insert("UE", string(&ue));
+        encrypt.insert("CF", filters);
+        encrypt.insert("StmF", Primitive::name("StdCF"));
+        encrypt.insert("StrF", Primitive::name("Identity"));
+        let dict = CryptDict::from_primitive(encrypt.into(), &NoResolve).unwrap();
+        let decoder = Decoder::from_password(&dict, b"", b"").unwrap();
+        assert_eq!(decoder.key, file_key);
+
+        let plaintext = b"0 0 m 100 100 l S";
+        let iv = [7u8; 16];
+        let mut buf = [0u8; 32];
+        let len = Aes256CbcEnc::new(&file_key.into(), &iv.into())
+            .encrypt_padded_b2b_mut::<Pkcs7>(plaintext, &mut buf)
+            .unwrap()
+            .len();
+        let mut data = [&iv[..], &buf[..len]].concat();
+
+        let id = PlainRef { id: 4, gen: 0 };
+        assert_eq!(decoder.decrypt(id, &mut data).unwrap(), plaintext);
+        let mut text = b"not encrypted".to_vec();
+        assert_eq!(decoder.decrypt_string(id, &mut text).unwrap(), b"not encrypted");
+    }
+
     #[test]
     fn unencrypted_strings() {
         let data_prefix = b"%PDF-1.5\n\
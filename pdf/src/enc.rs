@@ -1,14 +1,12 @@
 #![allow(clippy::many_single_char_names)]
 #![allow(dead_code)]  // TODO
 
-use itertools::Itertools;
-
 use crate as pdf;
 use crate::error::*;
 use crate::object::{Object, Resolve, Stream};
 use crate::primitive::{Primitive, Dictionary};
 use std::convert::TryInto;
-use std::io::{Read, Write};
+use std::io::Read;
 use once_cell::sync::OnceCell;
 use datasize::DataSize;
 
@@ -119,8 +117,8 @@ impl StreamFilter {
 pub fn decode_nibble(c: u8) -> Option<u8> {
     match c {
         n @ b'0' ..= b'9' => Some(n - b'0'),
-        a @ b'a' ..= b'h' => Some(a - b'a' + 0xa),
-        a @ b'A' ..= b'H' => Some(a - b'A' + 0xA),
+        a @ b'a' ..= b'f' => Some(a - b'a' + 0xa),
+        a @ b'A' ..= b'F' => Some(a - b'A' + 0xA),
         _ => None
     }
 }
@@ -135,18 +133,32 @@ fn encode_nibble(c: u8) -> u8 {
 }
 
 
+/// Decode `ASCIIHexDecode` data. Whitespace is skipped, `>` ends the data and
+/// a final odd nibble is padded with a zero.
 pub fn decode_hex(data: &[u8]) -> Result<Vec<u8>> {
     let mut out = Vec::with_capacity(data.len() / 2);
-    let pairs = data.iter().cloned()
-        .take_while(|&b| b != b'>')
-        .filter(|&b| !matches!(b, 0 | 9 | 10 | 12 | 13 | 32))
-        .tuples();
-    for (i, (high, low)) in pairs.enumerate() {
-        if let (Some(low), Some(high)) = (decode_nibble(low), decode_nibble(high)) {
-            out.push(high << 4 | low);
-        } else {
-            return Err(PdfError::HexDecode {pos: i * 2, bytes: [high, low]})
+    let mut high = None;
+    for (pos, &b) in data.iter().enumerate() {
+        if matches!(b, 0 | 9 | 10 | 12 | 13 | 32) {
+            continue;
+        }
+        if b == b'>' {
+            break;
         }
+        let nibble = match decode_nibble(b) {
+            Some(n) => n,
+            None => return Err(PdfError::HexDecode {pos, bytes: [high.map_or(b, |(_, c)| c), b]})
+        };
+        high = match high {
+            None => Some((nibble, b)),
+            Some((h, _)) => {
+                out.push(h << 4 | nibble);
+                None
+            }
+        };
+    }
+    if let Some((h, _)) = high {
+        out.push(h << 4);
     }
     Ok(out)
 }
@@ -174,11 +186,19 @@ fn word_85([a, b, c, d, e]: [u8; 5]) -> Option<[u8; 4]> {
     Some(q.to_be_bytes())
 }
 
+/// Decode `ASCII85Decode` data. The `<~` prefix some writers add is optional, the `~>` end marker is required.
 pub fn decode_85(data: &[u8]) -> Result<Vec<u8>> {
     let mut out = Vec::with_capacity((data.len() + 4) / 5 * 4);
     
     let mut stream = data.iter().cloned()
-        .filter(|&b| !matches!(b, b' ' | b'\n' | b'\r' | b'\t'));
+        .filter(|&b| !matches!(b, 0 | 9 | 10 | 12 | 13 | 32))
+        .peekable();
+    if stream.peek() == Some(&b'<') {
+        stream.next();
+        if stream.next() != Some(b'~') {
+            return Err(PdfError::Ascii85TailError);
+        }
+    }
 
     let mut symbols = stream.by_ref()
         .take_while(|&b| b != b'~');
@@ -272,13 +292,6 @@ fn inflate_bytes(data: &[u8]) -> Result<Vec<u8>> {
 }
 
 pub fn flate_decode(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
-
-    let predictor = params.predictor as usize;
-    let n_components = params.n_components as usize;
-    let columns = params.columns as usize;
-    let stride = columns * n_components;
-
-
     // First flate decode
     let decoded = {
         if let Ok(data) = inflate_bytes_zlib(data) {
@@ -290,53 +303,81 @@ pub fn flate_decode(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
             bail!("can't inflate");
         }
     };
-    // Then unfilter (PNG)
-    // For this, take the old out as input, and write output to out
-
-    if predictor > 10 {
-        let inp = decoded; // input buffer
-        let rows = inp.len() / (stride+1);
-        
-        // output buffer
-        let mut out = vec![0; rows * stride];
-    
-        // Apply inverse predictor
-        let null_vec = vec![0; stride];
-        
-        let mut in_off = 0; // offset into input buffer
-        
-        let mut out_off = 0; // offset into output buffer
-        let mut last_out_off = 0; // last offset to output buffer
+    predictor_decode(decoded, params)
+}
+
+/// Reverse the `/Predictor` of `FlateDecode` and `LZWDecode` data.
+fn predictor_decode(data: Vec<u8>, params: &LZWFlateParams) -> Result<Vec<u8>> {
+    let n_components = params.n_components.max(1) as usize;
+    let bpc = params.bits_per_component.max(1) as usize;
+    let columns = params.columns.max(1) as usize;
+    // bytes per row, and bytes per pixel (at least one)
+    let stride = (columns * n_components * bpc).div_ceil(8);
+    let bpp = (n_components * bpc).div_ceil(8);
+
+    match params.predictor {
+        // TIFF predictor 2
+        2 => {
+            let mut out = data;
+            match bpc {
+                8 => for row in out.chunks_exact_mut(stride) {
+                    for i in bpp .. stride {
+                        row[i] = row[i].wrapping_add(row[i - bpp]);
+                    }
+                }
+                16 => for row in out.chunks_exact_mut(stride) {
+                    for i in (bpp .. stride - 1).step_by(2) {
+                        let prev = u16::from_be_bytes([row[i - bpp], row[i - bpp + 1]]);
+                        let cur = u16::from_be_bytes([row[i], row[i + 1]]);
+                        row[i .. i + 2].copy_from_slice(&cur.wrapping_add(prev).to_be_bytes());
+                    }
+                }
+                _ => bail!("TIFF predictor with {} bits per component is not supported", bpc)
+            }
+            Ok(out)
+        }
+        // PNG predictors. The predictor value is only a hint, every row starts with its own predictor byte.
+        10 ..= 15 => {
+            let inp = data; // input buffer
+            let rows = inp.len() / (stride+1);
+            
+            // output buffer
+            let mut out = vec![0; rows * stride];
         
-        while in_off + stride < inp.len() {
-            let predictor = PredictorType::from_u8(inp[in_off])?;
-            in_off += 1; // +1 because the first byte on each row is predictor
+            // Apply inverse predictor
+            let null_vec = vec![0; stride];
             
-            let row_in = &inp[in_off .. in_off + stride];
-            let (prev_row, row_out) = if out_off == 0 {
-                (&null_vec[..], &mut out[out_off .. out_off+stride])
-            } else {
-                let (prev, curr) = out.split_at_mut(out_off);
-                (&prev[last_out_off ..], &mut curr[.. stride])
-            };
-            unfilter(predictor, n_components, prev_row, row_in, row_out);
+            let mut in_off = 0; // offset into input buffer
             
-            last_out_off = out_off;
+            let mut out_off = 0; // offset into output buffer
+            let mut last_out_off = 0; // last offset to output buffer
             
-            in_off += stride;
-            out_off += stride;
+            while in_off + stride < inp.len() {
+                let predictor = PredictorType::from_u8(inp[in_off])?;
+                in_off += 1; // +1 because the first byte on each row is predictor
+                
+                let row_in = &inp[in_off .. in_off + stride];
+                let (prev_row, row_out) = if out_off == 0 {
+                    (&null_vec[..], &mut out[out_off .. out_off+stride])
+                } else {
+                    let (prev, curr) = out.split_at_mut(out_off);
+                    (&prev[last_out_off ..], &mut curr[.. stride])
+                };
+                unfilter(predictor, bpp, prev_row, row_in, row_out);
+                
+                last_out_off = out_off;
+                
+                in_off += stride;
+                out_off += stride;
+            }
+            Ok(out)
         }
-        Ok(out)
-    } else {
-        Ok(decoded)
+        _ => Ok(data)
     }
 }
-fn flate_encode(data: &[u8]) -> Vec<u8> {
-    use libflate::deflate::Encoder;
-    let mut encoded = Vec::new();
-    let mut encoder = Encoder::new(&mut encoded);
-    encoder.write_all(data).unwrap();
-    encoded
+/// Compresses `data` for `/FlateDecode`, in the zlib format.
+pub fn flate_encode(data: &[u8]) -> Vec<u8> {
+    deflate::deflate_bytes_zlib(data)
 }
 
 pub fn dct_decode(data: &[u8], _params: &DCTDecodeParams) -> Result<Vec<u8>> {
@@ -350,24 +391,27 @@ pub fn lzw_decode(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
     use weezl::{BitOrder, decode::Decoder};
     let mut out = vec![];
 
+    // 8 bit literals, so codes start out 9 bits wide
     let mut decoder = if params.early_change != 0 {
-        Decoder::with_tiff_size_switch(BitOrder::Msb, 9)
+        Decoder::with_tiff_size_switch(BitOrder::Msb, 8)
     } else {
-        Decoder::new(BitOrder::Msb, 9)
+        Decoder::new(BitOrder::Msb, 8)
     };
 
     decoder
         .into_stream(&mut out)
         .decode_all(data).status?;
-    Ok(out)
+    predictor_decode(out, params)
 }
 fn lzw_encode(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
     use weezl::{BitOrder, encode::Encoder};
-    if params.early_change != 0 {
-        bail!("encoding early_change != 0 is not supported");
-    }
+    let mut encoder = if params.early_change != 0 {
+        Encoder::with_tiff_size_switch(BitOrder::Msb, 8)
+    } else {
+        Encoder::new(BitOrder::Msb, 8)
+    };
     let mut compressed = vec![];
-    Encoder::new(BitOrder::Msb, 9)
+    encoder
         .into_stream(&mut compressed)
         .encode_all(data).status?;
     Ok(compressed)
@@ -412,11 +456,12 @@ pub fn run_length_decode(data: &[u8]) -> Result<Vec<u8>> {
             let start = c + 1;
             let end = start + length as usize + 1;
             // copy _following_ length + 1 bytes literally
-            buf.extend_from_slice(&d[start..end]);
+            let run = d.get(start..end).ok_or(PdfError::EOF)?;
+            buf.extend_from_slice(run);
             c = end; // move cursor to next run
         } else if length >= 129 {
             let copy = 257 - length as usize; // copy 2 - 128 times
-            let b = d[c + 1]; // copied byte
+            let b = *d.get(c + 1).ok_or(PdfError::EOF)?; // copied byte
             buf.extend(std::iter::repeat(b).take(copy));
             c += 2; // move cursor to next run
         } else {
@@ -623,6 +668,7 @@ pub fn filter(method: PredictorType, bpp: usize, previous: &[u8], current: &mut
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::object::NoResolve;
 
     #[test]
     fn base_85() {
@@ -646,9 +692,106 @@ mod tests {
         */
     }
 
+    #[test]
+    fn flate() {
+        let zlib = [120, 156, 203, 72, 205, 201, 201, 87, 200, 64, 39, 1, 104, 3, 8, 177];
+        let raw = [203, 72, 205, 201, 201, 87, 200, 64, 39, 1];
+        let params = LZWFlateParams::default();
+        assert_eq!(flate_decode(&zlib, &params).unwrap(), b"hello hello hello hello");
+        assert_eq!(flate_decode(&raw, &params).unwrap(), b"hello hello hello hello");
+        assert!(flate_decode(b"not deflate", &params).is_err());
+
+        let stream = Stream::<()>::from_compressed((), &zlib[..], vec![StreamFilter::FlateDecode(params)]);
+        assert_eq!(&*stream.data(&NoResolve).unwrap(), b"hello hello hello hello");
+    }
+
+    #[test]
+    fn lzw() {
+        // example from the PDF reference, 7.4.4.2: codes 256 45 258 258 65 259 66 257,
+        // starting with a clear-table code and ending with end-of-data
+        let data = [0x80, 0x0B, 0x60, 0x50, 0x22, 0x0C, 0x0C, 0x85, 0x01];
+        assert_eq!(lzw_decode(&data, &LZWFlateParams::default()).unwrap(), b"-----A---B");
+
+        assert_eq!(lzw_encode(b"-----A---B", &LZWFlateParams::default()).unwrap(), data);
+
+        // long enough for the code width to grow past 9 bits
+        let input: Vec<u8> = (0 .. 5000u32).map(|i| (i * 7 % 251) as u8).collect();
+        for early_change in [0, 1] {
+            let params = LZWFlateParams { early_change, .. LZWFlateParams::default() };
+            let encoded = lzw_encode(&input, &params).unwrap();
+            assert_eq!(lzw_decode(&encoded, &params).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn ascii_hex() {
+        assert_eq!(decode_hex(b"48 65\n6C6c6F>").unwrap(), b"Hello");
+        assert_eq!(decode_hex(b"48656>ignored").unwrap(), b"He`");
+        assert_eq!(decode_hex(b"901fA").unwrap(), [0x90, 0x1f, 0xa0]);
+        assert!(matches!(decode_hex(b"48 6g"), Err(PdfError::HexDecode { pos: 4, bytes: [b'6', b'g'] })));
+        assert_eq!(decode_hex(&encode_hex(b"\x00\xff")).unwrap(), b"\x00\xff");
+    }
+
+    #[test]
+    fn ascii_85() {
+        let encoded = br#"<~9jqo^BlbD-BleB1DJ+*+F(f,q/0JhKF<GL>Cj@.4Gp$d7F!,L7@<6@)/0JDEF<G%<+EV:2
+F!,O<DJ+*.@<*K0@<6L(Df-\0Ec5e;DffZ(EZee.Bl.9pF"AGXBPCsi+DGm>@3BB/F*&OCAf
+u2/AKYi(DIb:@FD,*)+C]U=@3BN#EcYf8ATD3s@q?d$AftVqCh[NqF<G:8+EV:.+Cf>-FD5W
+8ARlolDIal(DId<j@<?3r@:F%a+D58'ATD4$Bl@l3De:,-DJs`8ARoFb/0JMK@qB4^F!,R<A
+KZ&-DfTqBG%G>uD.RTpAKYo'+CT/5+Cei#DII?(E,9)oF*2M7/c~>"#;
+        let plain = "Man is distinguished, not only by his reason, but by this singular passion from \
+            other animals, which is a lust of the mind, that by a perseverance of delight in the \
+            continued and indefatigable generation of knowledge, exceeds the short vehemence of \
+            any carnal pleasure.";
+        assert_eq!(std::str::from_utf8(&decode_85(encoded).unwrap()).unwrap(), plain);
+        // without the optional prefix
+        assert_eq!(decode_85(&encoded[2..]).unwrap(), plain.as_bytes());
+
+        assert_eq!(decode_85(b"z!!~>").unwrap(), [0, 0, 0, 0, 0]);
+        assert!(decode_85(b"9jqo^v~>").is_err());
+        assert!(decode_85(b"9jqo^").is_err());
+    }
+
+    #[test]
+    fn png_predictor() {
+        // two RGB pixels per row, every row encoded with the Paeth predictor
+        let params = LZWFlateParams { predictor: 15, n_components: 3, columns: 2, .. LZWFlateParams::default() };
+        let expected = [10, 20, 30, 40, 50, 60, 12, 25, 33, 47, 52, 70, 200, 100, 0, 255, 128, 1];
+        let paeth = [4, 10, 20, 30, 30, 30, 30, 4, 2, 5, 3, 7, 2, 10, 4, 188, 75, 223, 55, 28, 224];
+        assert_eq!(predictor_decode(paeth.to_vec(), &params).unwrap(), expected);
+
+        let zlib = [120, 156, 99, 225, 18, 145, 3, 2, 22, 38, 86, 102, 118, 38, 46, 150, 61, 222, 247, 205, 101, 30, 0, 0, 22, 244, 3, 217];
+        let params = LZWFlateParams { predictor: 12, .. params };
+        assert_eq!(flate_decode(&zlib, &params).unwrap(), expected);
+
+        // predictor 10 still has a predictor byte in front of every row
+        let params = LZWFlateParams { predictor: 10, n_components: 1, columns: 2, .. LZWFlateParams::default() };
+        assert_eq!(predictor_decode(vec![0, 1, 2, 2, 1, 1], &params).unwrap(), [1, 2, 2, 3]);
+    }
+
+    #[test]
+    fn tiff_predictor() {
+        let params = LZWFlateParams { predictor: 2, n_components: 2, columns: 3, .. LZWFlateParams::default() };
+        assert_eq!(
+            predictor_decode(vec![1, 2, 1, 1, 1, 1, 5, 5, 0, 0, 255, 0], &params).unwrap(),
+            [1, 2, 2, 3, 3, 4, 5, 5, 5, 5, 4, 5]
+        );
+        let params = LZWFlateParams { bits_per_component: 16, n_components: 1, .. params };
+        assert_eq!(
+            predictor_decode(vec![0, 255, 0, 1, 0, 1], &params).unwrap(),
+            [0, 255, 1, 0, 1, 1]
+        );
+    }
+
     #[test]
     fn run_length_decode_test() {
         let x = run_length_decode(&[254, b'a', 255, b'b', 2, b'c', b'b', b'c', 254, b'a', 128]).unwrap();
         assert_eq!(b"aaabbcbcaaa", x.as_slice());
+
+        // data after end-of-data is ignored
+        assert_eq!(run_length_decode(&[1, b'x', b'y', 128, 5]).unwrap(), b"xy");
+        // truncated runs
+        assert!(matches!(run_length_decode(&[3, b'a', b'b']), Err(PdfError::EOF)));
+        assert!(matches!(run_length_decode(&[200]), Err(PdfError::EOF)));
     }
 }
@@ -78,7 +78,7 @@ impl ObjectWrite for Encoding {
 
             for &(&gid, name) in diff_list.iter() {
                 if !last.map(|n| n + 1 == gid).unwrap_or(false) {
-                    list.push(Primitive::Integer(gid as i32));
+                    list.push(Primitive::Integer(gid as i64));
                 }
 
                 list.push(Primitive::Name(name.clone()));
@@ -100,9 +100,164 @@ impl Encoding {
             differences: HashMap::new()
         }
     }
+    /// The character for `code`, looking at `/Differences` first.
+    pub fn decode(&self, code: u8) -> Option<char> {
+        match self.differences.get(&(code as u32)) {
+            Some(name) => glyph_name_to_char(name),
+            None => self.base.decode(code),
+        }
+    }
+}
+
+impl BaseEncoding {
+    /// The character for `code`.
+    ///
+    /// Only `StandardEncoding` and `WinAnsiEncoding` are known. Other simple encodings are
+    /// treated as Latin-1, and `None` is returned for `Identity-H`.
+    pub fn decode(&self, code: u8) -> Option<char> {
+        match *self {
+            BaseEncoding::StandardEncoding => match code {
+                0x27 => Some('\u{2019}'),
+                0x60 => Some('\u{2018}'),
+                0x20 ..= 0x7e => Some(code as char),
+                _ => STANDARD_HIGH.iter().find(|&&(c, _)| c == code).map(|&(_, ch)| ch),
+            },
+            BaseEncoding::WinAnsiEncoding => match code {
+                0x80 ..= 0x9f => WIN_ANSI_80[code as usize - 0x80],
+                0x20 ..= 0x7e | 0xa0 ..= 0xff => Some(code as char),
+                _ => None,
+            },
+            BaseEncoding::IdentityH => None,
+            _ => match code {
+                0x20 ..= 0x7e | 0xa0 ..= 0xff => Some(code as char),
+                _ => None,
+            }
+        }
+    }
+    /// The code for `c`, the reverse of `decode`.
+    pub fn encode(&self, c: char) -> Option<u8> {
+        (0 ..= 255).find(|&code| self.decode(code) == Some(c))
+    }
+}
+
+/// `StandardEncoding` above 0x7E
+const STANDARD_HIGH: &[(u8, char)] = &[
+    (0xa1, '¡'), (0xa2, '¢'), (0xa3, '£'), (0xa4, '\u{2044}'), (0xa5, '¥'), (0xa6, 'ƒ'), (0xa7, '§'),
+    (0xa8, '¤'), (0xa9, '\''), (0xaa, '\u{201c}'), (0xab, '«'), (0xac, '\u{2039}'), (0xad, '\u{203a}'),
+    (0xae, '\u{fb01}'), (0xaf, '\u{fb02}'), (0xb1, '\u{2013}'), (0xb2, '\u{2020}'), (0xb3, '\u{2021}'),
+    (0xb4, '·'), (0xb6, '¶'), (0xb7, '\u{2022}'), (0xb8, '\u{201a}'), (0xb9, '\u{201e}'),
+    (0xba, '\u{201d}'), (0xbb, '»'), (0xbc, '\u{2026}'), (0xbd, '\u{2030}'), (0xbf, '¿'), (0xc1, '`'),
+    (0xc2, '´'), (0xc3, 'ˆ'), (0xc4, '˜'), (0xc5, '¯'), (0xc6, '˘'), (0xc7, '˙'), (0xc8, '¨'),
+    (0xca, '˚'), (0xcb, '¸'), (0xcd, '˝'), (0xce, '˛'), (0xcf, 'ˇ'), (0xd0, '\u{2014}'), (0xe1, 'Æ'),
+    (0xe3, 'ª'), (0xe8, 'Ł'), (0xe9, 'Ø'), (0xea, 'Œ'), (0xeb, 'º'), (0xf1, 'æ'), (0xf5, 'ı'),
+    (0xf8, 'ł'), (0xf9, 'ø'), (0xfa, 'œ'), (0xfb, 'ß'),
+];
+
+/// `WinAnsiEncoding` from 0x80 to 0x9F. The rest is Latin-1.
+const WIN_ANSI_80: [Option<char>; 32] = [
+    Some('€'), None, Some('\u{201a}'), Some('ƒ'), Some('\u{201e}'), Some('\u{2026}'), Some('\u{2020}'), Some('\u{2021}'),
+    Some('ˆ'), Some('\u{2030}'), Some('Š'), Some('\u{2039}'), Some('Œ'), None, Some('Ž'), None,
+    None, Some('\u{2018}'), Some('\u{2019}'), Some('\u{201c}'), Some('\u{201d}'), Some('\u{2022}'), Some('\u{2013}'), Some('\u{2014}'),
+    Some('˜'), Some('\u{2122}'), Some('š'), Some('\u{203a}'), Some('œ'), None, Some('ž'), Some('Ÿ'),
+];
+
+/// Glyph names for 0x20 to 0x7E, except for letters
+const ASCII_NAMES: &[(&str, char)] = &[
+    ("space", ' '), ("exclam", '!'), ("quotedbl", '"'), ("numbersign", '#'), ("dollar", '$'),
+    ("percent", '%'), ("ampersand", '&'), ("quotesingle", '\''), ("parenleft", '('), ("parenright", ')'),
+    ("asterisk", '*'), ("plus", '+'), ("comma", ','), ("hyphen", '-'), ("period", '.'), ("slash", '/'),
+    ("zero", '0'), ("one", '1'), ("two", '2'), ("three", '3'), ("four", '4'), ("five", '5'),
+    ("six", '6'), ("seven", '7'), ("eight", '8'), ("nine", '9'), ("colon", ':'), ("semicolon", ';'),
+    ("less", '<'), ("equal", '='), ("greater", '>'), ("question", '?'), ("at", '@'),
+    ("bracketleft", '['), ("backslash", '\\'), ("bracketright", ']'), ("asciicircum", '^'),
+    ("underscore", '_'), ("grave", '`'), ("braceleft", '{'), ("bar", '|'), ("braceright", '}'),
+    ("asciitilde", '~'),
+];
+
+/// Glyph names for 0xA1 to 0xFF
+const LATIN1_NAMES: [&str; 95] = [
+    "exclamdown", "cent", "sterling", "currency", "yen", "brokenbar", "section", "dieresis",
+    "copyright", "ordfeminine", "guillemotleft", "logicalnot", "softhyphen", "registered", "macron",
+    "degree", "plusminus", "twosuperior", "threesuperior", "acute", "mu", "paragraph",
+    "periodcentered", "cedilla", "onesuperior", "ordmasculine", "guillemotright", "onequarter",
+    "onehalf", "threequarters", "questiondown", "Agrave", "Aacute", "Acircumflex", "Atilde",
+    "Adieresis", "Aring", "AE", "Ccedilla", "Egrave", "Eacute", "Ecircumflex", "Edieresis", "Igrave",
+    "Iacute", "Icircumflex", "Idieresis", "Eth", "Ntilde", "Ograve", "Oacute", "Ocircumflex",
+    "Otilde", "Odieresis", "multiply", "Oslash", "Ugrave", "Uacute", "Ucircumflex", "Udieresis",
+    "Yacute", "Thorn", "germandbls", "agrave", "aacute", "acircumflex", "atilde", "adieresis",
+    "aring", "ae", "ccedilla", "egrave", "eacute", "ecircumflex", "edieresis", "igrave", "iacute",
+    "icircumflex", "idieresis", "eth", "ntilde", "ograve", "oacute", "ocircumflex", "otilde",
+    "odieresis", "divide", "oslash", "ugrave", "uacute", "ucircumflex", "udieresis", "yacute",
+    "thorn", "ydieresis",
+];
+
+/// Other glyph names of `StandardEncoding` and `WinAnsiEncoding`
+const OTHER_NAMES: &[(&str, char)] = &[
+    ("quoteleft", '\u{2018}'), ("quoteright", '\u{2019}'), ("quotedblleft", '\u{201c}'),
+    ("quotedblright", '\u{201d}'), ("quotesinglbase", '\u{201a}'), ("quotedblbase", '\u{201e}'),
+    ("guilsinglleft", '\u{2039}'), ("guilsinglright", '\u{203a}'), ("endash", '\u{2013}'),
+    ("emdash", '\u{2014}'), ("dagger", '\u{2020}'), ("daggerdbl", '\u{2021}'), ("bullet", '\u{2022}'),
+    ("ellipsis", '\u{2026}'), ("perthousand", '\u{2030}'), ("fraction", '\u{2044}'), ("florin", 'ƒ'),
+    ("fi", '\u{fb01}'), ("fl", '\u{fb02}'), ("circumflex", 'ˆ'), ("tilde", '˜'), ("breve", '˘'),
+    ("dotaccent", '˙'), ("ring", '˚'), ("hungarumlaut", '˝'), ("ogonek", '˛'), ("caron", 'ˇ'),
+    ("Lslash", 'Ł'), ("lslash", 'ł'), ("OE", 'Œ'), ("oe", 'œ'), ("dotlessi", 'ı'), ("Euro", '€'),
+    ("Scaron", 'Š'), ("scaron", 'š'), ("Zcaron", 'Ž'), ("zcaron", 'ž'), ("Ydieresis", 'Ÿ'),
+    ("trademark", '\u{2122}'), ("minus", '\u{2212}'), ("nbspace", '\u{a0}'),
+];
+
+/// Maps a glyph name like `eacute` or `uni00E9` to its character.
+///
+/// Knows the names used by `StandardEncoding` and `WinAnsiEncoding`, single letters and the
+/// `uniXXXX` and `uXXXX` forms.
+pub fn glyph_name_to_char(name: &str) -> Option<char> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_alphabetic() {
+            return Some(c);
+        }
+    }
+    if let Some(&(_, c)) = ASCII_NAMES.iter().chain(OTHER_NAMES).find(|&&(n, _)| n == name) {
+        return Some(c);
+    }
+    if let Some(i) = LATIN1_NAMES.iter().position(|&n| n == name) {
+        return char::from_u32(0xa1 + i as u32);
+    }
+    let hex = name.strip_prefix("uni").filter(|h| h.len() == 4)
+        .or_else(|| name.strip_prefix('u').filter(|h| (4 ..= 6).contains(&h.len())))?;
+    char::from_u32(u32::from_str_radix(hex, 16).ok()?)
 }
 impl DeepClone for Encoding {
     fn deep_clone(&self, cloner: &mut impl pdf::object::Cloner) -> Result<Self> {
         Ok(self.clone())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        let win = BaseEncoding::WinAnsiEncoding;
+        assert_eq!(win.decode(b'A'), Some('A'));
+        assert_eq!(win.decode(0x93), Some('\u{201c}'));
+        assert_eq!(win.decode(0xe9), Some('é'));
+        assert_eq!(win.decode(0x81), None);
+
+        let standard = BaseEncoding::StandardEncoding;
+        assert_eq!(standard.decode(0x27), Some('\u{2019}'));
+        assert_eq!(standard.decode(0xae), Some('\u{fb01}'));
+        assert_eq!(standard.decode(0xe9), Some('Ø'));
+
+        let mut encoding = Encoding { base: win, differences: HashMap::new() };
+        encoding.differences.insert(0x41, "eacute".into());
+        encoding.differences.insert(0x42, "uni2603".into());
+        assert_eq!(encoding.decode(0x41), Some('é'));
+        assert_eq!(encoding.decode(0x42), Some('\u{2603}'));
+        assert_eq!(encoding.decode(0x43), Some('C'));
+
+        assert_eq!(glyph_name_to_char("quotedblright"), Some('\u{201d}'));
+        assert_eq!(glyph_name_to_char("ydieresis"), Some('ÿ'));
+        assert_eq!(glyph_name_to_char("zero"), Some('0'));
+        assert_eq!(glyph_name_to_char("g123"), None);
+    }
+}
@@ -108,6 +108,12 @@ pub enum PdfError {
     #[snafu(display("Tried to dereference non-existing object nr {}.", obj_nr))]
     NullRef {obj_nr: u64},
 
+    #[snafu(display("Exceeded the limit of {} nested references.", limit))]
+    RecursionLimit {limit: usize},
+
+    #[snafu(display("Reference cycle through object nr {}.", id))]
+    ReferenceCycle {id: ObjNr},
+
     #[snafu(display("Expected primitive {}, found primitive {} instead.", expected, found))]
     UnexpectedPrimitive {expected: &'static str, found: &'static str},
     /*
@@ -116,6 +122,15 @@ pub enum PdfError {
         display("Expected {}, found {}.", expected, found)
     }
     */
+    #[snafu(display("Integer {} is out of range for {}.", value, typ))]
+    IntegerOutOfRange {value: i64, typ: &'static str},
+
+    #[snafu(display("Expected array of length {}, found {}.", expected, found))]
+    ArrayLength {expected: usize, found: usize},
+
+    #[snafu(display("Array index {} out of bounds, the array has {} elements.", index, len))]
+    ArrayIndex {index: usize, len: usize},
+
     #[snafu(display("Object stream index out of bounds ({}/{}).", index, max))]
     ObjStmOutOfBounds {index: usize, max: usize},
 
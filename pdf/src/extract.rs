@@ -0,0 +1,233 @@
+//! Extracting the text of a page.
+use std::collections::HashMap;
+
+use crate::content::{Matrix, Op, TextDrawAdjusted};
+use crate::encoding::BaseEncoding;
+use crate::error::*;
+use crate::font::{Font, FontType, Widths, ToUnicodeMap};
+use crate::object::*;
+use crate::primitive::{Name, PdfString};
+use crate::standard_fonts::StandardFont;
+
+/// A font of the page, with what is needed to decode and measure its strings.
+struct TextFont<'a> {
+    font: &'a Font,
+    widths: Option<Widths>,
+    to_unicode: Option<ToUnicodeMap>,
+    standard: Option<StandardFont>,
+}
+impl<'a> TextFont<'a> {
+    fn new(font: &'a Font, resolve: &impl Resolve) -> Self {
+        let widths = match font.widths(resolve) {
+            Ok(widths) => widths,
+            Err(e) => {
+                warn!("can't read the widths of {:?}: {:?}", font.name, e);
+                None
+            }
+        };
+        let to_unicode = match font.to_unicode(resolve) {
+            Some(Ok(map)) => Some(map),
+            Some(Err(e)) => {
+                warn!("can't read the ToUnicode map of {:?}: {:?}", font.name, e);
+                None
+            }
+            None => None,
+        };
+        let standard = font.name.as_ref().and_then(|name| StandardFont::from_name(name));
+        TextFont { font, widths, to_unicode, standard }
+    }
+    /// Splits `text` into character codes. CID fonts use two bytes per code.
+    fn codes<'s>(&self, text: &'s PdfString) -> impl Iterator<Item=u32> + 's {
+        let data = text.as_bytes();
+        let step = if self.font.is_cid() { 2 } else { 1 };
+        data.chunks(step).map(|c| c.iter().fold(0, |code, &b| code << 8 | b as u32))
+    }
+    /// Appends the text for `code` to `out`, preferring the `/ToUnicode` map over the encoding.
+    fn decode(&self, code: u32, out: &mut String) {
+        if let Some(text) = self.to_unicode.as_ref().and_then(|map| map.get(code as u16)) {
+            out.push_str(text);
+            return;
+        }
+        out.push(self.encoded_char(code).unwrap_or(std::char::REPLACEMENT_CHARACTER));
+    }
+    /// The character for `code` in the encoding of the font.
+    fn encoded_char(&self, code: u32) -> Option<char> {
+        match self.font.encoding() {
+            _ if self.font.is_cid() => None,
+            Some(encoding) => encoding.decode(code as u8),
+            None if matches!(self.font.subtype, FontType::Type1) => BaseEncoding::StandardEncoding.decode(code as u8),
+            None => BaseEncoding::WinAnsiEncoding.decode(code as u8),
+        }
+    }
+    /// Width of `code` in text space. Without `/Widths`, the metrics of the standard 14
+    /// fonts are used, or half the font size for other fonts.
+    fn width(&self, code: u32) -> f32 {
+        match (&self.widths, self.standard) {
+            (Some(widths), _) => widths.get(code as usize) * 0.001,
+            (None, Some(font)) if font.is_symbolic() => font.glyph_width(code as u8) * 0.001,
+            // the metrics are in WinAnsiEncoding
+            (None, Some(font)) => match self.encoded_char(code).and_then(|c| BaseEncoding::WinAnsiEncoding.encode(c)) {
+                Some(code) => font.glyph_width(code) * 0.001,
+                None => 0.5,
+            },
+            (None, None) => 0.5,
+        }
+    }
+}
+
+/// The parts of the graphics state that matter for text, saved by `q`.
+#[derive(Clone)]
+struct TextState {
+    ctm: Matrix,
+    font: Option<Name>,
+    size: f32,
+    char_space: f32,
+    word_space: f32,
+    horiz_scale: f32,
+    leading: f32,
+    rise: f32,
+}
+
+/// Collects the shown strings and decides where spaces and line breaks go.
+#[derive(Default)]
+struct TextWriter {
+    out: String,
+    /// Where the last string ended, in device space, and its font size
+    last: Option<(f32, f32, f32)>,
+}
+impl TextWriter {
+    fn push(&mut self, start: (f32, f32), size: f32, text: &str) {
+        if let Some((x, y, last_size)) = self.last {
+            let size = size.max(last_size);
+            let ends_with_space = self.out.ends_with(char::is_whitespace);
+            if (start.1 - y).abs() > 0.5 * size {
+                if !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+            } else if start.0 - x > 0.2 * size && !ends_with_space && !text.starts_with(char::is_whitespace) {
+                self.out.push(' ');
+            }
+        }
+        self.out.push_str(text);
+    }
+}
+
+/// Extracts the text of `ops`, using the fonts in `resources`.
+///
+/// Strings are taken in the order they are drawn, which is the reading order for simple
+/// single column layouts. A space is inserted where the gap between two strings is more than
+/// a fifth of the font size, and a line break where the baseline changes. Characters are
+/// looked up in the font's `/ToUnicode` map, then in its encoding. Characters that can't be
+/// decoded become U+FFFD.
+pub fn extract_text(ops: &[Op], resources: &Resources, resolve: &impl Resolve) -> Result<String> {
+    let mut fonts = HashMap::new();
+    let mut state = TextState {
+        ctm: Matrix::identity(),
+        font: None,
+        size: 0.0,
+        char_space: 0.0,
+        word_space: 0.0,
+        horiz_scale: 1.0,
+        leading: 0.0,
+        rise: 0.0,
+    };
+    let mut stack = vec![];
+    let mut tm = Matrix::identity();
+    let mut tlm = Matrix::identity();
+    let mut writer = TextWriter::default();
+
+    for op in ops {
+        let mut strings = vec![];
+        match *op {
+            Op::Save => stack.push(state.clone()),
+            Op::Restore => state = stack.pop().unwrap_or(state),
+            Op::Transform { matrix } => state.ctm = matrix.multiply(&state.ctm),
+            Op::BeginText => {
+                tm = Matrix::identity();
+                tlm = tm;
+            }
+            Op::TextFont { ref name, size } => {
+                if !fonts.contains_key(name) {
+                    if let Some(font) = resources.fonts.get(name) {
+                        fonts.insert(name.clone(), TextFont::new(font, resolve));
+                    }
+                }
+                state.font = Some(name.clone());
+                state.size = size;
+            }
+            Op::CharSpacing { char_space } => state.char_space = char_space,
+            Op::WordSpacing { word_space } => state.word_space = word_space,
+            Op::TextScaling { horiz_scale } => state.horiz_scale = horiz_scale * 0.01,
+            Op::Leading { leading } => state.leading = leading,
+            Op::TextRise { rise } => state.rise = rise,
+            Op::MoveTextPosition { translation } => {
+                tlm = translate(translation.x, translation.y).multiply(&tlm);
+                tm = tlm;
+            }
+            Op::SetTextMatrix { matrix } => {
+                tm = matrix;
+                tlm = matrix;
+            }
+            Op::TextNewline => {
+                tlm = translate(0.0, -state.leading).multiply(&tlm);
+                tm = tlm;
+            }
+            Op::TextDraw { ref text } => strings.push(TextDrawAdjusted::Text(text.clone())),
+            Op::TextDrawAdjusted { ref array } => strings.extend(array.iter().cloned()),
+            _ => {}
+        }
+
+        let font = state.font.as_ref().and_then(|name| fonts.get(name));
+        for part in strings {
+            let text = match part {
+                TextDrawAdjusted::Text(text) => text,
+                TextDrawAdjusted::Spacing(n) => {
+                    let tx = -n * 0.001 * state.size * state.horiz_scale;
+                    tm = translate(tx, 0.0).multiply(&tm);
+                    continue;
+                }
+            };
+            let device = tm.multiply(&state.ctm);
+            let start = device.apply((0.0, state.rise));
+            let size = state.size * (device.a * device.d - device.b * device.c).abs().sqrt();
+
+            let mut s = String::new();
+            if let Some(font) = font {
+                for code in font.codes(&text) {
+                    font.decode(code, &mut s);
+                    let word_space = if code == 32 && !font.font.is_cid() { state.word_space } else { 0.0 };
+                    let tx = (font.width(code) * state.size + state.char_space + word_space) * state.horiz_scale;
+                    tm = translate(tx, 0.0).multiply(&tm);
+                }
+            }
+            writer.push(start, size, &s);
+            let end = tm.multiply(&state.ctm).apply((0.0, state.rise));
+            writer.last = Some((end.0, end.1, size));
+        }
+    }
+    Ok(writer.out)
+}
+
+fn translate(x: f32, y: f32) -> Matrix {
+    Matrix { e: x, f: y, ..Matrix::identity() }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file::FileOptions;
+    use crate::file::tests::document;
+
+    #[test]
+    fn extract_text() {
+        let content = "BT /F1 12 Tf 72 700 Td (Hello) Tj ( world) Tj 0 -14 Td [(Sec) -20 (ond line)-600(here)] TJ ET\nBT /F2 10 Tf 72 600 Td (caf\\351 \\223ok\\224) Tj ET";
+        let data = document("", "", &["/MediaBox [0 0 612 792] /Contents 4 0 R /Resources << /Font << /F1 5 0 R /F2 6 0 R >> >>"], &[
+            &format!("<< /Length {} >>\nstream\n{}\nendstream", content.len(), content),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>",
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Times-Roman /Encoding /WinAnsiEncoding >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let page = file.get_page(0).unwrap();
+        let text = page.extract_text(&file.resolver()).unwrap();
+        assert_eq!(text, "Hello world\nSecond line here\ncaf\u{e9} \u{201c}ok\u{201d}");
+    }
+}
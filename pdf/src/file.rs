@@ -1,7 +1,7 @@
 //! This is kind of the entry-point of the type-safe PDF functionality.
 use std::marker::PhantomData;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::path::Path;
 use std::io::Write;
 
@@ -29,6 +29,9 @@ pub struct PromisedRef<T> {
     _marker:    PhantomData<T>
 }
 impl<T> PromisedRef<T> {
+    pub fn new(inner: PlainRef) -> Self {
+        PromisedRef { inner, _marker: PhantomData }
+    }
     pub fn get_inner(&self) -> PlainRef {
         self.inner
     }
@@ -154,7 +157,14 @@ where
     pub fn load_storage_and_trailer_password(&mut self, password: &[u8]) -> Result<Dictionary> {
 
         let resolver = StorageResolver::new(self);
-        let (refs, trailer) = t!(self.backend.read_xref_table_and_trailer(self.start_offset, &resolver));
+        let (refs, trailer) = match self.backend.read_xref_table_and_trailer(self.start_offset, &resolver) {
+            Ok(r) => r,
+            Err(e) if self.options.allow_xref_error => {
+                warn!("can't read xref table ({:?}), rebuilding it", e);
+                t!(crate::repair::rebuild_xref_and_trailer(t!(self.backend.read(self.start_offset ..))))
+            }
+            Err(e) => return Err(e),
+        };
         self.refs = refs;
 
         if let Some(crypt) = trailer.get("Encrypt") {
@@ -264,13 +274,14 @@ pub enum ScanItem {
 
 struct StorageResolver<'a, B, OC, SC, L> {
     storage: &'a Storage<B, OC, SC, L>,
-    chain: Mutex<Vec<PlainRef>>,
+    /// The objects currently being decoded, for each thread
+    chain: ResolvePath,
 }
 impl<'a, B, OC, SC, L> StorageResolver<'a, B, OC, SC, L> {
     pub fn new(storage: &'a Storage<B, OC, SC, L>) -> Self {
         StorageResolver {
             storage,
-            chain: Mutex::new(vec![])
+            chain: ResolvePath::new()
         }
     }
 }
@@ -302,15 +313,16 @@ where
         
         {
             debug!("get {key:?} as {}", std::any::type_name::<T>());
-            let mut chain = self.chain.lock().unwrap();
-            if chain.contains(&key) {
-                bail!("Recursive reference");
-            }
-            chain.push(key);
+            self.chain.with(|chain| {
+                if chain.contains(&key) {
+                    bail!("Recursive reference");
+                }
+                chain.push(key);
+                Ok(())
+            })?;
         }
         let _defer = Defer(|| {
-            let mut chain = self.chain.lock().unwrap();
-            assert_eq!(chain.pop(), Some(key));
+            assert_eq!(self.chain.with(|chain| chain.pop()), Some(key));
         });
         
         let res = self.storage.cache.get_or_compute(key, || {
@@ -581,6 +593,16 @@ where
         let data = std::fs::read(path)?;
         self.load(data)
     }
+    /// Open a file by mapping it into memory.
+    ///
+    /// Objects are read and parsed when they are first resolved, so only the parts of the file
+    /// that are used are loaded. The file must not be modified while it is open.
+    #[cfg(feature = "mmap")]
+    pub fn open_mmap(self, path: impl AsRef<Path>) -> Result<File<memmap2::Mmap, OC, SC, L>> {
+        let file = std::fs::File::open(path)?;
+        let data = unsafe { memmap2::Mmap::map(&file)? };
+        self.load(data)
+    }
     pub fn storage(self) -> Storage<Vec<u8>, OC, SC, L> {
         let FileOptions { oc, sc, log, .. } = self;
         Storage::empty(oc, sc, log)
@@ -619,12 +641,17 @@ where
         StorageResolver::new(&self.storage)
     }
 
+    /// Parses the object when it is first needed. Other objects are only read if it refers to them.
+    pub fn get<T: Object + DataSize>(&self, r: Ref<T>) -> Result<RcRef<T>> {
+        self.resolver().get(r)
+    }
+
     pub fn get_root(&self) -> &Catalog {
         &self.trailer.root
     }
 
     pub fn pages(&self) -> impl Iterator<Item=Result<PageRc>> + '_ {
-        (0 .. self.num_pages()).map(move |n| self.get_page(n))
+        self.trailer.root.pages.pages(self.resolver())
     }
     pub fn num_pages(&self) -> u32 {
         self.trailer.root.pages.count
@@ -659,7 +686,7 @@ pub struct Trailer {
     pub size:               i32,
 
     #[pdf(key = "Prev")]
-    pub prev_trailer_pos:   Option<i32>,
+    pub prev_trailer_pos:   Option<i64>,
 
     #[pdf(key = "Root")]
     pub root:               RcRef<Catalog>,
@@ -695,3 +722,239 @@ impl Object for XRefStream {
     }
 }
 */
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Builds a file from the given objects, numbered from 1. Object 1 is the root.
+    pub(crate) fn build_file(objects: &[&str]) -> Vec<u8> {
+        let mut file = b"%PDF-1.7\n".to_vec();
+        let mut offsets = vec![];
+        for (i, obj) in objects.iter().enumerate() {
+            offsets.push(file.len());
+            writeln!(file, "{} 0 obj\n{}\nendobj", i + 1, obj).unwrap();
+        }
+        let xref = file.len();
+        writeln!(file, "xref\n0 {}\n0000000000 65535 f ", objects.len() + 1).unwrap();
+        for pos in offsets {
+            writeln!(file, "{:010} 00000 n ", pos).unwrap();
+        }
+        write!(file, "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF", objects.len() + 1, xref).unwrap();
+        file
+    }
+
+    /// Builds a document with the catalog as object 1, the page tree as object 2 and one page
+    /// for each entry of `pages`, numbered from 3, followed by `objects`.
+    /// `catalog`, `tree` and the pages are extra entries for the respective dictionaries.
+    pub(crate) fn document(catalog: &str, tree: &str, pages: &[&str], objects: &[&str]) -> Vec<u8> {
+        let kids: Vec<String> = (0 .. pages.len()).map(|i| format!("{} 0 R", i + 3)).collect();
+        let mut all = vec![
+            format!("<< /Type /Catalog /Pages 2 0 R {} >>", catalog),
+            format!("<< /Type /Pages /Kids [{}] /Count {} {} >>", kids.join(" "), pages.len(), tree),
+        ];
+        all.extend(pages.iter().map(|page| format!("<< /Type /Page /Parent 2 0 R {} >>", page)));
+        all.extend(objects.iter().map(|obj| obj.to_string()));
+        build_file(&all.iter().map(|s| s.as_str()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn indirect_stream_length() {
+        let stream = |length: &str| document("", "", &[], &[
+            &format!("<< /Length {} >>\nstream\nhello world\nendstream", length),
+            "11",
+            "3",
+        ]);
+        let data = |file: &[u8]| {
+            let file = FileOptions::uncached().parse_options(ParseOptions::tolerant()).load(file.to_vec()).unwrap();
+            let resolver = file.resolver();
+            let stream = resolver.get::<Stream<()>>(Ref::from_id(3)).unwrap();
+            Stream::data(&stream, &resolver).unwrap().to_vec()
+        };
+        // the length is defined after the stream
+        assert_eq!(data(&stream("4 0 R")), b"hello world");
+        // wrong or missing lengths fall back to looking for `endstream`
+        assert_eq!(data(&stream("5 0 R")), b"hello world");
+        assert_eq!(data(&stream("9 0 R")), b"hello world");
+    }
+
+    #[test]
+    fn wrong_stream_length() {
+        let data = |stream: &str| {
+            let file = document("", "", &[], &[stream]);
+            let file = FileOptions::uncached().parse_options(ParseOptions::tolerant()).load(file).unwrap();
+            let resolver = file.resolver();
+            let stream = resolver.get::<Stream<()>>(Ref::from_id(3)).unwrap();
+            Stream::data(&stream, &resolver).unwrap().to_vec()
+        };
+        assert_eq!(data("<< /Length 3 >>\nstream\nhello world\nendstream"), b"hello world");
+        assert_eq!(data("<< /Length 40 >>\nstream\nhello world\r\nendstream"), b"hello world");
+        // only one end of line is removed
+        assert_eq!(data("<< /Length 0 >>\nstream\nhello\n\nendstream"), b"hello\n");
+        // a correct length followed by whitespace is fine
+        assert_eq!(data("<< /Length 5 >>\nstream\nhello  \n endstream"), b"hello");
+    }
+
+    #[test]
+    fn strict_and_tolerant() {
+        let data = document("", "", &["/MediaBox [0 0 10 10] /Contents 4 0 R"], &[
+            "<< /Length 99 >>\nstream\n0 0 m 10 10 l S\nendstream",
+        ]);
+        let page = |options| -> Result<Vec<u8>> {
+            let file = FileOptions::uncached().parse_options(options).load(data.clone())?;
+            let resolver = file.resolver();
+            let page = file.get_page(0)?;
+            let contents = page.contents.as_ref().unwrap();
+            Ok(contents.parts.iter().map(|part| Stream::data(part, &resolver).map(|d| d.to_vec())).collect::<Result<Vec<_>>>()?.concat())
+        };
+        assert!(page(ParseOptions::strict()).is_err());
+        assert_eq!(page(ParseOptions::tolerant()).unwrap(), b"0 0 m 10 10 l S");
+
+        let deep = document("/Extra [[[[[[]]]]]]", "", &[], &[]);
+        let options = ParseOptions { max_depth: 4, .. ParseOptions::tolerant() };
+        assert!(FileOptions::uncached().parse_options(options).load(deep.clone()).is_err());
+        assert!(FileOptions::uncached().load(deep).is_ok());
+    }
+
+    #[test]
+    fn broken_startxref() {
+        let mut data = document("", "", &["/MediaBox [0 0 10 20]"], &[]);
+        let pos = data.windows(10).rposition(|w| w == b"startxref\n").unwrap() + 10;
+        data[pos] = b'9';
+        assert!(FileOptions::uncached().load(data.clone()).is_err());
+
+        let file = FileOptions::uncached().parse_options(ParseOptions::tolerant()).load(data).unwrap();
+        assert_eq!(file.num_pages(), 1);
+        assert_eq!(file.get_page(0).unwrap().media_box().unwrap().height(), 20.);
+    }
+
+    #[test]
+    fn trailing_garbage() {
+        let garbage: Vec<u8> = (0 .. 1024u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut data = document("", "", &[], &[]);
+        data.extend_from_slice(&garbage);
+        assert!(FileOptions::uncached().load(data).is_ok());
+
+        // an offset past the end of the file is rebuilt in tolerant mode
+        let mut data = document("", "", &[], &[]);
+        let pos = data.windows(10).rposition(|w| w == b"startxref\n").unwrap() + 10;
+        data.splice(pos .. pos, b"99".iter().copied());
+        data.extend_from_slice(&garbage);
+        assert!(FileOptions::uncached().load(data.clone()).is_err());
+        assert!(FileOptions::uncached().parse_options(ParseOptions::tolerant()).load(data).is_ok());
+    }
+
+    #[test]
+    fn lazy_objects() {
+        #[derive(Default)]
+        struct Loads(Mutex<Vec<ObjNr>>);
+        impl Log for Loads {
+            fn load_object(&self, r: PlainRef) {
+                self.0.lock().unwrap().push(r.id);
+            }
+        }
+        let data = document("", "", &[], &["[4 0 R 5 0 R]", "(four)", "this is not an object"]);
+        let file = FileOptions::cached().log(Loads::default()).load(data).unwrap();
+        assert!(!file.log().0.lock().unwrap().contains(&4));
+        file.log().0.lock().unwrap().clear();
+
+        let s = file.get(Ref::<PdfString>::from_id(4)).unwrap();
+        assert_eq!(s.as_bytes(), b"four");
+        let _ = file.get(Ref::<PdfString>::from_id(4)).unwrap();
+        assert_eq!(*file.log().0.lock().unwrap(), [4]);
+
+        assert!(file.get(Ref::<Vec<PdfString>>::from_id(3)).is_err());
+    }
+
+    #[test]
+    fn catalog() {
+        let data = document("/Version /1.7 /Outlines 4 0 R", "", &["/MediaBox [0 0 612 792]"], &[
+            "<< /Type /Outlines /Count 0 >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let root = file.get_root();
+        assert_eq!(root.version.as_ref().map(|v| v.as_str()), Some("1.7"));
+        assert!(root.outlines.is_some());
+        assert!(root.metadata.is_none());
+        assert_eq!(root.pages.count, 1);
+        assert_eq!(file.get_page(0).unwrap().media_box().unwrap().height(), 792.);
+
+        let data = build_file(&[
+            "<< /Type /Pages /Pages 2 0 R >>",
+            "<< /Type /Pages /Kids [] /Count 0 >>",
+        ]);
+        let err = FileOptions::uncached().load(data).err().unwrap();
+        assert!(err.to_string().contains("Catalog"), "{}", err);
+    }
+
+    #[test]
+    fn page_iter() {
+        let data = build_file(&[
+            "<< /Type /Catalog /Pages 2 0 R >>",
+            "<< /Type /Pages /Kids [3 0 R 4 0 R 7 0 R] /Count 4 >>",
+            "<< /Type /Page /Parent 2 0 R /Rotate 0 >>",
+            "<< /Type /Pages /Parent 2 0 R /Kids [5 0 R 6 0 R] /Count 2 >>",
+            "<< /Type /Page /Parent 4 0 R /Rotate 90 >>",
+            "<< /Type /Page /Parent 4 0 R /Rotate 180 >>",
+            "<< /Type /Page /Parent 2 0 R /Rotate 270 >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let rotations: Vec<_> = file.pages().map(|p| p.unwrap().rotate).collect();
+        assert_eq!(rotations, [Some(0), Some(90), Some(180), Some(270)]);
+
+        // object 3 lists itself as a kid
+        let data = build_file(&[
+            "<< /Type /Catalog /Pages 2 0 R >>",
+            "<< /Type /Pages /Kids [3 0 R] /Count 2 >>",
+            "<< /Type /Pages /Parent 2 0 R /Kids [4 0 R 3 0 R] /Count 2 >>",
+            "<< /Type /Page /Parent 3 0 R >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let pages: Vec<_> = file.pages().collect();
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0].is_ok());
+        assert!(matches!(pages[1], Err(PdfError::ReferenceCycle { id: 3 })));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_pages() {
+        use rayon::iter::ParallelIterator;
+        use itertools::Itertools;
+
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!("<< /Type /Pages /Kids [{}] /Count 300 >>", (3 .. 13).map(|n| format!("{} 0 R", n)).join(" ")),
+        ];
+        for i in 0 .. 10 {
+            let kids = (0 .. 30).map(|j| format!("{} 0 R", 13 + i * 30 + j)).join(" ");
+            objects.push(format!("<< /Type /Pages /Parent 2 0 R /Kids [{}] /Count 30 >>", kids));
+        }
+        for n in 0 .. 300 {
+            objects.push(format!("<< /Type /Page /Parent {} 0 R /Rotate {} >>", 3 + n / 30, n));
+        }
+        let objects: Vec<&str> = objects.iter().map(|s| s.as_str()).collect();
+        let file = FileOptions::cached().load(build_file(&objects)).unwrap();
+        let resolver = file.resolver();
+        let serial: Vec<_> = file.pages().map(|p| p.unwrap().rotate).collect();
+        let parallel: Vec<_> = file.get_root().pages.par_pages(&resolver).map(|p| p.unwrap().rotate).collect();
+        assert_eq!(serial, (0 .. 300).map(Some).collect::<Vec<_>>());
+        assert_eq!(parallel, serial);
+    }
+
+    #[test]
+    fn indirect_kids() {
+        let data = build_file(&[
+            "<< /Type /Catalog /Pages 2 0 R >>",
+            "<< /Type /Pages /Kids 5 0 R /Count 2 >>",
+            "<< /Type /Page /Parent 2 0 R /Rotate 90 >>",
+            "<< /Type /Page /Parent 2 0 R /Rotate 180 >>",
+            "[3 0 R 4 0 R]",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        assert_eq!(file.get_root().pages.kids.len(), 2);
+        let rotations: Vec<_> = file.pages().map(|p| p.unwrap().rotate).collect();
+        assert_eq!(rotations, [Some(90), Some(180)]);
+    }
+}
@@ -2,7 +2,8 @@ use crate as pdf;
 use crate::object::*;
 use crate::primitive::*;
 use crate::error::*;
-use crate::encoding::Encoding;
+use crate::encoding::{BaseEncoding, Encoding};
+use crate::standard_fonts::StandardFont;
 use std::collections::HashMap;
 use std::fmt::Write;
 use crate::parser::{Lexer, parse_with_lexer, ParseFlags};
@@ -79,7 +80,7 @@ impl Object for CidToGidMap {
             },
             p => Err(PdfError::UnexpectedPrimitive {
                 expected: "/Identity or Stream",
-                found: p.get_debug_name()
+                found: p.type_name()
             })
         }
     }
@@ -241,6 +242,30 @@ impl Widths {
     }
 }
 impl Font {
+    /// A non-embedded Type1 font for one of the standard 14 fonts.
+    ///
+    /// Symbol and ZapfDingbats use their built-in encoding, the others `WinAnsiEncoding`.
+    pub fn standard(font: StandardFont) -> Font {
+        let name = Name::from(font.name());
+        let encoding = match font {
+            StandardFont::Symbol | StandardFont::ZapfDingbats => None,
+            _ => Some(Encoding { base: BaseEncoding::WinAnsiEncoding, differences: HashMap::new() }),
+        };
+        Font {
+            subtype: FontType::Type1,
+            name: Some(name.clone()),
+            data: FontData::Type1(TFont {
+                base_font: Some(name),
+                first_char: None,
+                last_char: None,
+                widths: None,
+                font_descriptor: None,
+            }),
+            encoding,
+            to_unicode: None,
+            _other: Dictionary::new(),
+        }
+    }
     pub fn embedded_data(&self, resolve: &impl Resolve) -> Option<Result<Arc<[u8]>>> {
         match self.data {
             FontData::Type0(ref t) => t.descendant_fonts.get(0).and_then(|f| f.embedded_data(resolve)),
@@ -249,6 +274,23 @@ impl Font {
             _ => None
         }
     }
+    /// The embedded font program, following the font descriptor. `None` if the font is not
+    /// embedded, like the standard 14 fonts usually are.
+    pub fn embedded_font_data(&self, resolve: &impl Resolve) -> Result<Option<(FontFormat, Arc<[u8]>)>> {
+        let descriptor = match self.data {
+            FontData::Type0(ref t) => return match t.descendant_fonts.first() {
+                Some(f) => f.embedded_font_data(resolve),
+                None => Ok(None),
+            },
+            FontData::CIDFontType0(ref c) | FontData::CIDFontType2(ref c) => Some(&c.font_descriptor),
+            FontData::Type1(ref t) | FontData::TrueType(ref t) => t.font_descriptor.as_ref(),
+            _ => None
+        };
+        match descriptor {
+            Some(d) => d.font_program(resolve),
+            None => Ok(None),
+        }
+    }
     pub fn is_cid(&self) -> bool {
         matches!(self.data, FontData::Type0(_) | FontData::CIDFontType0(_) | FontData::CIDFontType2(_))
     }
@@ -275,7 +317,7 @@ impl Font {
             FontData::Type1(ref info) | FontData::TrueType(ref info) => {
                 match *info {
                     TFont { first_char: Some(first), ref widths, .. } => Ok(Some(Widths {
-                        default: 0.0,
+                        default: info.missing_width(),
                         first_char: first as usize,
                         values: widths.as_ref().cloned().unwrap_or_default()
                     })),
@@ -319,10 +361,25 @@ impl Font {
             _ => Ok(None)
         }
     }
+    /// Width of `code` in thousandths of the font size, for Type1 and TrueType fonts.
+    ///
+    /// Codes outside of `/FirstChar` to `/LastChar` get the `/MissingWidth` of the font
+    /// descriptor. `None` if the font has neither, and for other font types, which need
+    /// `widths` instead.
+    pub fn width(&self, code: u32) -> Option<f32> {
+        let info = self.info()?;
+        let index = (code as i64).checked_sub(info.first_char? as i64).filter(|&i| i >= 0);
+        let in_range = info.last_char.is_none_or(|last| code as i64 <= last as i64);
+        match (index, &info.widths) {
+            (Some(i), Some(widths)) if in_range && (i as usize) < widths.len() => Some(widths[i as usize]),
+            _ => info.font_descriptor.as_ref().map(|d| d.missing_width),
+        }
+    }
     pub fn to_unicode(&self, resolve: &impl Resolve) -> Option<Result<ToUnicodeMap>> {
         self.to_unicode.as_ref().map(|s| (**s).data(resolve).and_then(|d| parse_cmap(&d)))
     }
 }
+/// The entries of simple fonts, Type1 and TrueType.
 #[derive(Object, ObjectWrite, Debug, DataSize, DeepClone)]
 pub struct TFont {
     #[pdf(key="BaseFont")]
@@ -342,6 +399,11 @@ pub struct TFont {
     #[pdf(key="FontDescriptor")]
     pub font_descriptor: Option<FontDescriptor>
 }
+impl TFont {
+    fn missing_width(&self) -> f32 {
+        self.font_descriptor.as_ref().map(|d| d.missing_width).unwrap_or(0.0)
+    }
+}
 
 #[derive(Object, ObjectWrite, Debug, DataSize, DeepClone)]
 pub struct Type0Font {
@@ -440,7 +502,39 @@ pub struct FontDescriptor {
     #[pdf(key="CharSet")]
     pub char_set: Option<PdfString>
 }
+/// The format of an embedded font program.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FontFormat {
+    /// `/FontFile`
+    Type1,
+    /// `/FontFile2`
+    TrueType,
+    /// `/FontFile3` with `/Subtype /Type1C`, a CFF font
+    Type1C,
+    /// `/FontFile3` with `/Subtype /CIDFontType0C`, a CID-keyed CFF font
+    CIDFontType0C,
+    /// `/FontFile3` with `/Subtype /OpenType`
+    OpenType,
+}
+
 impl FontDescriptor {
+    /// The decoded font program and its format, or `None` if the font is not embedded.
+    pub fn font_program(&self, resolve: &impl Resolve) -> Result<Option<(FontFormat, Arc<[u8]>)>> {
+        if let Some(ref s) = self.font_file {
+            Ok(Some((FontFormat::Type1, t!((**s).data(resolve)))))
+        } else if let Some(ref s) = self.font_file2 {
+            Ok(Some((FontFormat::TrueType, t!((**s).data(resolve)))))
+        } else if let Some(ref s) = self.font_file3 {
+            let format = match s.info.info.subtype {
+                FontTypeExt::Type1C => FontFormat::Type1C,
+                FontTypeExt::CIDFontType0C => FontFormat::CIDFontType0C,
+                FontTypeExt::OpenType => FontFormat::OpenType,
+            };
+            Ok(Some((format, t!((**s).data(resolve)))))
+        } else {
+            Ok(None)
+        }
+    }
     pub fn data(&self, resolve: &impl Resolve) -> Option<Result<Arc<[u8]>>> {
         if let Some(ref s) = self.font_file {
             Some((**s).data(resolve))
@@ -689,7 +783,53 @@ pub fn write_cmap(map: &ToUnicodeMap) -> String {
 #[cfg(test)]
 mod tests {
 
-    use crate::font::{utf16be_to_string, utf16be_to_char, utf16be_to_string_lossy};
+    use crate::font::{utf16be_to_string, utf16be_to_char, utf16be_to_string_lossy, parse_cmap};
+
+    #[test]
+    fn simple_font_widths() {
+        use crate::font::Font;
+        use crate::object::{Object, NoResolve};
+        use crate::parser::{parse, ParseFlags};
+
+        let font = |dict: &str| Font::from_primitive(parse(dict.as_bytes(), &NoResolve, ParseFlags::ANY).unwrap(), &NoResolve).unwrap();
+        let helvetica = font("<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /FirstChar 32 /LastChar 34
+            /Widths [278 278 355] /FontDescriptor << /FontName /Helvetica /Flags 32 /FontBBox [0 0 1000 1000]
+            /ItalicAngle 0 /MissingWidth 250 >> >>");
+        assert_eq!(helvetica.info().unwrap().widths.as_ref().unwrap().len(), 3);
+        assert_eq!(helvetica.width(32), Some(278.));
+        assert_eq!(helvetica.width(34), Some(355.));
+        assert_eq!(helvetica.width(35), Some(250.));
+        assert_eq!(helvetica.width(10), Some(250.));
+        assert_eq!(helvetica.widths(&NoResolve).unwrap().unwrap().get(200), 250.);
+
+        let no_widths = font("<< /Type /Font /Subtype /Type1 /BaseFont /Courier >>");
+        assert_eq!(no_widths.width(65), None);
+        assert_eq!(no_widths.info().unwrap().first_char, None);
+    }
+
+    #[test]
+    fn to_unicode_cmap() {
+        let data = b"/CIDInit /ProcSet findresource begin 12 dict begin begincmap
+            1 begincodespacerange <0000> <FFFF> endcodespacerange
+            2 beginbfchar
+            <0003> <0020>
+            <0011> <D835DC00>
+            endbfchar
+            2 beginbfrange
+            <0024> <0026> <0041>
+            <0030> <0031> [<0066006C> <00E9>]
+            endbfrange
+            endcmap CMapName currentdict /CMap defineresource pop end end";
+        let map = parse_cmap(data).unwrap();
+        assert_eq!(map.get(0x03), Some(" "));
+        assert_eq!(map.get(0x11), Some("\u{1d400}"));
+        assert_eq!(map.get(0x24), Some("A"));
+        assert_eq!(map.get(0x26), Some("C"));
+        assert_eq!(map.get(0x27), None);
+        assert_eq!(map.get(0x30), Some("fl"));
+        assert_eq!(map.get(0x31), Some("\u{e9}"));
+        assert_eq!(map.len(), 7);
+    }
     #[test]
     fn utf16be_to_string_quick() {
         let v = vec![0x20, 0x09];
@@ -736,4 +876,29 @@ mod tests {
         assert_eq!(utf16be_to_string(&v[..8]).unwrap(), String::from("𝄞mu"));
         assert_eq!(utf16be_to_string_lossy(&v), lossy);
     }
+
+    #[test]
+    fn embedded_font_data() {
+        use crate::file::FileOptions;
+        use crate::file::tests::document;
+        use crate::font::{Font, FontFormat};
+        use crate::object::{Ref, Resolve};
+
+        let data = document("", "", &[], &[
+            "<< /Type /Font /Subtype /TrueType /BaseFont /ABCDEF+Arial /FontDescriptor
+                << /Type /FontDescriptor /FontName /ABCDEF+Arial /Flags 32 /FontBBox [0 0 1000 1000]
+                /ItalicAngle 0 /FontFile2 4 0 R >> >>",
+            "<< /Length 8 /Length1 8 >>\nstream\n\x00\x01\x00\x00glyf\nendstream",
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let font = resolver.get::<Font>(Ref::from_id(3)).unwrap();
+        let (format, data) = font.embedded_font_data(&resolver).unwrap().unwrap();
+        assert_eq!(format, FontFormat::TrueType);
+        assert_eq!(&*data, b"\x00\x01\x00\x00glyf");
+
+        let helvetica = resolver.get::<Font>(Ref::from_id(5)).unwrap();
+        assert!(helvetica.embedded_font_data(&resolver).unwrap().is_none());
+    }
 }
@@ -16,9 +16,14 @@ pub mod backend;
 pub mod content;
 pub mod parser;
 pub mod font;
+pub mod standard_fonts;
 pub mod any;
 pub mod encoding;
 pub mod build;
+pub mod writer;
+pub mod repair;
+pub mod text;
+pub mod extract;
 
 // mod content;
 pub mod enc;
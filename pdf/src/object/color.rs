@@ -75,6 +75,35 @@ impl Object for ColorSpace {
     }
 }
 impl ColorSpace {
+    /// Number of color components of a color value, or of a sample of an image.
+    ///
+    /// Indexed spaces have one component, the index. Returns `None` for `Pattern`, named
+    /// spaces that have to be looked up in the resources, and unknown spaces.
+    pub fn num_components(&self) -> Option<usize> {
+        match *self {
+            ColorSpace::DeviceGray | ColorSpace::CalGray(_) => Some(1),
+            ColorSpace::DeviceRGB | ColorSpace::CalRGB(_) => Some(3),
+            ColorSpace::DeviceCMYK | ColorSpace::CalCMYK(_) => Some(4),
+            ColorSpace::DeviceN { ref names, .. } => Some(names.len()),
+            ColorSpace::Indexed(..) | ColorSpace::Separation(..) => Some(1),
+            ColorSpace::Icc(ref icc) => Some(icc.info.components as usize),
+            ColorSpace::Pattern | ColorSpace::Named(_) | ColorSpace::Other(_) => None,
+        }
+    }
+
+    /// The color of `index` in an indexed space, as components of the base space.
+    ///
+    /// Returns `None` if this is not an indexed space or the index is out of range.
+    pub fn palette_color(&self, index: u8) -> Option<&[u8]> {
+        match *self {
+            ColorSpace::Indexed(ref base, hival, ref lookup) if index <= hival => {
+                let n = base.num_components()?;
+                lookup.get(index as usize * n .. (index as usize + 1) * n)
+            }
+            _ => None,
+        }
+    }
+
     fn from_primitive_depth(p: Primitive, resolve: &impl Resolve, depth: usize) -> Result<ColorSpace> {
         let p = p.resolve(resolve)?;
 
@@ -114,7 +143,7 @@ impl ColorSpace {
                     },
                     p => return Err(PdfError::UnexpectedPrimitive {
                         expected: "String or Stream",
-                        found: p.get_debug_name()
+                        found: p.type_name()
                     })
                 };
                 Ok(ColorSpace::Indexed(base, hival, lookup))
@@ -159,8 +188,34 @@ impl ColorSpace {
 impl ObjectWrite for ColorSpace {
     fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
         match *self {
+            ColorSpace::DeviceGray => Ok(Primitive::name("DeviceGray")),
             ColorSpace::DeviceCMYK => Ok(Primitive::name("DeviceCMYK")),
             ColorSpace::DeviceRGB => Ok(Primitive::name("DeviceRGB")),
+            ColorSpace::Pattern => Ok(Primitive::name("Pattern")),
+            ColorSpace::Named(ref name) => Ok(name.clone().into()),
+            ColorSpace::CalGray(ref dict) => Ok(Primitive::Array(vec![Primitive::name("CalGray"), dict.clone().into()])),
+            ColorSpace::CalRGB(ref dict) => Ok(Primitive::Array(vec![Primitive::name("CalRGB"), dict.clone().into()])),
+            ColorSpace::CalCMYK(ref dict) => Ok(Primitive::Array(vec![Primitive::name("CalCMYK"), dict.clone().into()])),
+            ColorSpace::Icc(ref icc) => Ok(Primitive::Array(vec![Primitive::name("ICCBased"), icc.to_primitive(update)?])),
+            ColorSpace::Separation(ref name, ref alt, ref tint) => Ok(Primitive::Array(vec![
+                Primitive::name("Separation"),
+                name.clone().into(),
+                alt.to_primitive(update)?,
+                tint.to_primitive(update)?,
+            ])),
+            ColorSpace::DeviceN { ref names, ref alt, ref tint, ref attr } => {
+                let mut arr = vec![
+                    Primitive::name("DeviceN"),
+                    Primitive::Array(names.iter().map(|name| name.clone().into()).collect()),
+                    alt.to_primitive(update)?,
+                    tint.to_primitive(update)?,
+                ];
+                if let Some(attr) = attr {
+                    arr.push(attr.clone().into());
+                }
+                Ok(Primitive::Array(arr))
+            }
+            ColorSpace::Other(ref arr) => Ok(Primitive::Array(arr.clone())),
             ColorSpace::Indexed(ref  base, hival, ref lookup) => {
                 let base = base.to_primitive(update)?;
                 let hival = Primitive::Integer(hival.into());
@@ -171,10 +226,62 @@ impl ObjectWrite for ColorSpace {
                 };
                 Ok(Primitive::Array(vec![Primitive::name("Indexed"), base, hival, lookup]))
             }
-            ref p => {
-                dbg!(p);
-                unimplemented!()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, ParseFlags};
+
+    #[test]
+    fn indexed() {
+        let p = parse(b"[/Indexed /DeviceRGB 2 <ff0000 00ff00 0000ff>]", &NoResolve, ParseFlags::ARRAY).unwrap();
+        let cs = ColorSpace::from_primitive(p, &NoResolve).unwrap();
+        match cs {
+            ColorSpace::Indexed(ref base, hival, ref lookup) => {
+                assert!(matches!(**base, ColorSpace::DeviceRGB));
+                assert_eq!(hival, 2);
+                assert_eq!(lookup.len(), 9);
+            }
+            ref cs => panic!("{:?}", cs),
+        }
+        assert_eq!(cs.num_components(), Some(1));
+        assert_eq!(cs.palette_color(1), Some(&[0, 255, 0][..]));
+        assert_eq!(cs.palette_color(3), None);
+
+        let p = cs.to_primitive(&mut NoUpdate).unwrap();
+        let cs = ColorSpace::from_primitive(p, &NoResolve).unwrap();
+        assert_eq!(cs.palette_color(2), Some(&[0, 0, 255][..]));
+
+        let gray = ColorSpace::from_primitive(Primitive::name("DeviceGray"), &NoResolve).unwrap();
+        assert_eq!(gray.num_components(), Some(1));
+        assert_eq!(gray.to_primitive(&mut NoUpdate).unwrap(), Primitive::name("DeviceGray"));
+        assert_eq!(ColorSpace::DeviceCMYK.num_components(), Some(4));
+    }
+
+    #[test]
+    fn device_n() {
+        let p = parse(b"[/DeviceN [/Spot] /DeviceCMYK
+            << /FunctionType 2 /Domain [0 1] /C0 [0 0 0 0] /C1 [0 0.5 1 0] /N 1 >> << /Subtype /DeviceN >>]",
+            &NoResolve, ParseFlags::ARRAY).unwrap();
+        let cs = ColorSpace::from_primitive(p, &NoResolve).unwrap();
+        let cs = ColorSpace::from_primitive(cs.to_primitive(&mut NoUpdate).unwrap(), &NoResolve).unwrap();
+        match cs {
+            ColorSpace::DeviceN { ref names, ref alt, ref tint, ref attr } => {
+                assert_eq!(names, &[Name::from("Spot")]);
+                assert!(matches!(**alt, ColorSpace::DeviceCMYK));
+                let mut out = [0.; 4];
+                tint.apply(&[0.5], &mut out).unwrap();
+                assert_eq!(out, [0., 0.25, 0.5, 0.]);
+                assert_eq!(attr.as_ref().unwrap()["Subtype"], Primitive::name("DeviceN"));
             }
+            ref cs => panic!("{:?}", cs),
         }
+
+        // functions other than exponential ones can't be written yet
+        let cs = ColorSpace::Separation("Spot".into(), Box::new(ColorSpace::DeviceGray), Function::Calculator);
+        assert!(cs.to_primitive(&mut NoUpdate).is_err());
     }
 }
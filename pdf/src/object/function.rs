@@ -179,29 +179,28 @@ impl Object for Function {
     }
 }
 impl ObjectWrite for Function {
-    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
-        unimplemented!()
-        /*
-        let dict = match self {
-            Function::Interpolated(parts) => {
-                let first: &InterpolatedFunctionDim = try_opt!(parts.get(0));
-                let f2 = Function2 {
-                    c0: parts.iter().map(|p| p.c0).collect(),
-                    c1: parts.iter().map(|p| p.c0).collect(),
-                    exponent: first.exponent
-                };
-                let f = RawFunction {
-                    function_type: 2,
-                    domain: vec![first.input_range.0, first.input_range.1],
-                    range: parts.iter().flat_map(|p| [p.output_range.0, p.output_range.1]).collect(),
-                    decode: None,
-                    encode: None,
-                    order
-                };
+    /// Only exponential interpolation functions can be written so far.
+    fn to_primitive(&self, _update: &mut impl Updater) -> Result<Primitive> {
+        let parts = match *self {
+            Function::Interpolated(ref parts) => parts,
+            Function::Sampled(_) => bail!("can't write sampled functions"),
+            Function::Stiching => bail!("can't write stitching functions"),
+            Function::Calculator | Function::PostScript { .. } => bail!("can't write PostScript functions"),
+        };
+        let first = try_opt!(parts.first());
+        let numbers = |values: Vec<f32>| Primitive::Array(values.into_iter().map(Primitive::from).collect());
 
-            }
+        let mut dict = Dictionary::new();
+        dict.insert("FunctionType", 2);
+        dict.insert("Domain", numbers(vec![first.input_range.0, first.input_range.1]));
+        // an unbounded output is written without /Range
+        if parts.iter().all(|p| p.output_range.0.is_finite() && p.output_range.1.is_finite()) {
+            dict.insert("Range", numbers(parts.iter().flat_map(|p| [p.output_range.0, p.output_range.1]).collect()));
         }
-        */
+        dict.insert("C0", numbers(parts.iter().map(|p| p.c0).collect()));
+        dict.insert("C1", numbers(parts.iter().map(|p| p.c1).collect()));
+        dict.insert("N", first.exponent);
+        Ok(dict.into())
     }
 }
 impl DeepClone for Function {
@@ -6,13 +6,15 @@ mod types;
 mod stream;
 mod color;
 mod function;
+mod resolve;
 
 pub use self::types::*;
 pub use self::stream::*;
 pub use self::color::*;
 pub use self::function::*;
+pub use self::resolve::*;
 pub use crate::file::PromisedRef;
-use crate::parser::ParseFlags;
+use crate::parser::{ParseFlags, MAX_DEPTH};
 
 use crate::primitive::*;
 use crate::error::*;
@@ -26,16 +28,24 @@ use std::ops::{Deref, Range};
 use std::hash::{Hash, Hasher};
 use std::convert::TryInto;
 use datasize::DataSize;
+use once_cell::sync::OnceCell;
 use itertools::Itertools;
 
 pub type ObjNr = u64;
 pub type GenNr = u64;
 
+/// How to deal with files that violate the spec.
+///
+/// `strict` errors on violations, `tolerant` recovers where it can.
 pub struct ParseOptions {
     pub allow_error_in_option: bool,
     pub allow_xref_error: bool,
     pub allow_invalid_ops: bool,
     pub allow_missing_endobj: bool,
+    /// Find the end of a stream by looking for `endstream` if `/Length` is wrong.
+    pub allow_wrong_length: bool,
+    /// How deep arrays and dictionaries may be nested.
+    pub max_depth: usize,
 }
 impl ParseOptions {
     pub const fn tolerant() -> Self {
@@ -44,6 +54,8 @@ impl ParseOptions {
             allow_xref_error: true,
             allow_invalid_ops: true,
             allow_missing_endobj: true,
+            allow_wrong_length: true,
+            max_depth: MAX_DEPTH,
         }
     }
     pub const fn strict() -> Self {
@@ -52,6 +64,8 @@ impl ParseOptions {
             allow_xref_error: false,
             allow_invalid_ops: true,
             allow_missing_endobj: false,
+            allow_wrong_length: false,
+            max_depth: MAX_DEPTH,
         }
     }
 }
@@ -122,6 +136,19 @@ impl Updater for NoUpdate {
 
 pub trait ObjectWrite {
     fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive>;
+
+    /// The object in PDF syntax, as it would appear in a file.
+    ///
+    /// This uses `NoUpdate`, so it is only meant for objects that do not create indirect objects.
+    fn to_pdf_bytes(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.to_primitive(&mut NoUpdate)?.serialize(&mut out)?;
+        Ok(out)
+    }
+    /// Same as `to_pdf_bytes`, but fails if the result is not UTF-8, like for binary strings.
+    fn to_pdf_string(&self) -> Result<String> {
+        Ok(String::from_utf8(self.to_pdf_bytes()?)?)
+    }
 }
 
 pub trait FromDict: Sized {
@@ -142,7 +169,8 @@ pub trait Trace {
 ///////
 
 // TODO move to primitive.rs
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, DataSize)]
+/// Ordered by `id`, then `gen`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord, DataSize)]
 pub struct PlainRef {
     pub id:     ObjNr,
     pub gen:    GenNr,
@@ -196,6 +224,27 @@ impl<T> Ref<T> {
     pub fn upcast<U>(self) -> Ref<U> where T: SubType<U> {
         Ref::new(self.inner)
     }
+    /// The same reference, as a reference to a `U`.
+    ///
+    /// This only changes the type. Nothing is checked, so reading the object as `U` may fail
+    /// later. Use `try_cast` to check it first.
+    pub fn cast<U>(self) -> Ref<U> {
+        Ref::new(self.inner)
+    }
+    /// Like `cast`, but fails if the object can't be read as a `U`, e.g. because `/Type` is wrong.
+    pub fn try_cast<U: Object>(self, resolve: &impl Resolve) -> Result<Ref<U>> {
+        let r = self.cast();
+        r.resolve(resolve)?;
+        Ok(r)
+    }
+}
+impl<T: Object> Ref<T> {
+    /// Reads the object this reference points to.
+    ///
+    /// Unlike `Resolve::get`, the result is not shared or cached.
+    pub fn resolve(&self, resolve: &impl Resolve) -> Result<T> {
+        T::from_primitive(resolve.resolve(self.inner)?, resolve)
+    }
 }
 impl<T: Object> Object for Ref<T> {
     fn from_primitive(p: Primitive, _: &impl Resolve) -> Result<Self> {
@@ -268,7 +317,7 @@ impl<T: Object + std::fmt::Debug + DataSize> Object for RcRef<T> {
     fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
         match p {
             Primitive::Reference(r) => resolve.get(Ref::new(r)),
-            p => Err(PdfError::UnexpectedPrimitive {expected: "Reference", found: p.get_debug_name()})
+            p => Err(PdfError::UnexpectedPrimitive {expected: "Reference", found: p.type_name()})
         }
     }
 }
@@ -319,12 +368,17 @@ impl<T> PartialEq for RcRef<T> {
 }
 impl<T> Eq for RcRef<T> {}
 
+/// A value that can be given directly or as a reference, like `/Resources`.
+///
+/// References are resolved when reading, and written back as references, so the file keeps
+/// its structure. Both variants deref to the value.
 #[derive(Debug, DataSize)]
 pub enum MaybeRef<T> {
     Direct(Shared<T>),
     Indirect(RcRef<T>),
 }
 impl<T> MaybeRef<T> {
+    /// The reference, if the value is indirect.
     pub fn as_ref(&self) -> Option<Ref<T>> {
         match *self {
             MaybeRef::Indirect(ref r) => Some(r.get_ref()),
@@ -337,6 +391,13 @@ impl<T> MaybeRef<T> {
             MaybeRef::Indirect(ref r) => &r.data
         }
     }
+    /// A copy of the value.
+    ///
+    /// An indirect value was already read along with its reference, so `resolve` isn't needed.
+    /// This also works for values made with `Updater::create`, which no resolver knows about.
+    pub fn resolve(&self, _resolve: &impl Resolve) -> Result<T> where T: Clone {
+        Ok((**self.data()).clone())
+    }
 }
 impl<T: Object+DataSize> Object for MaybeRef<T> {
     fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
@@ -430,19 +491,29 @@ impl<T> PartialEq for MaybeRef<T> {
 }
 impl<T> Eq for MaybeRef<T> {}
 
+/// A value that is only read when it is needed.
+///
+/// Reading a `Lazy` keeps the primitive, usually a reference, without resolving it.
+/// `get` resolves it on the first call and keeps the result, `load` reads it every time.
 #[derive(Debug, Clone, DataSize)]
 pub struct Lazy<T> {
     primitive: Primitive,
+    #[data_size(skip)]
+    cache: OnceCell<T>,
     _marker: PhantomData<T>
 }
 impl<T: Object> Lazy<T> {
     pub fn load(&self, resolve: &impl Resolve) -> Result<T> {
         T::from_primitive(self.primitive.clone(), resolve)
     }
+    /// The value, resolved on the first call only.
+    pub fn get(&self, resolve: &impl Resolve) -> Result<&T> {
+        self.cache.get_or_try_init(|| self.load(resolve))
+    }
 }
 impl<T: Object> Object for Lazy<T> {
     fn from_primitive(p: Primitive, _: &impl Resolve) -> Result<Self> {
-        Ok(Self { primitive: p, _marker: PhantomData })
+        Ok(Self { primitive: p, cache: OnceCell::new(), _marker: PhantomData })
     }
 }
 impl<T: ObjectWrite> ObjectWrite for Lazy<T> {
@@ -452,7 +523,7 @@ impl<T: ObjectWrite> ObjectWrite for Lazy<T> {
 }
 impl<T> Default for Lazy<T> {
     fn default() -> Self {
-        Lazy { primitive: Primitive::Null, _marker: PhantomData }
+        Lazy { primitive: Primitive::Null, cache: OnceCell::new(), _marker: PhantomData }
     }
 }
 
@@ -470,7 +541,35 @@ impl Object for i32 {
 }
 impl ObjectWrite for i32 {
     fn to_primitive(&self, _: &mut impl Updater) -> Result<Primitive> {
-        Ok(Primitive::Integer(*self))
+        Ok(Primitive::Integer((*self).into()))
+    }
+}
+
+impl Object for u8 {
+    fn from_primitive(p: Primitive, r: &impl Resolve) -> Result<Self> {
+        match p {
+            Primitive::Reference(id) => r.resolve(id)?.as_u8(),
+            p => p.as_u8()
+        }
+    }
+}
+impl ObjectWrite for u8 {
+    fn to_primitive(&self, _: &mut impl Updater) -> Result<Primitive> {
+        Ok(Primitive::Integer(*self as _))
+    }
+}
+
+impl Object for u16 {
+    fn from_primitive(p: Primitive, r: &impl Resolve) -> Result<Self> {
+        match p {
+            Primitive::Reference(id) => r.resolve(id)?.as_u16(),
+            p => p.as_u16()
+        }
+    }
+}
+impl ObjectWrite for u16 {
+    fn to_primitive(&self, _: &mut impl Updater) -> Result<Primitive> {
+        Ok(Primitive::Integer(*self as _))
     }
 }
 
@@ -488,11 +587,42 @@ impl ObjectWrite for u32 {
     }
 }
 
+impl Object for i64 {
+    fn from_primitive(p: Primitive, r: &impl Resolve) -> Result<Self> {
+        match p {
+            Primitive::Reference(id) => r.resolve(id)?.as_i64(),
+            p => p.as_i64()
+        }
+    }
+}
+impl ObjectWrite for i64 {
+    fn to_primitive(&self, _: &mut impl Updater) -> Result<Primitive> {
+        Ok(Primitive::Integer(*self))
+    }
+}
+
+impl Object for u64 {
+    fn from_primitive(p: Primitive, r: &impl Resolve) -> Result<Self> {
+        match p {
+            Primitive::Reference(id) => r.resolve(id)?.as_u64(),
+            p => p.as_u64()
+        }
+    }
+}
+impl ObjectWrite for u64 {
+    fn to_primitive(&self, _: &mut impl Updater) -> Result<Primitive> {
+        match (*self).try_into() {
+            Ok(i) => Ok(Primitive::Integer(i)),
+            Err(_) => bail!("integer {} does not fit into a PDF integer", self)
+        }
+    }
+}
+
 impl Object for usize {
     fn from_primitive(p: Primitive, r: &impl Resolve) -> Result<Self> {
         match p {
-            Primitive::Reference(id) => Ok(r.resolve(id)?.as_u32()? as usize),
-            p => Ok(p.as_u32()? as usize)
+            Primitive::Reference(id) => r.resolve(id)?.as_usize(),
+            p => p.as_usize()
         }
     }
 }
@@ -512,6 +642,26 @@ impl Object for f32 {
 }
 impl ObjectWrite for f32 {
     fn to_primitive(&self, _: &mut impl Updater) -> Result<Primitive> {
+        if !self.is_finite() {
+            bail!("{} can't be written as a PDF real", self);
+        }
+        Ok((*self).into())
+    }
+}
+
+impl Object for f64 {
+    fn from_primitive(p: Primitive, r: &impl Resolve) -> Result<Self> {
+        match p {
+            Primitive::Reference(id) => r.resolve(id)?.as_f64(),
+            p => p.as_f64()
+        }
+    }
+}
+impl ObjectWrite for f64 {
+    fn to_primitive(&self, _: &mut impl Updater) -> Result<Primitive> {
+        if !self.is_finite() {
+            bail!("{} can't be written as a PDF real", self);
+        }
         Ok(Primitive::Number(*self))
     }
 }
@@ -535,7 +685,7 @@ impl Object for Dictionary {
         match p {
             Primitive::Dictionary(dict) => Ok(dict),
             Primitive::Reference(id) => Dictionary::from_primitive(r.resolve(id)?, r),
-            _ => Err(PdfError::UnexpectedPrimitive {expected: "Dictionary", found: p.get_debug_name()}),
+            _ => Err(PdfError::UnexpectedPrimitive {expected: "Dictionary", found: p.type_name()}),
         }
     }
 }
@@ -576,6 +726,30 @@ impl<T: ObjectWrite> ObjectWrite for Vec<T> {
         Primitive::array::<T, _, _, _>(self.iter(), update)
     }
 }
+impl<T: Object, const N: usize> Object for [T; N] {
+    fn from_primitive(p: Primitive, r: &impl Resolve) -> Result<Self> {
+        let arr = p.resolve(r)?.into_array()?;
+        if arr.len() != N {
+            return Err(PdfError::ArrayLength { expected: N, found: arr.len() });
+        }
+        let items = arr.into_iter()
+            .map(|p| T::from_primitive(p, r))
+            .collect::<Result<Vec<T>>>()?;
+        Ok(items.try_into().unwrap_or_else(|_| unreachable!()))
+    }
+}
+impl<T: ObjectWrite, const N: usize> ObjectWrite for [T; N] {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        Primitive::array::<T, _, _, _>(self.iter(), update)
+    }
+}
+impl<T: Trace, const N: usize> Trace for [T; N] {
+    fn trace(&self, cb: &mut impl FnMut(PlainRef)) {
+        for i in self.iter() {
+            i.trace(cb);
+        }
+    }
+}
 impl<T: DeepClone> DeepClone for Vec<T> {
     fn deep_clone(&self, cloner: &mut impl Cloner) -> Result<Self> {
         self.iter().map(|t| t.deep_clone(cloner)).collect()
@@ -662,7 +836,7 @@ impl<V: Object> Object for HashMap<Name, V> {
                 Ok(new)
             }
             Primitive::Reference (id) => HashMap::from_primitive(resolve.resolve(id)?, resolve),
-            p => Err(PdfError::UnexpectedPrimitive {expected: "Dictionary", found: p.get_debug_name()})
+            p => Err(PdfError::UnexpectedPrimitive {expected: "Dictionary", found: p.type_name()})
         }
     }
 }
@@ -685,6 +859,35 @@ impl<V: DeepClone> DeepClone for HashMap<Name, V> {
     }
 }
 
+/// Same as `HashMap<Name, V>`, but with plain `String` keys.
+///
+/// Iteration order follows `HashMap` and is not guaranteed to match the order
+/// of the keys in the source dictionary. Use `Dictionary` if order matters.
+impl<V: Object> Object for HashMap<String, V> {
+    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+        let map = HashMap::<Name, V>::from_primitive(p, resolve)?;
+        Ok(map.into_iter().map(|(k, v)| (k.as_str().into(), v)).collect())
+    }
+}
+impl<V: ObjectWrite> ObjectWrite for HashMap<String, V> {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        if self.is_empty() {
+            Ok(Primitive::Null)
+        } else {
+            let mut dict = Dictionary::new();
+            for (k, v) in self.iter() {
+                dict.insert(k.as_str(), v.to_primitive(update)?);
+            }
+            Ok(Primitive::Dictionary(dict))
+        }
+    }
+}
+impl<V: DeepClone> DeepClone for HashMap<String, V> {
+    fn deep_clone(&self, cloner: &mut impl Cloner) -> Result<Self> {
+        self.iter().map(|(k, v)| Ok((k.clone(), v.deep_clone(cloner)?))).collect()
+    }
+}
+
 impl<T: Object> Object for Option<T> {
     fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
         match p {
@@ -738,6 +941,50 @@ impl<T: ObjectWrite> ObjectWrite for Box<T> {
         (**self).to_primitive(update)
     }
 }
+/// Writes a text string (see `PdfString::from_text`). There is no `Object` impl, as a borrowed
+/// `str` cannot be produced from a `Primitive`; read a `PdfString` instead.
+impl ObjectWrite for str {
+    fn to_primitive(&self, _: &mut impl Updater) -> Result<Primitive> {
+        Ok(Primitive::String(PdfString::from_text(self)))
+    }
+}
+impl ObjectWrite for &str {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        (**self).to_primitive(update)
+    }
+}
+
+impl Object for String {
+    fn from_primitive(p: Primitive, r: &impl Resolve) -> Result<Self> {
+        PdfString::from_primitive(p, r)?.to_string()
+    }
+}
+impl ObjectWrite for String {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        self.as_str().to_primitive(update)
+    }
+}
+
+/// Read from a string or name that holds exactly one character.
+impl Object for char {
+    fn from_primitive(p: Primitive, r: &impl Resolve) -> Result<Self> {
+        let s = match p.resolve(r)? {
+            Primitive::Name(name) => String::from(name.as_str()),
+            p => PdfString::from_primitive(p, r)?.to_string()?,
+        };
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => bail!("expected a single character, found {} characters in {:?}", s.chars().count(), s)
+        }
+    }
+}
+impl ObjectWrite for char {
+    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+        self.encode_utf8(&mut [0; 4]).to_primitive(update)
+    }
+}
+
 impl<T: Trace> Trace for Box<T> {
     fn trace(&self, cb: &mut impl FnMut(PlainRef)) {
         (**self).trace(cb)
@@ -756,29 +1003,40 @@ impl ObjectWrite for () {
 }
 impl Trace for () {}
 
-impl<T, U> Object for (T, U) where T: Object, U: Object {
-    fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
-        let arr = p.resolve(resolve)?.into_array()?;
-        if arr.len() != 2 {
-            bail!("expected array of length 2 (found {})", arr.len());
+macro_rules! tuple_impls {
+    ($n:expr; $($t:ident $v:ident $idx:tt),*) => {
+        impl<$($t: Object),*> Object for ($($t,)*) {
+            fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<Self> {
+                let arr = p.resolve(resolve)?.into_array()?;
+                if arr.len() != $n {
+                    return Err(PdfError::ArrayLength { expected: $n, found: arr.len() });
+                }
+                let [$($v),*]: [Primitive; $n] = arr.try_into().unwrap();
+                Ok(($($t::from_primitive($v, resolve)?,)*))
+            }
         }
-        let [a, b]: [Primitive; 2] = arr.try_into().unwrap();
-        Ok((T::from_primitive(a, resolve)?, U::from_primitive(b, resolve)?))
-    }
-}
-
-impl<T, U> ObjectWrite for (T, U) where T: ObjectWrite, U: ObjectWrite {
-    fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
-        Ok(Primitive::Array(vec![self.0.to_primitive(update)?, self.1.to_primitive(update)?]))
-    }
-}
-
-impl<T: Trace, U: Trace> Trace for (T, U) {
-    fn trace(&self, cb: &mut impl FnMut(PlainRef)) {
-        self.0.trace(cb);
-        self.1.trace(cb);
-    }
+        impl<$($t: ObjectWrite),*> ObjectWrite for ($($t,)*) {
+            fn to_primitive(&self, update: &mut impl Updater) -> Result<Primitive> {
+                Ok(Primitive::Array(vec![$(self.$idx.to_primitive(update)?),*]))
+            }
+        }
+        impl<$($t: Trace),*> Trace for ($($t,)*) {
+            fn trace(&self, cb: &mut impl FnMut(PlainRef)) {
+                $(self.$idx.trace(cb);)*
+            }
+        }
+        impl<$($t: DeepClone),*> DeepClone for ($($t,)*) {
+            fn deep_clone(&self, cloner: &mut impl Cloner) -> Result<Self> {
+                Ok(($(self.$idx.deep_clone(cloner)?,)*))
+            }
+        }
+    };
 }
+tuple_impls!(2; A a 0, B b 1);
+tuple_impls!(3; A a 0, B b 1, C c 2);
+tuple_impls!(4; A a 0, B b 1, C c 2, D d 3);
+tuple_impls!(5; A a 0, B b 1, C c 2, D d 3, E e 4);
+tuple_impls!(6; A a 0, B b 1, C c 2, D d 3, E e 4, F f 5);
 
 impl<T: DeepClone> DeepClone for Box<T> {
     fn deep_clone(&self, cloner: &mut impl Cloner) -> Result<Self> {
@@ -796,10 +1054,281 @@ macro_rules! deep_clone_simple {
         )*
     )
 }
-deep_clone_simple!(f32, i32, u32, bool, Name, (), Date, PdfString, Rectangle, u8, Arc<[u8]>, Vec<u16>);
+deep_clone_simple!(f32, f64, i32, i64, u64, u32, bool, Name, (), Date, PdfString, Rectangle, u8, u16, Arc<[u8]>);
 
-impl<A: DeepClone, B: DeepClone> DeepClone for (A, B) {
-    fn deep_clone(&self, cloner: &mut impl Cloner) -> Result<Self> {
-        Ok((self.0.deep_clone(cloner)?, self.1.deep_clone(cloner)?))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::FileOptions;
+    use crate::file::tests::document;
+    use crate::parser::{parse, ParseFlags};
+
+    fn roundtrip<T: Object + ObjectWrite>(input: &[u8]) -> (T, Vec<u8>) {
+        let p = parse(input, &NoResolve, ParseFlags::ANY).unwrap();
+        let t = T::from_primitive(p, &NoResolve).unwrap();
+        let mut out = vec![];
+        t.to_primitive(&mut NoUpdate).unwrap().serialize(&mut out).unwrap();
+        (t, out)
+    }
+
+    #[test]
+    fn f64_roundtrip() {
+        assert_eq!(roundtrip::<f64>(b"-1234.25"), (-1234.25, b"-1234.25".to_vec()));
+        assert_eq!(roundtrip::<f64>(b"0.5"), (0.5, b"0.5".to_vec()));
+        assert_eq!(roundtrip::<f64>(b"3.141592653589793"), (std::f64::consts::PI, b"3.141592653589793".to_vec()));
+        assert_eq!(roundtrip::<f64>(b"16777217.5"), (16777217.5, b"16777217.5".to_vec()));
+        assert_eq!(f64::from_primitive(Primitive::Integer(16777216), &NoResolve).unwrap(), 16777216.0);
+    }
+
+    #[test]
+    fn i64_u64() {
+        assert_eq!(i64::from_primitive(Primitive::Integer(-7), &NoResolve).unwrap(), -7);
+        assert_eq!(u64::from_primitive(Primitive::Integer(i32::MAX as i64), &NoResolve).unwrap(), i32::MAX as u64);
+        assert!(u64::from_primitive(Primitive::Integer(-1), &NoResolve).is_err());
+        // offsets in files above 2 GiB
+        let big = i32::MAX as i64 + 1;
+        assert_eq!(big.to_primitive(&mut NoUpdate).unwrap(), Primitive::Integer(big));
+        assert_eq!(roundtrip::<u64>(b"3000000001"), (3000000001, b"3000000001".to_vec()));
+        assert_eq!(roundtrip::<i64>(b"-3000000001"), (-3000000001, b"-3000000001".to_vec()));
+        assert!(i32::from_primitive(Primitive::Integer(big), &NoResolve).is_err());
+        assert!(u64::MAX.to_primitive(&mut NoUpdate).is_err());
+        assert_eq!(42u64.to_primitive(&mut NoUpdate).unwrap(), Primitive::Integer(42));
+    }
+
+    #[test]
+    fn option() {
+        assert_eq!(Option::<i32>::from_primitive(Primitive::Null, &NoResolve).unwrap(), None);
+        assert_eq!(Option::<i32>::from_primitive(Primitive::Integer(5), &NoResolve).unwrap(), Some(5));
+        assert_eq!(None::<i32>.to_primitive(&mut NoUpdate).unwrap(), Primitive::Null);
+        assert_eq!(Some(5i32).to_primitive(&mut NoUpdate).unwrap(), Primitive::Integer(5));
+    }
+
+    #[test]
+    fn tuples() {
+        let arr = |n: i64| Primitive::Array((0..n).map(Primitive::Integer).collect());
+        assert_eq!(<(i32, f32)>::from_primitive(arr(2), &NoResolve).unwrap(), (0, 1.0));
+        let t = <(i32, i32, i32, i32, i32, i32)>::from_primitive(arr(6), &NoResolve).unwrap();
+        assert_eq!(t, (0, 1, 2, 3, 4, 5));
+        assert_eq!(t.to_primitive(&mut NoUpdate).unwrap(), arr(6));
+        assert!(<(i32, i32, i32)>::from_primitive(arr(2), &NoResolve).is_err());
+        assert!(<(i32, i32, i32)>::from_primitive(arr(4), &NoResolve).is_err());
+    }
+
+    #[test]
+    fn fixed_arrays() {
+        let arr = |n: i64| Primitive::Array((0..n).map(Primitive::Integer).collect());
+        let m = <[f32; 6]>::from_primitive(arr(6), &NoResolve).unwrap();
+        assert_eq!(m, [0., 1., 2., 3., 4., 5.]);
+        assert_eq!(<[i32; 4]>::from_primitive(arr(4), &NoResolve).unwrap().to_primitive(&mut NoUpdate).unwrap(), arr(4));
+        assert!(matches!(
+            <[f32; 4]>::from_primitive(arr(3), &NoResolve),
+            Err(PdfError::ArrayLength { expected: 4, found: 3 })
+        ));
+        assert!(matches!(
+            <[f32; 4]>::from_primitive(Primitive::Integer(1), &NoResolve),
+            Err(PdfError::UnexpectedPrimitive { .. })
+        ));
+    }
+
+    #[test]
+    fn string_map() {
+        let mut dict = Dictionary::new();
+        dict.insert("F1", Primitive::Integer(1));
+        dict.insert("F2", Primitive::Integer(2));
+        let map = HashMap::<String, i32>::from_primitive(Primitive::Dictionary(dict.clone()), &NoResolve).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["F1"], 1);
+        assert_eq!(map["F2"], 2);
+
+        let p = map.to_primitive(&mut NoUpdate).unwrap().into_dictionary().unwrap();
+        assert_eq!(p.len(), 2);
+        assert_eq!(p.get("F2"), Some(&Primitive::Integer(2)));
+
+        dict.insert("F3", Primitive::Null);
+        assert!(HashMap::<String, i32>::from_primitive(Primitive::Dictionary(dict), &NoResolve).is_err());
+    }
+
+    #[test]
+    fn str_write() {
+        let mut out = vec![];
+        "a)(b\\c".to_primitive(&mut NoUpdate).unwrap().serialize(&mut out).unwrap();
+        assert_eq!(out, br"(a\)\(b\\c)");
+    }
+
+    #[test]
+    fn string_roundtrip() {
+        let (s, out) = roundtrip::<String>(br"(a\(b\))");
+        assert_eq!(s, "a(b)");
+        assert_eq!(out, br"(a(b))");
+        let (s, out) = roundtrip::<String>(br"(back\\slash \(\(nested\))");
+        assert_eq!(s, r"back\slash ((nested)");
+        assert_eq!(roundtrip::<String>(&out).0, s);
+    }
+
+    #[test]
+    fn utf16_string() {
+        let mut out = vec![];
+        "café".to_primitive(&mut NoUpdate).unwrap().serialize(&mut out).unwrap();
+        assert_eq!(out, b"<feff00630061006600e9>");
+        let (s, _) = roundtrip::<String>(&out);
+        assert_eq!(s, "café");
+
+        let mut out = vec![];
+        "a\u{1F600}".to_primitive(&mut NoUpdate).unwrap().serialize(&mut out).unwrap();
+        assert_eq!(out, b"<feff0061d83dde00>");
+        assert_eq!(roundtrip::<String>(&out).0, "a\u{1F600}");
+
+        let mut out = vec![];
+        "plain".to_primitive(&mut NoUpdate).unwrap().serialize(&mut out).unwrap();
+        assert_eq!(out, b"(plain)");
+    }
+
+    #[test]
+    fn name_vs_string() {
+        let (name, out) = roundtrip::<Name>(b"/Foo");
+        assert_eq!(name.as_str(), "Foo");
+        assert_eq!(out, b"/Foo");
+        let (s, out) = roundtrip::<String>(b"(Foo)");
+        assert_eq!(s, "Foo");
+        assert_eq!(out, b"(Foo)");
+
+        assert!(String::from_primitive(Primitive::Name("Foo".into()), &NoResolve).is_err());
+        assert!(Name::from_primitive(Primitive::String("Foo".into()), &NoResolve).is_err());
+    }
+
+    #[test]
+    fn plain_ref_eq_ord() {
+        let r = |id, gen| PlainRef { id, gen };
+        assert_eq!(r(3, 0), r(3, 0));
+        assert_ne!(r(3, 0), r(3, 1));
+        let mut refs = vec![r(4, 0), r(3, 1), r(3, 0), r(1, 2)];
+        refs.sort();
+        assert_eq!(refs, [r(1, 2), r(3, 0), r(3, 1), r(4, 0)]);
+
+        let mut map = HashMap::new();
+        map.insert(r(3, 0), "a");
+        map.insert(r(3, 1), "b");
+        assert_eq!(map.len(), 2);
+        assert_eq!(map[&r(3, 0)], "a");
+    }
+
+    #[test]
+    fn ref_hash_set() {
+        use std::collections::HashSet;
+        use crate::object::types::Page;
+
+        let a = Ref::<Page>::new(PlainRef { id: 7, gen: 0 });
+        let b = Ref::<Page>::from_id(7);
+        assert_eq!(a, b);
+        let set: HashSet<_> = vec![a, b, Ref::from_id(8)].into_iter().collect();
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&Ref::from_id(7)));
+    }
+
+    #[test]
+    fn small_unsigned() {
+        assert_eq!(u8::from_primitive(Primitive::Integer(255), &NoResolve).unwrap(), 255);
+        assert!(u8::from_primitive(Primitive::Integer(256), &NoResolve).is_err());
+        assert!(u8::from_primitive(Primitive::Integer(-1), &NoResolve).is_err());
+        assert_eq!(u16::from_primitive(Primitive::Integer(65535), &NoResolve).unwrap(), 65535);
+        assert!(u16::from_primitive(Primitive::Integer(65536), &NoResolve).is_err());
+        assert_eq!(u32::from_primitive(Primitive::Integer(i32::MAX as i64), &NoResolve).unwrap(), i32::MAX as u32);
+        assert!(u32::from_primitive(Primitive::Integer(-1), &NoResolve).is_err());
+    }
+
+    #[test]
+    fn to_pdf_string() {
+        assert_eq!(42i32.to_pdf_string().unwrap(), "42");
+        assert_eq!(Some(Name::from("A B")).to_pdf_string().unwrap(), "/A#20B");
+        assert_eq!(vec![1.5f32, 2.].to_pdf_bytes().unwrap(), b"[1.5 2]");
+        // binary strings are written in hex
+        assert_eq!(PdfString::new(vec![0xff].into()).to_pdf_string().unwrap(), "<ff>");
+    }
+
+    #[test]
+    fn char() {
+        assert_eq!(char::from_primitive(PdfString::from("x").into(), &NoResolve).unwrap(), 'x');
+        assert_eq!(char::from_primitive(Primitive::name("S"), &NoResolve).unwrap(), 'S');
+        let (c, out) = roundtrip::<char>(br"(\()");
+        assert_eq!(c, '(');
+        assert_eq!(out, br"(\()");
+        assert_eq!(char::from_primitive('é'.to_primitive(&mut NoUpdate).unwrap(), &NoResolve).unwrap(), 'é');
+
+        let err = char::from_primitive(PdfString::from("ab").into(), &NoResolve).unwrap_err();
+        assert!(err.to_string().contains("found 2 characters"), "{}", err);
+        let err = char::from_primitive(PdfString::from("").into(), &NoResolve).unwrap_err();
+        assert!(err.to_string().contains("found 0 characters"), "{}", err);
+        assert!(char::from_primitive(Primitive::Integer(1), &NoResolve).is_err());
+    }
+
+    #[test]
+    fn conversion_errors() {
+        assert!(matches!(
+            u32::from_primitive(Primitive::name("Count"), &NoResolve),
+            Err(PdfError::UnexpectedPrimitive { expected: "Integer", found: "Name" })
+        ));
+        assert!(matches!(
+            bool::from_primitive(Primitive::Integer(1), &NoResolve),
+            Err(PdfError::UnexpectedPrimitive { expected: "Boolean", found: "Integer" })
+        ));
+        assert!(matches!(
+            u8::from_primitive(Primitive::Integer(300), &NoResolve),
+            Err(PdfError::IntegerOutOfRange { value: 300, typ: "u8" })
+        ));
+        assert!(matches!(
+            usize::from_primitive(Primitive::Integer(-2), &NoResolve),
+            Err(PdfError::IntegerOutOfRange { value: -2, typ: "usize" })
+        ));
+        let arr = Primitive::Array(vec![Primitive::Integer(0); 3]);
+        assert!(matches!(
+            Rectangle::from_primitive(arr, &NoResolve),
+            Err(PdfError::ArrayLength { expected: 4, found: 3 })
+        ));
+        assert!(matches!(
+            PageLabel::from_primitive(Primitive::Integer(1), &NoResolve),
+            Err(PdfError::UnexpectedPrimitive { expected: "Dictionary", .. })
+        ));
+    }
+
+    #[test]
+    fn ref_cast() {
+        let file = FileOptions::uncached().load(document("", "", &[], &[])).unwrap();
+        let resolver = file.resolver();
+        let root = Ref::<Primitive>::from_id(1);
+        assert_eq!(root.cast::<Catalog>().get_inner(), root.get_inner());
+        assert!(root.try_cast::<Catalog>(&resolver).is_ok());
+
+        let pages = Ref::<Primitive>::from_id(2);
+        assert!(matches!(
+            pages.try_cast::<Catalog>(&resolver),
+            Err(PdfError::KeyValueMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn maybe_ref() {
+        let data = document("", "", &[], &["<< /Direct << /Foo 1 >> /Indirect 4 0 R >>", "<< /Foo 2 >>"]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let mut dict = resolver.resolve(PlainRef { id: 3, gen: 0 }).unwrap().into_dictionary().unwrap();
+
+        let direct = MaybeRef::<Dictionary>::from_primitive(dict.remove("Direct").unwrap(), &resolver).unwrap();
+        assert!(direct.as_ref().is_none());
+        assert_eq!(direct["Foo"], Primitive::Integer(1));
+        assert!(matches!(direct.to_primitive(&mut NoUpdate).unwrap(), Primitive::Dictionary(_)));
+        assert_eq!(direct.resolve(&NoResolve).unwrap()["Foo"], Primitive::Integer(1));
+
+        let indirect = MaybeRef::<Dictionary>::from_primitive(dict.remove("Indirect").unwrap(), &resolver).unwrap();
+        assert_eq!(indirect.as_ref().map(|r| r.get_inner().id), Some(4));
+        assert_eq!(indirect["Foo"], Primitive::Integer(2));
+        assert_eq!(indirect.to_primitive(&mut NoUpdate).unwrap(), Primitive::Reference(PlainRef { id: 4, gen: 0 }));
+        assert_eq!(indirect.resolve(&resolver).unwrap()["Foo"], Primitive::Integer(2));
+        assert_eq!(indirect.resolve(&NoResolve).unwrap()["Foo"], Primitive::Integer(2));
+
+        // like a value from `Updater::create`, which the file doesn't have
+        let mut created = Dictionary::new();
+        created.insert("Foo", 3);
+        let created = MaybeRef::Indirect(RcRef::new(PlainRef { id: 99, gen: 0 }, Shared::new(created)));
+        assert_eq!(created.resolve(&resolver).unwrap()["Foo"], Primitive::Integer(3));
     }
 }
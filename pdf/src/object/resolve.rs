@@ -0,0 +1,389 @@
+//! Wrappers around a `Resolve` implementation.
+//!
+//! All of them are `Send + Sync` if the inner resolver is, so they can be shared between threads.
+
+use super::*;
+use std::cell::RefCell;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A `Resolve` that can be shared between threads, e.g. behind an `Arc`.
+pub trait SyncResolve: Resolve + Send + Sync {}
+impl<R: Resolve + Send + Sync> SyncResolve for R {}
+
+fn primitive_flag(p: &Primitive) -> ParseFlags {
+    match *p {
+        Primitive::Null => ParseFlags::NULL,
+        Primitive::Integer(_) => ParseFlags::INTEGER,
+        Primitive::Number(_) => ParseFlags::NUMBER,
+        Primitive::Boolean(_) => ParseFlags::BOOL,
+        Primitive::String(_) => ParseFlags::STRING,
+        Primitive::Stream(_) => ParseFlags::STREAM,
+        Primitive::Dictionary(_) => ParseFlags::DICT,
+        Primitive::Array(_) => ParseFlags::ARRAY,
+        Primitive::Reference(_) => ParseFlags::REF,
+        Primitive::Name(_) => ParseFlags::NAME,
+    }
+}
+
+thread_local! {
+    static PATHS: RefCell<HashMap<usize, Vec<PlainRef>>> = RefCell::new(HashMap::new());
+}
+
+/// The references a resolver is currently resolving, for each thread.
+///
+/// The paths live in thread local storage, so a resolver that is shared between threads
+/// doesn't have to lock them. Every `ResolvePath` has its own id to tell them apart.
+pub(crate) struct ResolvePath {
+    id: usize,
+}
+impl ResolvePath {
+    pub fn new() -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        ResolvePath { id: NEXT_ID.fetch_add(1, Ordering::Relaxed) }
+    }
+    /// Calls `f` with the path of the current thread.
+    pub fn with<T>(&self, f: impl FnOnce(&mut Vec<PlainRef>) -> T) -> T {
+        PATHS.with(|paths| {
+            let mut paths = paths.borrow_mut();
+            let path = paths.entry(self.id).or_default();
+            let t = f(path);
+            if path.is_empty() {
+                paths.remove(&self.id);
+            }
+            t
+        })
+    }
+}
+
+/// Remembers every resolved primitive, so shared objects are only parsed once.
+///
+/// The cache is never evicted on its own and keeps a copy of every object that was
+/// resolved through it. Call `clear` to release the memory.
+pub struct CachedResolve<R> {
+    inner: R,
+    cache: Mutex<HashMap<PlainRef, Primitive>>,
+}
+impl<R: Resolve> CachedResolve<R> {
+    pub fn new(inner: R) -> Self {
+        CachedResolve {
+            inner,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+    /// Number of cached objects.
+    pub fn len(&self) -> usize {
+        self.cache.lock().unwrap().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.cache.lock().unwrap().is_empty()
+    }
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+}
+impl<R: Resolve> Resolve for CachedResolve<R> {
+    fn resolve_flags(&self, r: PlainRef, flags: ParseFlags, depth: usize) -> Result<Primitive> {
+        if let Some(p) = self.cache.lock().unwrap().get(&r) {
+            let found = primitive_flag(p);
+            if !flags.contains(found) {
+                return Err(PdfError::PrimitiveNotAllowed { allowed: flags, found });
+            }
+            return Ok(p.clone());
+        }
+        // not locked while resolving, the inner resolver may call back into this one
+        let p = self.inner.resolve_flags(r, flags, depth)?;
+        self.cache.lock().unwrap().insert(r, p.clone());
+        Ok(p)
+    }
+    /// Typed objects are not cached here; `Storage` already shares them.
+    fn get<T: Object+DataSize>(&self, r: Ref<T>) -> Result<RcRef<T>> {
+        self.inner.get(r)
+    }
+    fn options(&self) -> &ParseOptions {
+        self.inner.options()
+    }
+    fn stream_data(&self, id: PlainRef, range: Range<usize>) -> Result<Arc<[u8]>> {
+        self.inner.stream_data(id, range)
+    }
+    fn get_data_or_decode(&self, id: PlainRef, range: Range<usize>, filters: &[StreamFilter]) -> Result<Arc<[u8]>> {
+        self.inner.get_data_or_decode(id, range, filters)
+    }
+}
+
+/// Limits how deeply references may be nested, to protect against reference chains
+/// that never end.
+///
+/// If an object resolves to another reference, that reference is followed as well,
+/// and counts towards the limit. Calls made back into the wrapper while resolving,
+/// e.g. through `Object::from_primitive`, count as nested too.
+/// The depth is counted for each thread separately.
+pub struct DepthLimitResolve<R> {
+    inner: R,
+    limit: usize,
+    path: ResolvePath,
+}
+impl<R: Resolve> DepthLimitResolve<R> {
+    pub const DEFAULT_LIMIT: usize = 128;
+
+    pub fn new(inner: R) -> Self {
+        Self::with_limit(inner, Self::DEFAULT_LIMIT)
+    }
+    pub fn with_limit(inner: R, limit: usize) -> Self {
+        DepthLimitResolve {
+            inner,
+            limit,
+            path: ResolvePath::new(),
+        }
+    }
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+    fn nested<T>(&self, r: PlainRef, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.path.with(|path| {
+            if path.len() >= self.limit {
+                return Err(PdfError::RecursionLimit { limit: self.limit });
+            }
+            path.push(r);
+            Ok(())
+        })?;
+        let res = f();
+        self.path.with(|path| path.pop());
+        res
+    }
+}
+impl<R: Resolve> Resolve for DepthLimitResolve<R> {
+    fn resolve_flags(&self, r: PlainRef, flags: ParseFlags, depth: usize) -> Result<Primitive> {
+        self.nested(r, || match self.inner.resolve_flags(r, flags, depth)? {
+            Primitive::Reference(next) => self.resolve_flags(next, flags, depth),
+            p => Ok(p)
+        })
+    }
+    fn get<T: Object+DataSize>(&self, r: Ref<T>) -> Result<RcRef<T>> {
+        self.nested(r.get_inner(), || self.inner.get(r))
+    }
+    fn options(&self) -> &ParseOptions {
+        self.inner.options()
+    }
+    fn stream_data(&self, id: PlainRef, range: Range<usize>) -> Result<Arc<[u8]>> {
+        self.inner.stream_data(id, range)
+    }
+    fn get_data_or_decode(&self, id: PlainRef, range: Range<usize>, filters: &[StreamFilter]) -> Result<Arc<[u8]>> {
+        self.inner.get_data_or_decode(id, range, filters)
+    }
+}
+
+/// Detects reference cycles like `A -> B -> A`.
+///
+/// Tracks the references that are currently being resolved. A reference that is encountered
+/// again while it is still on that path results in `PdfError::ReferenceCycle`.
+/// Like `DepthLimitResolve`, objects that resolve to a reference are followed,
+/// and every thread has its own path.
+pub struct CycleDetectResolve<R> {
+    inner: R,
+    path: ResolvePath,
+}
+impl<R: Resolve> CycleDetectResolve<R> {
+    pub fn new(inner: R) -> Self {
+        CycleDetectResolve {
+            inner,
+            path: ResolvePath::new(),
+        }
+    }
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+    fn visit<T>(&self, r: PlainRef, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.path.with(|path| {
+            if path.contains(&r) {
+                return Err(PdfError::ReferenceCycle { id: r.id });
+            }
+            path.push(r);
+            Ok(())
+        })?;
+        let res = f();
+        self.path.with(|path| path.pop());
+        res
+    }
+}
+impl<R: Resolve> Resolve for CycleDetectResolve<R> {
+    fn resolve_flags(&self, r: PlainRef, flags: ParseFlags, depth: usize) -> Result<Primitive> {
+        self.visit(r, || match self.inner.resolve_flags(r, flags, depth)? {
+            Primitive::Reference(next) => self.resolve_flags(next, flags, depth),
+            p => Ok(p)
+        })
+    }
+    fn get<T: Object+DataSize>(&self, r: Ref<T>) -> Result<RcRef<T>> {
+        self.visit(r.get_inner(), || self.inner.get(r))
+    }
+    fn options(&self) -> &ParseOptions {
+        self.inner.options()
+    }
+    fn stream_data(&self, id: PlainRef, range: Range<usize>) -> Result<Arc<[u8]>> {
+        self.inner.stream_data(id, range)
+    }
+    fn get_data_or_decode(&self, id: PlainRef, range: Range<usize>, filters: &[StreamFilter]) -> Result<Arc<[u8]>> {
+        self.inner.get_data_or_decode(id, range, filters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Resolves object `n` to `Integer(n)` and counts the calls.
+    struct Counting(AtomicUsize);
+    impl Resolve for Counting {
+        fn resolve_flags(&self, r: PlainRef, _: ParseFlags, _: usize) -> Result<Primitive> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(Primitive::Integer(r.id as i64))
+        }
+        fn get<T: Object+DataSize>(&self, _: Ref<T>) -> Result<RcRef<T>> {
+            Err(PdfError::Reference)
+        }
+        fn options(&self) -> &ParseOptions {
+            static STRICT: ParseOptions = ParseOptions::strict();
+            &STRICT
+        }
+        fn stream_data(&self, _: PlainRef, _: Range<usize>) -> Result<Arc<[u8]>> {
+            Err(PdfError::Reference)
+        }
+        fn get_data_or_decode(&self, _: PlainRef, _: Range<usize>, _: &[StreamFilter]) -> Result<Arc<[u8]>> {
+            Err(PdfError::Reference)
+        }
+    }
+
+    /// Resolves objects from a fixed table.
+    struct Table(HashMap<ObjNr, Primitive>);
+    impl Resolve for Table {
+        fn resolve_flags(&self, r: PlainRef, _: ParseFlags, _: usize) -> Result<Primitive> {
+            self.0.get(&r.id).cloned().ok_or(PdfError::NullRef { obj_nr: r.id })
+        }
+        fn get<T: Object+DataSize>(&self, _: Ref<T>) -> Result<RcRef<T>> {
+            Err(PdfError::Reference)
+        }
+        fn options(&self) -> &ParseOptions {
+            static STRICT: ParseOptions = ParseOptions::strict();
+            &STRICT
+        }
+        fn stream_data(&self, _: PlainRef, _: Range<usize>) -> Result<Arc<[u8]>> {
+            Err(PdfError::Reference)
+        }
+        fn get_data_or_decode(&self, _: PlainRef, _: Range<usize>, _: &[StreamFilter]) -> Result<Arc<[u8]>> {
+            Err(PdfError::Reference)
+        }
+    }
+    fn plain(id: ObjNr) -> PlainRef {
+        PlainRef { id, gen: 0 }
+    }
+
+    #[test]
+    fn lazy() {
+        let counting = Counting(AtomicUsize::new(0));
+        let lazy = Lazy::<i32>::from_primitive(Primitive::Reference(plain(7)), &counting).unwrap();
+        assert_eq!(counting.0.load(Ordering::SeqCst), 0);
+        assert_eq!(*lazy.get(&counting).unwrap(), 7);
+        assert_eq!(*lazy.get(&counting).unwrap(), 7);
+        assert_eq!(counting.0.load(Ordering::SeqCst), 1);
+        assert_eq!(lazy.to_primitive(&mut NoUpdate).unwrap(), Primitive::Reference(plain(7)));
+    }
+
+    #[test]
+    fn depth_limit() {
+        let table = Table(vec![
+            (1, Primitive::Reference(plain(1))),
+            (2, Primitive::Reference(plain(3))),
+            (3, Primitive::Integer(3)),
+        ].into_iter().collect());
+        let r = DepthLimitResolve::new(table);
+        assert!(matches!(r.resolve(plain(1)), Err(PdfError::RecursionLimit { limit: 128 })));
+        assert!(matches!(f32::from_primitive(Primitive::Reference(plain(1)), &r), Err(PdfError::RecursionLimit { .. })));
+        assert_eq!(r.resolve(plain(2)).unwrap(), Primitive::Integer(3));
+
+        let r = DepthLimitResolve::with_limit(r.inner, 1);
+        assert!(matches!(r.resolve(plain(2)), Err(PdfError::RecursionLimit { limit: 1 })));
+        assert_eq!(r.resolve(plain(3)).unwrap(), Primitive::Integer(3));
+    }
+
+    #[test]
+    fn cycle_detect() {
+        let table = Table(vec![
+            (1, Primitive::Reference(plain(2))),
+            (2, Primitive::Reference(plain(1))),
+            (3, Primitive::Reference(plain(4))),
+            (4, Primitive::Integer(4)),
+        ].into_iter().collect());
+        let r = CycleDetectResolve::new(table);
+        assert!(matches!(r.resolve(plain(1)), Err(PdfError::ReferenceCycle { id: 1 })));
+        assert!(matches!(r.resolve(plain(2)), Err(PdfError::ReferenceCycle { id: 2 })));
+        // the path is unwound after an error
+        assert_eq!(r.resolve(plain(3)).unwrap(), Primitive::Integer(4));
+        assert_eq!(r.resolve(plain(3)).unwrap(), Primitive::Integer(4));
+    }
+
+    #[test]
+    fn typed_ref() {
+        let table = Table(vec![
+            (1, Primitive::Integer(42)),
+            (2, Primitive::Reference(plain(1))),
+            (3, Primitive::name("Pages")),
+        ].into_iter().collect());
+        assert_eq!(Ref::<i32>::from_id(1).resolve(&table).unwrap(), 42);
+        assert_eq!(Ref::<i32>::from_id(2).resolve(&table).unwrap(), 42);
+        assert!(matches!(Ref::<i32>::from_id(3).resolve(&table), Err(PdfError::UnexpectedPrimitive { .. })));
+        assert!(matches!(Ref::<i32>::from_id(4).resolve(&table), Err(PdfError::NullRef { obj_nr: 4 })));
+    }
+
+    #[test]
+    fn cached() {
+        let r = CachedResolve::new(Counting(AtomicUsize::new(0)));
+        let a = PlainRef { id: 1, gen: 0 };
+        assert_eq!(r.resolve(a).unwrap(), Primitive::Integer(1));
+        assert_eq!(r.resolve(a).unwrap(), Primitive::Integer(1));
+        assert_eq!(r.inner().0.load(Ordering::SeqCst), 1);
+        assert!(r.resolve_flags(a, ParseFlags::DICT, 16).is_err());
+
+        r.resolve(PlainRef { id: 2, gen: 0 }).unwrap();
+        assert_eq!(r.len(), 2);
+        r.clear();
+        assert!(r.is_empty());
+        r.resolve(a).unwrap();
+        assert_eq!(r.inner().0.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn threads() {
+        fn assert_sync<R: SyncResolve>(_: &R) {}
+
+        let r = Arc::new(CachedResolve::new(Counting(AtomicUsize::new(0))));
+        assert_sync(&*r);
+        let handles: Vec<_> = (0 .. 4).map(|_| {
+            let r = r.clone();
+            thread::spawn(move || {
+                for id in 1 ..= 10 {
+                    assert_eq!(r.resolve(plain(id)).unwrap(), Primitive::Integer(id as i64));
+                }
+            })
+        }).collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(r.len(), 10);
+        // threads may race to resolve the same object, but never more than once each
+        let calls = r.inner().0.load(Ordering::SeqCst);
+        assert!((10 ..= 40).contains(&calls), "{}", calls);
+
+        let table = Table(vec![(1, Primitive::Reference(plain(1)))].into_iter().collect());
+        let r = Arc::new(DepthLimitResolve::new(CycleDetectResolve::new(table)));
+        let handles: Vec<_> = (0 .. 4).map(|_| {
+            let r = r.clone();
+            thread::spawn(move || matches!(r.resolve(plain(1)), Err(PdfError::ReferenceCycle { id: 1 })))
+        }).collect();
+        assert!(handles.into_iter().all(|h| h.join().unwrap()));
+    }
+}
@@ -4,7 +4,7 @@ use crate as pdf;
 use crate::object::*;
 use crate::primitive::*;
 use crate::error::*;
-use crate::parser::Lexer;
+use crate::parser::{Lexer, parse, ParseFlags};
 use crate::enc::{StreamFilter, decode};
 
 use std::ops::{Deref, Range};
@@ -22,16 +22,17 @@ datasize::non_dynamic_const_heap_size!(StreamData, std::mem::size_of::<StreamDat
 pub struct Stream<I> {
     pub info: StreamInfo<I>,
     pub (crate) inner_data: StreamData,
+    pub (crate) keep_uncompressed: bool,
 }
 impl<I: Object> Stream<I> {
     pub fn from_stream(s: PdfStream, resolve: &impl Resolve) -> Result<Self> {
-        let PdfStream {info, inner} = s;
+        let PdfStream {info, inner, keep_uncompressed} = s;
         let info = StreamInfo::<I>::from_primitive(Primitive::Dictionary (info), resolve)?;
         let inner_data = match inner {
             StreamInner::InFile { id, file_range } => StreamData::Original(file_range, id),
             StreamInner::Pending { data } => StreamData::Generated(data)
         };
-        Ok(Stream { info, inner_data })
+        Ok(Stream { info, inner_data, keep_uncompressed })
     }
 
     /// the data is not compressed. the specified filters are to be applied when compressing the data
@@ -44,6 +45,7 @@ impl<I: Object> Stream<I> {
                 info: i
             },
             inner_data: StreamData::Generated(data.into()),
+            keep_uncompressed: false,
         }
     }
     pub fn new(i: I, data: impl Into<Arc<[u8]>>) -> Stream<I> {
@@ -55,6 +57,7 @@ impl<I: Object> Stream<I> {
                 info: i
             },
             inner_data: StreamData::Generated(data.into()),
+            keep_uncompressed: false,
         }
     }
     /// the data is already compressed with the specified filters
@@ -67,8 +70,14 @@ impl<I: Object> Stream<I> {
                 info: i
             },
             inner_data: StreamData::Generated(data.into()),
+            keep_uncompressed: false,
         }
     }
+    /// Excludes the stream from `PdfWriter::compress_streams`.
+    pub fn keep_uncompressed(mut self) -> Self {
+        self.keep_uncompressed = true;
+        self
+    }
 
     pub fn data(&self, resolve: &impl Resolve) -> Result<Arc<[u8]>> {
         match self.inner_data {
@@ -165,7 +174,7 @@ impl<I: ObjectWrite> Stream<I> {
             }
         };
 
-        Ok(PdfStream { info, inner })
+        Ok(PdfStream { info, inner, keep_uncompressed: self.keep_uncompressed })
     }
 }
 impl<I: ObjectWrite> ObjectWrite for Stream<I> {
@@ -182,6 +191,7 @@ impl<I: DeepClone> DeepClone for Stream<I> {
         Ok(Stream {
             info: self.info.deep_clone(cloner)?,
             inner_data: StreamData::Generated(data),
+            keep_uncompressed: self.keep_uncompressed,
         })
     }
 }
@@ -327,8 +337,10 @@ pub struct ObjStmInfo {
 
 #[derive(DataSize)]
 pub struct ObjectStream {
-    /// Byte offset of each object. Index is the object number.
+    /// Byte offset of each object, relative to `/First`.
     offsets:    Vec<usize>,
+    /// Object number of each object.
+    obj_nrs:    Vec<ObjNr>,
     /// The object number of this object.
     _id:         ObjNr,
     
@@ -340,19 +352,22 @@ impl Object for ObjectStream {
         let stream: Stream<ObjStmInfo> = Stream::from_primitive(p, resolve)?;
 
         let mut offsets = Vec::new();
+        let mut obj_nrs = Vec::new();
         {
             debug!("parsing stream");
             let data = stream.data(resolve)?;
             let mut lexer = Lexer::new(&data);
             for _ in 0..(stream.info.num_objects as ObjNr) {
-                let _obj_nr = lexer.next()?.to::<ObjNr>()?;
+                let obj_nr = lexer.next()?.to::<ObjNr>()?;
                 let offset = lexer.next()?.to::<usize>()?;
+                obj_nrs.push(obj_nr);
                 offsets.push(offset);
             }
         }
 
         Ok(ObjectStream {
             offsets,
+            obj_nrs,
             _id: 0, // TODO
             inner: stream
         })
@@ -374,6 +389,23 @@ impl ObjectStream {
 
         Ok((data, start..end))
     }
+    /// Parse the object at `index`.
+    pub fn get_object(&self, index: usize, resolve: &impl Resolve) -> Result<Primitive> {
+        let (data, range) = self.get_object_slice(index, resolve)?;
+        let slice = data.get(range.clone()).ok_or_else(|| other!("invalid range {:?}, but only have {} bytes", range, data.len()))?;
+        parse(slice, resolve, ParseFlags::ANY)
+    }
+    /// Index of the object with number `obj_nr`, if it is contained in this stream.
+    pub fn index_of(&self, obj_nr: ObjNr) -> Option<usize> {
+        self.obj_nrs.iter().position(|&n| n == obj_nr)
+    }
+    /// Parse the object with number `obj_nr`.
+    pub fn get_object_by_nr(&self, obj_nr: ObjNr, resolve: &impl Resolve) -> Result<Primitive> {
+        match self.index_of(obj_nr) {
+            Some(index) => self.get_object(index, resolve),
+            None => Err(PdfError::NullRef { obj_nr })
+        }
+    }
     /// Returns the number of contained objects
     pub fn n_objects(&self) -> usize {
         self.offsets.len()
@@ -382,3 +414,58 @@ impl ObjectStream {
         self.inner.data(resolve)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(filter: Primitive, params: Primitive, data: &[u8]) -> Stream<()> {
+        let mut info = Dictionary::new();
+        info.insert("Length", data.len() as i64);
+        info.insert("Filter", filter);
+        info.insert("DecodeParms", params);
+        let s = PdfStream::new(info, data);
+        Stream::from_primitive(Primitive::Stream(s), &NoResolve).unwrap()
+    }
+
+    #[test]
+    fn filter_chain() {
+        let name = |s: &str| Primitive::Name(s.into());
+        let hex_zlib = b"789ccb48cdc9c957c8402701680308b1>";
+
+        let s = stream(
+            Primitive::Array(vec![name("ASCIIHexDecode"), name("FlateDecode")]),
+            Primitive::Array(vec![Primitive::Null, Primitive::Dictionary(Dictionary::new())]),
+            hex_zlib
+        );
+        assert_eq!(s.info.filters.len(), 2);
+        assert_eq!(&*s.data(&NoResolve).unwrap(), b"hello hello hello hello");
+
+        // a single filter does not need to be wrapped in an array
+        let s = stream(name("ASCIIHexDecode"), Primitive::Null, b"68656c6c6f>");
+        assert_eq!(&*s.data(&NoResolve).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn object_stream() {
+        let data = b"11 0 12 14 << /A true >> [1 (two)]";
+        let mut info = Dictionary::new();
+        info.insert("Type", Primitive::Name("ObjStm".into()));
+        info.insert("Length", data.len() as i64);
+        info.insert("N", 2);
+        info.insert("First", 10);
+        let s = PdfStream::new(info, &data[..]);
+        let objs = ObjectStream::from_primitive(Primitive::Stream(s), &NoResolve).unwrap();
+
+        assert_eq!(objs.n_objects(), 2);
+        assert_eq!(objs.index_of(12), Some(1));
+        let dict = objs.get_object(0, &NoResolve).unwrap().into_dictionary().unwrap();
+        assert_eq!(dict.get("A"), Some(&Primitive::Boolean(true)));
+        assert_eq!(
+            objs.get_object_by_nr(12, &NoResolve).unwrap(),
+            Primitive::Array(vec![Primitive::Integer(1), Primitive::String("two".into())])
+        );
+        assert!(matches!(objs.get_object_by_nr(13, &NoResolve), Err(PdfError::NullRef { obj_nr: 13 })));
+        assert!(objs.get_object(2, &NoResolve).is_err());
+    }
+}
@@ -1,15 +1,19 @@
 //! Models of PDF types
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use datasize::DataSize;
 
 use crate as pdf;
 use crate::content::deep_clone_op;
 use crate::object::*;
 use crate::error::*;
-use crate::content::{Content, FormXObject, Matrix, parse_ops, serialize_ops, Op};
-use crate::font::Font;
+use crate::content::{Content, FormXObject, Matrix, parse_ops, serialize_ops, Op, Point};
+use crate::font::{Font, FontType};
+use crate::standard_fonts::StandardFont;
+use crate::encoding::{BaseEncoding, Encoding};
 use crate::enc::StreamFilter;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
 /// Node in a page tree - type is either `Page` or `PageTree`
 #[derive(Debug, Clone, DataSize)]
@@ -104,6 +108,9 @@ impl PagesRc {
     pub fn create(tree: PageTree, update: &mut impl Updater) -> Result<PagesRc> {
         Ok(PagesRc(update.create(PagesNode::Tree(tree))?))
     }
+    pub fn get_ref(&self) -> Ref<PagesNode> {
+        self.0.get_ref()
+    }
 }
 impl Object for PagesRc {
     fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<PagesRc> {
@@ -164,13 +171,117 @@ pub struct Catalog {
 // SpiderInfo: dict
 // OutputIntents: array
 // PieceInfo: dict
-// OCProperties: dict
+    #[pdf(key="OCProperties")]
+    pub optional_content: Option<OptionalContentProperties>,
+
 // Perms: dict
 // Legal: dict
 // Requirements: array
 // Collection: dict
 // NeedsRendering: bool
 }
+impl Catalog {
+    /// The label of the page with index `page_nr`, from `/PageLabels`.
+    ///
+    /// Without page labels, or before the first range, this is the page number.
+    pub fn page_label(&self, page_nr: u32, resolve: &impl Resolve) -> Result<String> {
+        let mut label = None;
+        if let Some(ref labels) = self.page_labels {
+            t!(labels.walk(resolve, &mut |start, range| {
+                let start = start.max(0) as u32;
+                if start <= page_nr && label.as_ref().is_none_or(|&(s, _)| s <= start) {
+                    label = Some((start, range.label((page_nr - start) as usize)));
+                }
+            }));
+        }
+        Ok(match label {
+            Some((_, label)) => label,
+            None => (page_nr + 1).to_string(),
+        })
+    }
+    /// Looks up a named destination, first in the `/Dests` name tree of `/Names`, then in
+    /// the PDF 1.1 `/Dests` dictionary.
+    pub fn resolve_dest(&self, name: &[u8], resolve: &impl Resolve) -> Result<Option<Dest>> {
+        if let Some(tree) = self.names.as_ref().and_then(|names| names.dests.as_ref()) {
+            if let Some(dest) = t!(tree.get(name, resolve)) {
+                return Ok(dest);
+            }
+        }
+        if let Some(ref dests) = self.dests {
+            let key = std::str::from_utf8(name).ok();
+            if let Some(p) = key.and_then(|key| dests.get(key)) {
+                return Ok(Some(t!(Dest::from_primitive(p.clone(), resolve))));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The layers of a document, `/OCProperties` in the catalog.
+#[derive(Object, ObjectWrite, Debug, Clone, DataSize)]
+pub struct OptionalContentProperties {
+    /// All optional content groups of the document
+    #[pdf(key="OCGs")]
+    pub groups: Vec<Ref<OptionalContentGroup>>,
+
+    /// The default configuration
+    #[pdf(key="D")]
+    pub default_config: OptionalContentConfig,
+
+    #[pdf(key="Configs")]
+    pub configs: Vec<OptionalContentConfig>,
+}
+impl OptionalContentProperties {
+    /// Whether `group` is visible in the default configuration.
+    pub fn is_visible(&self, group: Ref<OptionalContentGroup>) -> bool {
+        self.default_config.is_visible(group)
+    }
+}
+
+#[derive(Object, ObjectWrite, Debug, Clone, DataSize)]
+#[pdf(Type="OCG")]
+pub struct OptionalContentGroup {
+    #[pdf(key="Name")]
+    pub name: PdfString,
+
+    #[pdf(key="Intent")]
+    pub intent: Option<Primitive>,
+
+    #[pdf(key="Usage")]
+    pub usage: Option<Dictionary>,
+}
+
+#[derive(Object, ObjectWrite, Debug, Clone, DataSize)]
+pub struct OptionalContentConfig {
+    #[pdf(key="Name")]
+    pub name: Option<PdfString>,
+
+    /// `ON`, `OFF` or `Unchanged`, the state of the groups not in `/ON` or `/OFF`
+    #[pdf(key="BaseState")]
+    pub base_state: Option<Name>,
+
+    #[pdf(key="ON")]
+    pub on: Vec<Ref<OptionalContentGroup>>,
+
+    #[pdf(key="OFF")]
+    pub off: Vec<Ref<OptionalContentGroup>>,
+
+    /// How the groups are presented to the user
+    #[pdf(key="Order")]
+    pub order: Option<Primitive>,
+}
+impl OptionalContentConfig {
+    /// Whether `group` is visible in this configuration.
+    pub fn is_visible(&self, group: Ref<OptionalContentGroup>) -> bool {
+        if self.off.contains(&group) {
+            false
+        } else if self.on.contains(&group) {
+            true
+        } else {
+            self.base_state.as_ref().is_none_or(|state| state != "OFF")
+        }
+    }
+}
 
 #[derive(Object, ObjectWrite, Debug, Default, Clone, DataSize)]
 #[pdf(Type = "Pages?")]
@@ -197,6 +308,24 @@ impl PageTree {
     pub fn page(&self, resolve: &impl Resolve, page_nr: u32) -> Result<PageRc> {
         self.page_limited(resolve, page_nr, 16)
     }
+    /// Iterates over the pages below this node in document order.
+    ///
+    /// Nodes are only resolved once the iterator reaches them.
+    pub fn pages<R: Resolve>(&self, resolve: R) -> PageIter<R> {
+        PageIter {
+            resolve,
+            stack: vec![(self.kids.clone(), 0)],
+            path: vec![],
+        }
+    }
+    /// Like `pages`, but the nodes are resolved in parallel on the rayon thread pool.
+    ///
+    /// The pages are in document order. `resolve` is used by all threads at once, so its
+    /// cache should be shared.
+    #[cfg(feature = "rayon")]
+    pub fn par_pages<'a, R: Resolve + Sync>(&'a self, resolve: &'a R) -> impl ParallelIterator<Item=Result<PageRc>> + 'a {
+        self.kids.par_iter().flat_map_iter(move |&kid| par_subtree(kid, resolve, &[]))
+    }
     fn page_limited(&self, resolve: &impl Resolve, page_nr: u32, depth: usize) -> Result<PageRc> {
         if depth == 0 {
             bail!("page tree depth exeeded");
@@ -261,6 +390,72 @@ impl PageTree {
 }
 impl SubType<PagesNode> for PageTree {}
 
+/// The pages below `kid`, which is not one of the nodes on `path`.
+#[cfg(feature = "rayon")]
+fn par_subtree(kid: Ref<PagesNode>, resolve: &(impl Resolve + Sync), path: &[PlainRef]) -> Vec<Result<PageRc>> {
+    let r = kid.get_inner();
+    if path.contains(&r) {
+        return vec![Err(PdfError::ReferenceCycle { id: r.id })];
+    }
+    let node = match resolve.get(kid) {
+        Ok(node) => node,
+        Err(e) => return vec![Err(e)],
+    };
+    match *node {
+        PagesNode::Tree(ref tree) => {
+            let path: Vec<_> = path.iter().copied().chain(Some(r)).collect();
+            tree.kids.par_iter().flat_map_iter(|&kid| par_subtree(kid, resolve, &path)).collect()
+        }
+        PagesNode::Leaf(_) => vec![Ok(PageRc(node))],
+    }
+}
+
+/// Iterator over the pages of a `PageTree`, see `PageTree::pages`.
+///
+/// A `/Kids` entry that points back at one of its ancestors ends the iteration
+/// with `PdfError::ReferenceCycle`.
+pub struct PageIter<R> {
+    resolve: R,
+    /// The kids of every node on the current path and the position of the next one.
+    stack: Vec<(Vec<Ref<PagesNode>>, usize)>,
+    /// The nodes we descended into.
+    path: Vec<PlainRef>,
+}
+impl<R: Resolve> Iterator for PageIter<R> {
+    type Item = Result<PageRc>;
+    fn next(&mut self) -> Option<Result<PageRc>> {
+        loop {
+            let (kids, pos) = self.stack.last_mut()?;
+            let kid = match kids.get(*pos) {
+                Some(&kid) => kid,
+                None => {
+                    self.stack.pop();
+                    self.path.pop();
+                    continue;
+                }
+            };
+            *pos += 1;
+
+            let r = kid.get_inner();
+            if self.path.contains(&r) {
+                self.stack.clear();
+                return Some(Err(PdfError::ReferenceCycle { id: r.id }));
+            }
+            let node = match self.resolve.get(kid) {
+                Ok(node) => node,
+                Err(e) => return Some(Err(e)),
+            };
+            match *node {
+                PagesNode::Tree(ref tree) => {
+                    self.path.push(r);
+                    self.stack.push((tree.kids.clone(), 0));
+                }
+                PagesNode::Leaf(_) => return Some(Ok(PageRc(node))),
+            }
+        }
+    }
+}
+
 #[derive(Object, ObjectWrite, Debug, Clone, DataSize)]
 #[pdf(Type="Page?")]
 pub struct Page {
@@ -282,8 +477,10 @@ pub struct Page {
     #[pdf(key="Contents")]
     pub contents:   Option<Content>,
 
-    #[pdf(key="Rotate", default="0")]
-    pub rotate: i32,
+    /// `None` if the page doesn't set `/Rotate`. Use `effective_rotation()` to include the
+    /// value inherited from the page tree.
+    #[pdf(key="Rotate")]
+    pub rotate: Option<i32>,
 
     #[pdf(key="Metadata")]
     pub metadata:   Option<Primitive>,
@@ -321,7 +518,7 @@ impl Page {
             trim_box:   None,
             resources:  None,
             contents:   None,
-            rotate:     0,
+            rotate:     None,
             metadata:   None,
             lgi:        None,
             vp:         None,
@@ -345,6 +542,101 @@ impl Page {
             }
         }
     }
+    /// Sets `/CropBox`, clamped to the media box.
+    ///
+    /// Returns the crop box that was set. Fails if `rect` doesn't overlap the media box.
+    pub fn set_crop_box(&mut self, rect: Rectangle) -> Result<Rectangle> {
+        let media_box = self.media_box()?;
+        match rect.intersection(&media_box) {
+            Some(crop_box) => {
+                self.crop_box = Some(crop_box);
+                Ok(crop_box)
+            }
+            None => bail!("crop box {:?} lies outside the media box {:?}", rect, media_box)
+        }
+    }
+    /// Draws `text` on top of the page, starting at `position` in default user space.
+    ///
+    /// The existing content is wrapped in `q`/`Q`, so that the stamp is drawn with the default
+    /// graphics state, and the stamp is added as a new content stream after it. The font is
+    /// added to the page's resources unless they already have a Type1 font with that name
+    /// and the same encoding, i.e. `/WinAnsiEncoding` for everything but Symbol and
+    /// ZapfDingbats. Fails if `text` can't be encoded in the font's encoding.
+    pub fn add_text_stamp(&mut self, text: &str, position: (f32, f32), font: StandardFont, size: f32) -> Result<()> {
+        let new_font = Font::standard(font);
+        let encoding = new_font.encoding.as_ref().map_or(BaseEncoding::None, |e| e.base.clone());
+        let text = text.chars().map(|c| match encoding.encode(c) {
+            Some(code) => Ok(code),
+            None => bail!("{:?} can't be encoded in {}", c, font.name()),
+        }).collect::<Result<Vec<u8>>>()?;
+
+        // a page without resources gets its own
+        let mut resources = self.resources().map(|r| (**r).clone()).unwrap_or_default();
+        // the text is encoded for `new_font`, so a font with other codes can't be reused
+        let same_encoding = |e: &Option<Encoding>| match (e, &new_font.encoding) {
+            (Some(a), Some(b)) => a.base == b.base && a.differences.is_empty(),
+            (None, None) => true,
+            _ => false,
+        };
+        let existing = resources.fonts.iter()
+            .find(|(_, f)| matches!(f.subtype, FontType::Type1)
+                && f.name.as_ref().map(|n| n.as_str()) == Some(font.name())
+                && same_encoding(&f.encoding))
+            .map(|(name, _)| name.clone());
+        let font_name = match existing {
+            Some(name) => name,
+            None => {
+                let name: Name = (1 ..).map(|n| Name::from(format!("F{}", n)))
+                    .find(|name| !resources.fonts.contains_key(name)).unwrap();
+                resources.fonts.insert(name.clone(), new_font.into());
+                name
+            }
+        };
+        self.resources = Some(resources.into());
+
+        let stamp = serialize_ops(&[
+            Op::Restore,
+            Op::Save,
+            Op::BeginText,
+            Op::TextFont { name: font_name, size },
+            Op::MoveTextPosition { translation: Point { x: position.0, y: position.1 } },
+            Op::TextDraw { text: PdfString::new(text.into()) },
+            Op::EndText,
+            Op::Restore,
+        ])?;
+        let mut parts = vec![Stream::new((), serialize_ops(&[Op::Save])?)];
+        parts.extend(self.contents.take().map(|c| c.parts).unwrap_or_default());
+        parts.push(Stream::new((), stamp));
+        self.contents = Some(Content { parts });
+        Ok(())
+    }
+    /// Rotation in degrees, clockwise, looking up `/Rotate` in the ancestors of the page if the
+    /// page doesn't set it. Defaults to 0.
+    pub fn effective_rotation(&self, resolve: &impl Resolve) -> Result<i32> {
+        if let Some(rotate) = self.rotate {
+            return Ok(rotate);
+        }
+        let mut node = Some(&self.parent);
+        while let Some(tree) = node {
+            let dict = t!(t!(resolve.resolve(tree.get_ref().get_inner())).into_dictionary());
+            if let Some(rotate) = dict.get("Rotate") {
+                return rotate.as_integer();
+            }
+            node = tree.parent.as_ref();
+        }
+        Ok(0)
+    }
+    /// Sets `/Rotate` on the page, which overrides the value of the page tree.
+    ///
+    /// `degrees` has to be a multiple of 90. It is normalized to 0, 90, 180 or 270, so -90
+    /// becomes 270.
+    pub fn set_rotation(&mut self, degrees: i32) -> Result<()> {
+        if degrees % 90 != 0 {
+            bail!("rotation {} is not a multiple of 90", degrees);
+        }
+        self.rotate = Some(degrees.rem_euclid(360));
+        Ok(())
+    }
     pub fn resources(&self) -> Result<&MaybeRef<Resources>> {
         match self.resources {
             Some(ref r) => Ok(r),
@@ -352,6 +644,20 @@ impl Page {
                 .ok_or_else(|| PdfError::MissingEntry { typ: "Page", field: "Resources".into() })
         }
     }
+    /// The annotations of the page, from `/Annots`.
+    pub fn annotations(&self, resolve: &impl Resolve) -> Result<&[MaybeRef<Annot>]> {
+        Ok(t!(self.annotations.get(resolve)))
+    }
+    /// The text of the page, see `extract::extract_text`.
+    pub fn extract_text(&self, resolve: &impl Resolve) -> Result<String> {
+        let ops = match self.contents {
+            Some(ref contents) => t!(contents.operations(resolve)),
+            None => return Ok(String::new()),
+        };
+        let empty = Resources::default();
+        let resources = self.resources().map(|r| &**r).unwrap_or(&empty);
+        crate::extract::extract_text(&ops, resources, resolve)
+    }
 }
 impl SubType<PagesNode> for Page {}
 
@@ -367,6 +673,16 @@ pub struct PageLabel {
     #[pdf(key="St")]
     pub start:  Option<usize>
 }
+impl PageLabel {
+    /// The label of the page `offset` pages after the first page of this range.
+    pub fn label(&self, offset: usize) -> String {
+        let mut label = self.prefix.as_ref().map(|p| p.to_text()).unwrap_or_default();
+        if let Some(ref style) = self.style {
+            label.push_str(&style.format(self.start.unwrap_or(1) + offset));
+        }
+        label
+    }
+}
 
 #[derive(Object, ObjectWrite, Debug, DataSize, Default, DeepClone, Clone)]
 pub struct Resources {
@@ -445,7 +761,7 @@ impl Object for Pattern {
                 let dict = stream.info.info;
                 Ok(Pattern::Stream(dict, ops))
             }
-            p => Err(PdfError::UnexpectedPrimitive { expected: "Dictionary or Stream", found: p.get_debug_name() })
+            p => Err(PdfError::UnexpectedPrimitive { expected: "Dictionary or Stream", found: p.type_name() })
         }
     }
 }
@@ -615,6 +931,16 @@ pub enum ImageFormat {
     Png
 }
 
+/// The data of an image, see `ImageXObject::samples`.
+#[derive(Debug, Clone)]
+pub enum ImageData {
+    /// Decoded samples. `bits_per_component` bits per color component, rows are padded to
+    /// whole bytes.
+    Samples(Arc<[u8]>),
+    /// A `/DCTDecode` image, still JPEG encoded.
+    Jpeg(Arc<[u8]>),
+}
+
 impl ImageXObject {
     pub fn from_stream(s: PdfStream, resolve: &impl Resolve) -> Result<Self> {
         let inner = Stream::from_stream(s, resolve)?;
@@ -653,6 +979,55 @@ impl ImageXObject {
         }
     }
 
+    /// The decoded samples, or for JPEG images the JPEG data, which can be used as it is.
+    pub fn samples(&self, resolve: &impl Resolve) -> Result<ImageData> {
+        match self.raw_image_data(resolve)? {
+            (data, Some(StreamFilter::DCTDecode(_))) => Ok(ImageData::Jpeg(data)),
+            _ => Ok(ImageData::Samples(self.image_data(resolve)?)),
+        }
+    }
+
+    /// Writes the decoded image as a PNG.
+    ///
+    /// Works for gray, RGB and indexed images with an RGB palette, and image masks.
+    #[cfg(feature = "png")]
+    pub fn write_png(&self, resolve: &impl Resolve, out: impl std::io::Write) -> Result<()> {
+        let bpc = if self.image_mask { 1 } else { self.bits_per_component.unwrap_or(8) };
+        let depth = match bpc {
+            1 => png::BitDepth::One,
+            2 => png::BitDepth::Two,
+            4 => png::BitDepth::Four,
+            8 => png::BitDepth::Eight,
+            16 => png::BitDepth::Sixteen,
+            n => bail!("invalid BitsPerComponent {}", n),
+        };
+        let mut palette = None;
+        let color = match self.color_space {
+            _ if self.image_mask => png::ColorType::Grayscale,
+            Some(ColorSpace::DeviceGray) | Some(ColorSpace::CalGray(_)) => png::ColorType::Grayscale,
+            Some(ColorSpace::DeviceRGB) | Some(ColorSpace::CalRGB(_)) => png::ColorType::Rgb,
+            Some(ColorSpace::Icc(ref icc)) if icc.info.components == 1 => png::ColorType::Grayscale,
+            Some(ColorSpace::Icc(ref icc)) if icc.info.components == 3 => png::ColorType::Rgb,
+            Some(ColorSpace::Indexed(ref base, hival, ref lookup)) if matches!(**base, ColorSpace::DeviceRGB) => {
+                palette = Some(lookup.get(.. 3 * (hival as usize + 1)).unwrap_or(lookup).to_vec());
+                png::ColorType::Indexed
+            }
+            ref cs => bail!("can't write a PNG for color space {:?}", cs),
+        };
+        let data = self.image_data(resolve)?;
+
+        let mut encoder = png::Encoder::new(out, self.width, self.height);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        if let Some(palette) = palette {
+            encoder.set_palette(palette);
+        }
+        let mut writer = encoder.write_header().map_err(|e| other!("PNG error: {}", e))?;
+        writer.write_image_data(&data).map_err(|e| other!("PNG error: {}", e))?;
+        writer.finish().map_err(|e| other!("PNG error: {}", e))?;
+        Ok(())
+    }
+
     pub fn image_data(&self, resolve: &impl Resolve) -> Result<Arc<[u8]>> {
         let (data, filter) = self.raw_image_data(resolve)?;
         let filter = match filter {
@@ -858,6 +1233,101 @@ pub struct InteractiveFormDictionary {
     #[pdf(key="XFA")]
     pub xfa: Option<Primitive>,
 }
+impl InteractiveFormDictionary {
+    /// The terminal fields of the form, depth first.
+    ///
+    /// Kids without `/T` are widget annotations of their parent, so a field whose kids are all
+    /// widgets is a terminal field. `/FT` and `/V` are inherited from the parent fields.
+    pub fn fields(&self, resolve: &impl Resolve) -> Result<Vec<FormField>> {
+        fn walk(
+            field: RcRef<FieldDictionary>, parent: Option<&FormField>, resolve: &impl Resolve,
+            seen: &mut HashSet<PlainRef>, out: &mut Vec<FormField>,
+        ) -> Result<()> {
+            if !seen.insert(field.get_ref().get_inner()) {
+                warn!("form field {:?} was already visited", field.get_ref().get_inner());
+                return Ok(());
+            }
+            let mut name = parent.map(|p| p.name.clone()).unwrap_or_default();
+            if let Some(ref t) = field.name {
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.push_str(&t.to_text());
+            }
+            let value = match field.value {
+                Primitive::Null => parent.map(|p| p.value.clone()).unwrap_or(Primitive::Null),
+                ref v => v.clone(),
+            };
+            let typ = field.typ.or_else(|| parent.and_then(|p| p.typ));
+            let kids = field.kids.iter().map(|&kid| resolve.get(kid)).collect::<Result<Vec<_>>>()?;
+            let form_field = FormField { name, typ, value, field };
+            if kids.iter().all(|kid| kid.name.is_none()) {
+                out.push(form_field);
+            } else {
+                for kid in kids.into_iter().filter(|kid| kid.name.is_some()) {
+                    walk(kid, Some(&form_field), resolve, seen, out)?;
+                }
+            }
+            Ok(())
+        }
+
+        let mut seen = HashSet::new();
+        let mut out = vec![];
+        for field in self.fields.iter() {
+            t!(walk(field.clone(), None, resolve, &mut seen, &mut out));
+        }
+        Ok(out)
+    }
+}
+
+/// A terminal field of a form, see `InteractiveFormDictionary::fields`.
+#[derive(Debug, Clone)]
+pub struct FormField {
+    /// The fully qualified name, the `/T` of the field and its parents joined with dots
+    pub name: String,
+    /// `/FT`, possibly inherited
+    pub typ: Option<FieldType>,
+    /// `/V`, possibly inherited
+    pub value: Primitive,
+    pub field: RcRef<FieldDictionary>,
+}
+impl FormField {
+    /// The signature of a signature field, or `None` if it isn't one or is not signed yet.
+    pub fn signature(&self, resolve: &impl Resolve) -> Result<Option<SignatureDictionary>> {
+        match (self.typ, &self.value) {
+            (Some(FieldType::Signature), &Primitive::Null) => Ok(None),
+            (Some(FieldType::Signature), v) => Ok(Some(t!(SignatureDictionary::from_primitive(v.clone(), resolve)))),
+            _ => Ok(None),
+        }
+    }
+    /// The value as text, one string per selected option.
+    ///
+    /// Text strings are decoded and buttons give the name of the selected state. Choice
+    /// fields with several selected options give one string each; everything else gives at
+    /// most one. Empty without a value and for buttons in the `/Off` state.
+    pub fn value_strings(&self, resolve: &impl Resolve) -> Result<Vec<String>> {
+        fn text(p: Primitive, resolve: &impl Resolve) -> Result<Option<String>> {
+            Ok(match p.resolve(resolve)? {
+                Primitive::Null => None,
+                Primitive::String(s) => Some(s.to_text()),
+                Primitive::Name(ref n) if n == "Off" => None,
+                Primitive::Name(n) => Some(n.as_str().into()),
+                Primitive::Stream(s) => {
+                    let s: Stream<()> = t!(Stream::from_stream(s, resolve));
+                    Some(PdfString::new((*t!(s.data(resolve))).into()).to_text())
+                }
+                p => bail!("unexpected field value {:?}", p),
+            })
+        }
+        match self.value {
+            Primitive::Array(ref options) => options.iter()
+                .map(|o| text(o.clone(), resolve))
+                .filter_map(Result::transpose)
+                .collect(),
+            ref v => Ok(text(v.clone(), resolve)?.into_iter().collect()),
+        }
+    }
+}
 
 #[derive(Object, ObjectWrite, Debug, Copy, Clone, PartialEq, DataSize)]
 pub enum FieldType {
@@ -915,19 +1385,44 @@ pub struct SignatureDictionary {
     pub reason: Option<PdfString>,
     #[pdf(key="ContactInfo")]
     pub contact_info: Option<PdfString>,
-    #[pdf(key="V")]
+    #[pdf(key="V", default="0")]
     pub v: i32,
     #[pdf(key="R")]
-    pub r: i32,
+    pub r: Option<i32>,
     #[pdf(key="Prop_Build")]
-    pub prop_build: Dictionary,
+    pub prop_build: Option<Dictionary>,
     #[pdf(key="Prop_AuthTime")]
-    pub prop_auth_time: i32,
+    pub prop_auth_time: Option<i32>,
     #[pdf(key="Prop_AuthType")]
-    pub prop_auth_type: Name,
+    pub prop_auth_type: Option<Name>,
     #[pdf(other)]
     pub other: Dictionary
 }
+impl SignatureDictionary {
+    /// The signed parts of the file, from the `(offset, length)` pairs of `/ByteRange`.
+    pub fn byte_ranges(&self) -> Result<Vec<std::ops::Range<usize>>> {
+        if !self.byte_range.len().is_multiple_of(2) {
+            bail!("/ByteRange has an odd number of entries: {:?}", self.byte_range);
+        }
+        self.byte_range.chunks_exact(2).map(|r| match r[0].checked_add(r[1]) {
+            Some(end) => Ok(r[0] .. end),
+            None => bail!("/ByteRange entry {} + {} is too large", r[0], r[1]),
+        }).collect()
+    }
+    /// The bytes covered by the signature. `data` is the whole file.
+    ///
+    /// Together with the PKCS#7 data in `/Contents`, this is what a verifier needs.
+    pub fn signed_data(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut out = vec![];
+        for range in t!(self.byte_ranges()) {
+            match data.get(range.clone()) {
+                Some(part) => out.extend_from_slice(part),
+                None => bail!("/ByteRange {:?} is outside of the file ({} bytes)", range, data.len()),
+            }
+        }
+        Ok(out)
+    }
+}
 
 #[derive(Object, ObjectWrite, Debug)]
 #[pdf(Type="SigRef?")]
@@ -977,6 +1472,23 @@ pub struct Annot {
 
     #[pdf(key="Border")]
     pub border: Option<Primitive>,
+
+    /// Subtype specific entries, like `/QuadPoints` of markup annotations, or `/Dest` and `/A`
+    /// of links, see `dest()` and `action()`
+    #[pdf(other)]
+    pub other: Dictionary,
+}
+impl Annot {
+    /// The destination of a `/Link` annotation, from `/Dest`.
+    ///
+    /// Parsed on request, so that a broken destination doesn't affect the rest of the annotation.
+    pub fn dest(&self, resolve: &impl Resolve) -> Result<Option<MaybeNamedDest>> {
+        self.other.get("Dest").map(|p| MaybeNamedDest::from_primitive(p.clone(), resolve)).transpose()
+    }
+    /// The action of a `/Link` or `/Widget` annotation, from `/A`. Parsed on request, like `dest()`.
+    pub fn action(&self, resolve: &impl Resolve) -> Result<Option<Action>> {
+        self.other.get("A").map(|p| Action::from_primitive(p.clone(), resolve)).transpose()
+    }
 }
 
 #[derive(Object, ObjectWrite, Debug, DataSize, Clone)]
@@ -1052,7 +1564,7 @@ impl Object for AppearanceStreamEntry {
         match p.resolve(resolve)? {
             p @ Primitive::Dictionary(_) => Object::from_primitive(p, resolve).map(AppearanceStreamEntry::Dict),
             p @ Primitive::Stream(_) => Object::from_primitive(p, resolve).map(AppearanceStreamEntry::Single),
-            p => Err(PdfError::UnexpectedPrimitive {expected: "Dict or Stream", found: p.get_debug_name()})
+            p => Err(PdfError::UnexpectedPrimitive {expected: "Dict or Stream", found: p.type_name()})
         }
     }
 }
@@ -1079,15 +1591,48 @@ impl DataSize for AppearanceStreamEntry {
 pub enum Counter {
     #[pdf(name="D")]
     Arabic,
-    #[pdf(name="r")]
-    RomanUpper,
     #[pdf(name="R")]
+    RomanUpper,
+    #[pdf(name="r")]
     RomanLower,
-    #[pdf(name="a")]
-    AlphaUpper,
     #[pdf(name="A")]
+    AlphaUpper,
+    #[pdf(name="a")]
     AlphaLower
 }
+impl Counter {
+    /// Formats `n`, which starts at 1. Letters go a to z, then aa to zz and so on.
+    pub fn format(&self, n: usize) -> String {
+        fn roman(mut n: usize) -> String {
+            const DIGITS: [(usize, &str); 13] = [
+                (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"),
+                (50, "l"), (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i")
+            ];
+            let mut s = String::new();
+            for &(value, digits) in DIGITS.iter() {
+                while n >= value {
+                    s.push_str(digits);
+                    n -= value;
+                }
+            }
+            s
+        }
+        fn alpha(n: usize) -> String {
+            if n == 0 {
+                return String::new();
+            }
+            let letter = (b'a' + ((n - 1) % 26) as u8) as char;
+            letter.to_string().repeat((n - 1) / 26 + 1)
+        }
+        match *self {
+            Counter::Arabic => n.to_string(),
+            Counter::RomanLower => roman(n),
+            Counter::RomanUpper => roman(n).to_uppercase(),
+            Counter::AlphaLower => alpha(n),
+            Counter::AlphaUpper => alpha(n).to_uppercase(),
+        }
+    }
+}
 
 #[derive(Debug, DataSize)]
 pub enum NameTreeNode<T> {
@@ -1105,7 +1650,11 @@ pub struct NameTree<T> {
     pub node: NameTreeNode<T>,
 }
 impl<T: Object+DataSize> NameTree<T> {
+    /// Calls `callback` for every entry. Nodes that were already visited are skipped.
     pub fn walk(&self, r: &impl Resolve, callback: &mut dyn FnMut(&PdfString, &T)) -> Result<(), PdfError> {
+        self.walk_seen(r, callback, &mut HashSet::new())
+    }
+    fn walk_seen(&self, r: &impl Resolve, callback: &mut dyn FnMut(&PdfString, &T), seen: &mut HashSet<PlainRef>) -> Result<()> {
         match self.node {
             NameTreeNode::Leaf(ref items) => {
                 for (name, val) in items {
@@ -1114,13 +1663,48 @@ impl<T: Object+DataSize> NameTree<T> {
             }
             NameTreeNode::Intermediate(ref items) => {
                 for &tree_ref in items {
+                    if !seen.insert(tree_ref.get_inner()) {
+                        warn!("name tree node {:?} was already visited", tree_ref.get_inner());
+                        continue;
+                    }
                     let tree = r.get(tree_ref)?;
-                    tree.walk(r, callback)?;
+                    tree.walk_seen(r, callback, seen)?;
                 }
             }
         }
         Ok(())
     }
+    /// Looks up `name`, skipping the subtrees whose `/Limits` don't include it.
+    ///
+    /// Like `walk`, nodes that were already visited are skipped, so cyclic `/Kids` can't
+    /// make the lookup recurse forever.
+    pub fn get(&self, name: &[u8], r: &impl Resolve) -> Result<Option<T>> where T: Clone {
+        self.get_seen(name, r, &mut HashSet::new())
+    }
+    fn get_seen(&self, name: &[u8], r: &impl Resolve, seen: &mut HashSet<PlainRef>) -> Result<Option<T>> where T: Clone {
+        if let Some((ref min, ref max)) = self.limits {
+            if name < min.as_bytes() || name > max.as_bytes() {
+                return Ok(None);
+            }
+        }
+        match self.node {
+            NameTreeNode::Leaf(ref items) => {
+                Ok(items.iter().find(|(key, _)| key.as_bytes() == name).map(|(_, val)| val.clone()))
+            }
+            NameTreeNode::Intermediate(ref items) => {
+                for &tree_ref in items {
+                    if !seen.insert(tree_ref.get_inner()) {
+                        warn!("name tree node {:?} was already visited", tree_ref.get_inner());
+                        continue;
+                    }
+                    if let Some(val) = r.get(tree_ref)?.get_seen(name, r, seen)? {
+                        return Ok(Some(val));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
 }
 
 impl<T: Object> Object for NameTree<T> {
@@ -1347,20 +1931,20 @@ impl Dest {
                 left: match *try_opt!(array.get(2)) {
                     Primitive::Null => None,
                     Primitive::Integer(n) => Some(n as f32),
-                    Primitive::Number(f) => Some(f),
-                    ref p => return Err(PdfError::UnexpectedPrimitive { expected: "Number | Integer | Null", found: p.get_debug_name() }),
+                    Primitive::Number(f) => Some(f as f32),
+                    ref p => return Err(PdfError::UnexpectedPrimitive { expected: "Number | Integer | Null", found: p.type_name() }),
                 },
                 top: match *try_opt!(array.get(3)) {
                     Primitive::Null => None,
                     Primitive::Integer(n) => Some(n as f32),
-                    Primitive::Number(f) => Some(f),
-                    ref p => return Err(PdfError::UnexpectedPrimitive { expected: "Number | Integer | Null", found: p.get_debug_name() }),
+                    Primitive::Number(f) => Some(f as f32),
+                    ref p => return Err(PdfError::UnexpectedPrimitive { expected: "Number | Integer | Null", found: p.type_name() }),
                 },
                 zoom: match array.get(4) {
                     Some(Primitive::Null) => 0.0,
                     Some(&Primitive::Integer(n)) => n as f32,
-                    Some(&Primitive::Number(f)) => f,
-                    Some(p) => return Err(PdfError::UnexpectedPrimitive { expected: "Number | Integer | Null", found: p.get_debug_name() }),
+                    Some(&Primitive::Number(f)) => f as f32,
+                    Some(p) => return Err(PdfError::UnexpectedPrimitive { expected: "Number | Integer | Null", found: p.type_name() }),
                     None => 0.0,
                 },
             },
@@ -1398,6 +1982,8 @@ impl Object for MaybeNamedDest {
         let p = match p {
             Primitive::Dictionary(mut dict) => dict.require("Dest", "D")?,
             Primitive::String(s) => return Ok(MaybeNamedDest::Named(s)),
+            // PDF 1.1 named destinations are names
+            Primitive::Name(n) => return Ok(MaybeNamedDest::Named(PdfString::new(n.as_bytes().into()))),
             p => p
         };
         let array = t!(p.as_array(), p);
@@ -1420,32 +2006,32 @@ impl ObjectWrite for Dest {
                 arr.push(Primitive::Name("XYZ".into()));
                 arr.push(left.to_primitive(update)?);
                 arr.push(top.to_primitive(update)?);
-                arr.push(Primitive::Number(zoom));
+                arr.push(zoom.into());
             }
             DestView::Fit => {
                 arr.push(Primitive::Name("Fit".into()));
             }
             DestView::FitH { top } => {
                 arr.push(Primitive::Name("FitH".into()));
-                arr.push(Primitive::Number(top));
+                arr.push(top.into());
             }
             DestView::FitV { left } => {
                 arr.push(Primitive::Name("FitV".into()));
-                arr.push(Primitive::Number(left));
+                arr.push(left.into());
             }
             DestView::FitR(rect) => {
                 arr.push(Primitive::Name("FitR".into()));
-                arr.push(Primitive::Number(rect.left));
-                arr.push(Primitive::Number(rect.bottom));
-                arr.push(Primitive::Number(rect.right));
-                arr.push(Primitive::Number(rect.top));
+                arr.push(rect.left.into());
+                arr.push(rect.bottom.into());
+                arr.push(rect.right.into());
+                arr.push(rect.top.into());
             }
             DestView::FitB => {
                 arr.push(Primitive::Name("FitB".into()));
             }
             DestView::FitBH { top } => {
                 arr.push(Primitive::Name("FitBH".into()));
-                arr.push(Primitive::Number(top));
+                arr.push(top.into());
             }
         }
         Ok(Primitive::Array(arr))
@@ -1594,6 +2180,20 @@ pub struct OutlineItem {
     #[pdf(key="F")]
     pub flags: Option<i32>,
 }
+impl OutlineItem {
+    /// The decoded `/Title`, empty if there is none.
+    pub fn title_text(&self) -> String {
+        self.title.as_ref().map(|t| t.to_text()).unwrap_or_default()
+    }
+    /// Where the item points to, from `/Dest`, or the destination of a `/GoTo` action.
+    pub fn destination(&self, resolve: &impl Resolve) -> Result<Option<MaybeNamedDest>> {
+        match (&self.dest, &self.action) {
+            (Some(dest), _) => Ok(Some(t!(MaybeNamedDest::from_primitive(dest.clone(), resolve)))),
+            (None, Some(Action::Goto(dest))) => Ok(Some(dest.clone())),
+            _ => Ok(None),
+        }
+    }
+}
 
 #[derive(Clone, Debug, DataSize)]
 pub enum Action {
@@ -1618,6 +2218,7 @@ impl ObjectWrite for Action {
         match self {
             Action::Goto(dest) => {
                 let mut dict = Dictionary::new();
+                dict.insert("S", Primitive::name("GoTo"));
                 dict.insert("D", dest.to_primitive(update)?);
                 Ok(Primitive::Dictionary(dict))
             }
@@ -1639,6 +2240,54 @@ pub struct Outlines {
     pub last: Option<Ref<OutlineItem>>,
 
 }
+impl Outlines {
+    /// All items of the outline, depth first, with their depth. Top level items have depth 0.
+    pub fn iter<'a, R: Resolve>(&self, resolve: &'a R) -> OutlineIter<'a, R> {
+        OutlineIter { resolve, stack: vec![self.first], seen: HashSet::new() }
+    }
+}
+
+/// Iterator over the items of an outline, see `Outlines::iter`.
+///
+/// An item that was already visited ends its list of siblings, so cyclic `/Next` or
+/// `/First` chains can't loop. Iteration stops after an error.
+pub struct OutlineIter<'a, R> {
+    resolve: &'a R,
+    /// The next item of each level
+    stack: Vec<Option<Ref<OutlineItem>>>,
+    seen: HashSet<PlainRef>,
+}
+impl<'a, R: Resolve> Iterator for OutlineIter<'a, R> {
+    type Item = Result<(usize, RcRef<OutlineItem>)>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let r = match self.stack.last_mut()?.take() {
+                Some(r) => r,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            if !self.seen.insert(r.get_inner()) {
+                warn!("outline item {:?} was already visited", r.get_inner());
+                continue;
+            }
+            let item = match self.resolve.get(r) {
+                Ok(item) => item,
+                Err(e) => {
+                    self.stack.clear();
+                    return Some(Err(e));
+                }
+            };
+            let depth = self.stack.len() - 1;
+            *self.stack.last_mut().unwrap() = item.next;
+            if item.first.is_some() {
+                self.stack.push(item.first);
+            }
+            return Some(Ok((depth, item)));
+        }
+    }
+}
 
 /// ISO 32000-2:2020(E) 7.9.5 Rectangles (Pg 134)
 /// specifying the lower-left x, lower-left y,
@@ -1649,7 +2298,7 @@ pub struct Outlines {
 /// (ur x , ll y ).
 /// Also see Table 74, key BBox definition Pg 221
 /// defining top, left, bottom, right labeling
-#[derive(Debug, Copy, Clone, DataSize, Default)]
+#[derive(Debug, Copy, Clone, DataSize, Default, PartialEq)]
 pub struct Rectangle {
     pub left:   f32,
     pub bottom: f32,
@@ -1661,16 +2310,48 @@ pub type Rect = Rectangle;
 
 impl Object for Rectangle {
     fn from_primitive(p: Primitive, r: &impl Resolve) -> Result<Self> {
-        let arr = p.resolve(r)?.into_array()?;
-        if arr.len() != 4 {
-            bail!("len != 4 {:?}", arr);
-        }
-        Ok(Rectangle {
-            left:   arr[0].as_number()?,
-            bottom: arr[1].as_number()?,
-            right:  arr[2].as_number()?,
-            top:    arr[3].as_number()?
-        })
+        let p = p.resolve(r)?;
+        let len = p.as_array()?.len();
+        if len != 4 {
+            return Err(PdfError::ArrayLength { expected: 4, found: len });
+        }
+        Ok(Rectangle::new(
+            p.get_number(0)?,
+            p.get_number(1)?,
+            p.get_number(2)?,
+            p.get_number(3)?
+        ))
+    }
+}
+impl Rectangle {
+    /// Create a rectangle from two opposite corners, given in any order.
+    pub fn new(x0: f32, y0: f32, x1: f32, y1: f32) -> Rectangle {
+        Rectangle {
+            left:   x0.min(x1),
+            bottom: y0.min(y1),
+            right:  x0.max(x1),
+            top:    y0.max(y1),
+        }
+    }
+    pub fn width(&self) -> f32 {
+        self.right - self.left
+    }
+    pub fn height(&self) -> f32 {
+        self.top - self.bottom
+    }
+    /// Whether the point lies inside the rectangle or on its border.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        (self.left ..= self.right).contains(&x) && (self.bottom ..= self.top).contains(&y)
+    }
+    /// The part that lies in both rectangles, or `None` if they don't overlap.
+    pub fn intersection(&self, other: &Rectangle) -> Option<Rectangle> {
+        let r = Rectangle {
+            left:   self.left.max(other.left),
+            bottom: self.bottom.max(other.bottom),
+            right:  self.right.min(other.right),
+            top:    self.top.min(other.top),
+        };
+        (r.left < r.right && r.bottom < r.top).then_some(r)
     }
 }
 impl ObjectWrite for Rectangle {
@@ -1780,6 +2461,9 @@ pub enum Trapped {
     Unknown,
 }
 
+/// The document information dictionary, `/Info` in the trailer.
+///
+/// The strings are text strings, use `PdfString::to_text` to decode them.
 #[derive(Object, ObjectWrite, Debug, DataSize, Default)]
 pub struct InfoDict {
     #[pdf(key="Title")]
@@ -1797,7 +2481,7 @@ pub struct InfoDict {
     #[pdf(key="Creator")]
     pub creator: Option<PdfString>,
 
-    #[pdf(key="Author")]
+    #[pdf(key="Producer")]
     pub producer: Option<PdfString>,
 
     #[pdf(key="CreationDate")]
@@ -1813,6 +2497,24 @@ pub struct InfoDict {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::file::FileOptions;
+    use crate::file::tests::{build_file, document};
+
+    #[test]
+    fn info_dict() {
+        let (p, _) = Primitive::parse(b"<< /Title <FEFF004800E9> /Author (Me) /Producer (pdf) /CreationDate (D:20240102030405+01'30) >>").unwrap();
+        let info = InfoDict::from_primitive(p, &NoResolve).unwrap();
+        assert_eq!(info.title.unwrap().to_text(), "H\u{e9}");
+        assert_eq!(info.author.unwrap().to_string().unwrap(), "Me");
+        assert_eq!(info.producer.unwrap().to_string().unwrap(), "pdf");
+        assert!(info.subject.is_none());
+        let date = info.creation_date.unwrap();
+        assert_eq!((date.year, date.month, date.day, date.hour, date.minute, date.second), (2024, 1, 2, 3, 4, 5));
+        assert_eq!((date.rel, date.tz_hour, date.tz_minute), (TimeRel::Later, 1, 30));
+
+        let p = InfoDict { producer: Some("pdf".into()), .. InfoDict::default() }.to_primitive(&mut NoUpdate).unwrap();
+        assert_eq!(p.into_dictionary().unwrap()["Producer"], Primitive::String("pdf".into()));
+    }
 
     #[test]
     fn parse_struct_type() {
@@ -1830,6 +2532,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn rectangle() {
+        let p = Primitive::Array(vec![100.into(), 50.into(), 0.into(), 250.into()]);
+        let r = Rectangle::from_primitive(p, &NoResolve).unwrap();
+        assert_eq!((r.left, r.bottom, r.right, r.top), (0., 50., 100., 250.));
+        assert_eq!(r.width(), 100.);
+        assert_eq!(r.height(), 200.);
+        assert!(r.contains(0., 50.));
+        assert!(r.contains(50., 100.));
+        assert!(!r.contains(101., 100.));
+        assert_eq!(
+            r.to_primitive(&mut NoUpdate).unwrap(),
+            Primitive::Array(vec![0f32.into(), 50f32.into(), 100f32.into(), 250f32.into()])
+        );
+        assert!(Rectangle::from_primitive(Primitive::Array(vec![1.into()]), &NoResolve).is_err());
+    }
+
     #[test]
     fn test_field_type() {
         assert_eq!(
@@ -1837,4 +2556,392 @@ mod tests {
             FieldType::Text
         );
     }
+
+    #[test]
+    fn inherited_attributes() {
+        let data = build_file(&[
+            "<< /Type /Catalog /Pages 2 0 R >>",
+            "<< /Type /Pages /Kids [3 0 R] /Count 2 /MediaBox [0 0 612 792] /Rotate 90 /Resources << >> >>",
+            "<< /Type /Pages /Parent 2 0 R /Kids [4 0 R 5 0 R] /Count 2 /CropBox [10 10 600 780] >>",
+            "<< /Type /Page /Parent 3 0 R >>",
+            "<< /Type /Page /Parent 3 0 R /MediaBox [0 0 100 200] /CropBox [0 0 50 50] /Rotate 180 >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let page = file.get_page(0).unwrap();
+        assert!(page.media_box.is_none());
+        assert_eq!(page.media_box().unwrap().width(), 612.);
+        assert_eq!(page.crop_box().unwrap().width(), 590.);
+        assert_eq!(page.rotate, None);
+        assert_eq!(page.effective_rotation(&file.resolver()).unwrap(), 90);
+        assert!(page.resources().is_ok());
+
+        let page = file.get_page(1).unwrap();
+        assert_eq!(page.media_box().unwrap().height(), 200.);
+        assert_eq!(page.crop_box().unwrap().width(), 50.);
+        assert_eq!(page.effective_rotation(&file.resolver()).unwrap(), 180);
+    }
+
+    #[test]
+    fn image_samples() {
+        let pixels = [0u8, 64, 128, 255, 32, 96];
+        let hex: String = deflate::deflate_bytes_zlib(&pixels).iter().map(|b| format!("{:02x}", b)).collect();
+        let data = document("", "", &[], &[
+            &format!("<< /Type /XObject /Subtype /Image /Width 3 /Height 2 /ColorSpace /DeviceGray
+                /BitsPerComponent 8 /Filter [/ASCIIHexDecode /FlateDecode] /Length {} >>\nstream\n{}>\nendstream",
+                hex.len() + 1, hex),
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let image = match &*resolver.get::<XObject>(Ref::from_id(3)).unwrap() {
+            XObject::Image(image) => image.clone(),
+            _ => panic!("not an image"),
+        };
+        assert_eq!((image.width, image.height, image.bits_per_component), (3, 2, Some(8)));
+        match image.samples(&resolver).unwrap() {
+            ImageData::Samples(samples) => assert_eq!(&*samples, &pixels),
+            ImageData::Jpeg(_) => panic!("not a JPEG"),
+        }
+
+        #[cfg(feature = "png")]
+        {
+            let mut png = vec![];
+            image.write_png(&resolver, &mut png).unwrap();
+            assert!(png.starts_with(b"\x89PNG"));
+        }
+    }
+
+    #[test]
+    fn page_annotations() {
+        let data = document("", "", &["/MediaBox [0 0 612 792] /Annots 4 0 R"], &[
+            "[5 0 R << /Subtype /Link /Rect [0 0 10 10] /Dest /chapter1 >> << /Subtype /Link /Rect [0 0 10 10] /A << /S /GoTo >> /Dest 7 >>]",
+            "<< /Type /Annot /Subtype /Link /Rect [72 700 144 714] /Contents (Go) /Border [0 0 0]
+                /A << /S /GoTo /D [3 0 R /Fit] >> >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let page = file.get_page(0).unwrap();
+        let annots = page.annotations(&resolver).unwrap();
+        assert_eq!(annots.len(), 3);
+
+        let link = &annots[0];
+        assert_eq!(link.subtype.as_str(), "Link");
+        assert_eq!((link.rect.left, link.rect.top), (72., 714.));
+        assert_eq!(link.contents.as_ref().unwrap().as_bytes(), b"Go");
+        match link.action(&resolver).unwrap() {
+            Some(Action::Goto(MaybeNamedDest::Direct(ref dest))) => {
+                assert_eq!(dest.page.unwrap().get_inner().id, 3);
+                assert!(matches!(dest.view, DestView::Fit));
+            }
+            ref a => panic!("{:?}", a),
+        }
+        match annots[1].dest(&resolver).unwrap() {
+            Some(MaybeNamedDest::Named(ref name)) => assert_eq!(name.as_bytes(), b"chapter1"),
+            ref d => panic!("{:?}", d),
+        }
+        assert!(annots[1].action(&resolver).unwrap().is_none());
+
+        // a broken action or destination only fails the accessor
+        assert!(annots[2].action(&resolver).is_err());
+        assert!(annots[2].dest(&resolver).is_err());
+        assert_eq!(annots[2].subtype.as_str(), "Link");
+    }
+
+    #[test]
+    fn outline_tree() {
+        let data = document("/Outlines 3 0 R", "", &[], &[
+            "<< /Type /Outlines /First 4 0 R /Last 5 0 R /Count 4 >>",
+            "<< /Title (Chapter 1) /Parent 3 0 R /Next 5 0 R /First 6 0 R /Last 7 0 R /Dest /c1 >>",
+            // the broken /Next points back to the first chapter
+            "<< /Title <feff00430068002e00a0003200a0> /Parent 3 0 R /Prev 4 0 R /Next 4 0 R >>",
+            "<< /Title (Section 1.1) /Parent 4 0 R /Next 7 0 R /A << /S /GoTo /D (s11) >> >>",
+            "<< /Title (Section 1.2) /Parent 4 0 R /Prev 6 0 R >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let outlines = file.get_root().outlines.as_ref().unwrap();
+        let items: Vec<_> = outlines.iter(&resolver).map(|r| r.unwrap()).collect();
+        let titles: Vec<_> = items.iter().map(|(depth, item)| (*depth, item.title_text())).collect();
+        assert_eq!(titles, [
+            (0, "Chapter 1".to_string()),
+            (1, "Section 1.1".into()),
+            (1, "Section 1.2".into()),
+            (0, "Ch.\u{a0}2\u{a0}".into()),
+        ]);
+
+        let name = |i: usize| match items[i].1.destination(&resolver).unwrap() {
+            Some(MaybeNamedDest::Named(s)) => Some(s.to_text()),
+            _ => None,
+        };
+        assert_eq!(name(0).as_deref(), Some("c1"));
+        assert_eq!(name(1).as_deref(), Some("s11"));
+        assert_eq!(name(2), None);
+    }
+
+    #[test]
+    fn named_destinations() {
+        let data = document("/Names << /Dests 4 0 R >> /Dests << /old [3 0 R /Fit] >>", "", &["/MediaBox [0 0 612 792]"], &[
+            "<< /Kids [5 0 R 6 0 R] >>",
+            "<< /Limits [(a) (b)] /Names [(a) [3 0 R /Fit] (b) [3 0 R /FitH 500]] >>",
+            "<< /Limits [(c) (d)] /Names [(c) [3 0 R /XYZ 72 720 0] (d) << /D [3 0 R /FitB] >>] >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let root = file.get_root();
+
+        let dest = root.resolve_dest(b"c", &resolver).unwrap().unwrap();
+        assert_eq!(dest.page.unwrap().get_inner().id, 3);
+        assert!(matches!(dest.view, DestView::XYZ { left: Some(l), top: Some(t), .. } if l == 72. && t == 720.));
+        let dest = root.resolve_dest(b"d", &resolver).unwrap().unwrap();
+        assert!(matches!(dest.view, DestView::FitB));
+        let dest = root.resolve_dest(b"b", &resolver).unwrap().unwrap();
+        assert!(matches!(dest.view, DestView::FitH { top } if top == 500.));
+        let dest = root.resolve_dest(b"old", &resolver).unwrap().unwrap();
+        assert!(matches!(dest.view, DestView::Fit));
+        assert!(root.resolve_dest(b"e", &resolver).unwrap().is_none());
+    }
+
+    #[test]
+    fn name_tree_cycle() {
+        let data = document("", "", &[], &[
+            "<< /Kids [4 0 R] >>",
+            // lists its parent and itself as kids
+            "<< /Kids [3 0 R 4 0 R 5 0 R] >>",
+            "<< /Names [(a) 1 (b) 2] >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let tree = resolver.get::<NameTree<i32>>(Ref::from_id(3)).unwrap();
+        assert_eq!(tree.get(b"b", &resolver).unwrap(), Some(2));
+        assert_eq!(tree.get(b"c", &resolver).unwrap(), None);
+
+        let mut names = vec![];
+        tree.walk(&resolver, &mut |name, &val| names.push((name.to_text(), val))).unwrap();
+        assert_eq!(names, [("a".to_string(), 1), ("b".into(), 2)]);
+    }
+
+    #[test]
+    fn page_labels() {
+        let data = document("/PageLabels << /Kids [3 0 R 4 0 R] >>", "", &[], &[
+            "<< /Limits [0 3] /Nums [0 << /S /r >> 3 << /S /D >>] >>",
+            "<< /Limits [10 12] /Nums [10 << /P (A-) /S /D /St 1 >> 12 << /P (Index) >>] >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let root = file.get_root();
+        let labels: Vec<_> = (0 .. 14).map(|i| root.page_label(i, &resolver).unwrap()).collect();
+        assert_eq!(labels, [
+            "i", "ii", "iii", "1", "2", "3", "4", "5", "6", "7", "A-1", "A-2", "Index", "Index",
+        ]);
+
+        assert_eq!(Counter::RomanUpper.format(1994), "MCMXCIV");
+        assert_eq!(Counter::AlphaLower.format(28), "bb");
+        assert_eq!(Counter::AlphaUpper.format(26), "Z");
+    }
+
+    #[test]
+    fn form_fields() {
+        let data = document("/AcroForm << /Fields [4 0 R 5 0 R] >>", "", &["/MediaBox [0 0 612 792] /Annots [6 0 R 7 0 R 8 0 R]"], &[
+            // the last kid is the field itself
+            "<< /T (person) /FT /Tx /Kids [6 0 R 9 0 R 4 0 R] >>",
+            // a checkbox whose kids are its widgets
+            "<< /T (agree) /FT /Btn /V /Yes /Kids [7 0 R 8 0 R] >>",
+            "<< /T (name) /Parent 4 0 R /V (Jane) /Type /Annot /Subtype /Widget /Rect [0 0 100 20] >>",
+            "<< /Parent 5 0 R /Type /Annot /Subtype /Widget /Rect [0 30 20 50] /AS /Yes >>",
+            "<< /Parent 5 0 R /Type /Annot /Subtype /Widget /Rect [30 30 50 50] /AS /Off >>",
+            "<< /T (age) /Parent 4 0 R >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let form = file.get_root().forms.as_ref().unwrap();
+        let fields = form.fields(&resolver).unwrap();
+        let fields: Vec<_> = fields.iter().map(|f| (f.name.as_str(), f.typ, f.value.clone())).collect();
+        assert_eq!(fields, [
+            ("person.name", Some(FieldType::Text), Primitive::String("Jane".into())),
+            ("person.age", Some(FieldType::Text), Primitive::Null),
+            ("agree", Some(FieldType::Button), Primitive::name("Yes")),
+        ]);
+    }
+
+    #[test]
+    fn form_field_values() {
+        let data = document("/AcroForm << /Fields [3 0 R 4 0 R 5 0 R 6 0 R] >>", "", &[], &[
+            "<< /T (name) /FT /Tx /V <feff004a00fc0072006700650020d83dde00> >>",
+            "<< /T (agree) /FT /Btn /V /Off >>",
+            "<< /T (colors) /FT /Ch /V [(red) <feff0067007200fc006e>] >>",
+            "<< /T (city) /FT /Ch /V 7 0 R >>",
+            "(Z\\374rich)",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let fields = file.get_root().forms.as_ref().unwrap().fields(&resolver).unwrap();
+        let values: Vec<_> = fields.iter().map(|f| f.value_strings(&resolver).unwrap()).collect();
+        assert_eq!(values, [
+            vec!["J\u{fc}rge \u{1F600}".to_string()],
+            vec![],
+            vec!["red".into(), "gr\u{fc}n".into()],
+            vec!["Z\u{fc}rich".into()],
+        ]);
+    }
+
+    #[test]
+    fn signature_field() {
+        let data = document("/AcroForm << /Fields [3 0 R 4 0 R 5 0 R] /SigFlags 3 >>", "", &[], &[
+            "<< /T (Signature1) /FT /Sig /V 6 0 R >>",
+            "<< /T (Signature2) /FT /Sig >>",
+            "<< /T (name) /FT /Tx /V (x) >>",
+            "<< /Type /Sig /Filter /Adobe.PPKLite /SubFilter /adbe.pkcs7.detached
+                /ByteRange [0 9 20 6] /Contents <30820102> /Name (A. Signer) /M (D:20240102030405Z) >>",
+        ]);
+        let file = FileOptions::uncached().load(data.clone()).unwrap();
+        let resolver = file.resolver();
+        let fields = file.get_root().forms.as_ref().unwrap().fields(&resolver).unwrap();
+        let sig = fields[0].signature(&resolver).unwrap().unwrap();
+        assert!(fields[1].signature(&resolver).unwrap().is_none());
+        assert!(fields[2].signature(&resolver).unwrap().is_none());
+
+        assert_eq!(sig.sub_filter.as_str(), "adbe.pkcs7.detached");
+        assert_eq!(sig.byte_range, [0, 9, 20, 6]);
+        assert_eq!(sig.byte_ranges().unwrap(), [0 .. 9, 20 .. 26]);
+        assert_eq!(sig.contents.as_bytes(), b"\x30\x82\x01\x02");
+        assert_eq!(sig.name.as_ref().unwrap().to_text(), "A. Signer");
+        assert_eq!(sig.m.as_ref().unwrap().as_bytes(), b"D:20240102030405Z");
+
+        let signed = sig.signed_data(&data).unwrap();
+        assert_eq!(signed, [&data[0 .. 9], &data[20 .. 26]].concat());
+        let mut sig = sig;
+        sig.byte_range.push(3);
+        assert!(sig.signed_data(&data).is_err());
+        // the end of the range overflows
+        sig.byte_range = vec![0, 9, usize::MAX, 6];
+        assert!(sig.byte_ranges().is_err());
+        assert!(sig.signed_data(&data).is_err());
+    }
+
+    #[test]
+    fn optional_content() {
+        let data = document("/OCProperties << /OCGs [3 0 R 4 0 R] /D << /Order [3 0 R 4 0 R] /OFF [4 0 R] >> >>", "", &[], &[
+            "<< /Type /OCG /Name (Text) >>",
+            "<< /Type /OCG /Name (Watermark) >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let oc = file.get_root().optional_content.as_ref().unwrap();
+        let layers: Vec<_> = oc.groups.iter().map(|&group| {
+            (resolver.get(group).unwrap().name.to_text(), oc.is_visible(group))
+        }).collect();
+        assert_eq!(layers, [("Text".to_string(), true), ("Watermark".into(), false)]);
+
+        // groups that are in neither list have the base state
+        assert!(oc.is_visible(Ref::from_id(9)));
+        let mut config = oc.default_config.clone();
+        config.base_state = Some("OFF".into());
+        assert!(!config.is_visible(Ref::from_id(9)));
+        assert!(!config.is_visible(oc.groups[1]));
+    }
+
+    #[test]
+    fn set_rotation() {
+        let data = document("", "/MediaBox [0 0 612 792] /Rotate 90", &[""], &[]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let mut page = (*file.get_page(0).unwrap()).clone();
+        assert_eq!(page.effective_rotation(&resolver).unwrap(), 90);
+
+        page.set_rotation(-90).unwrap();
+        assert_eq!(page.rotate, Some(270));
+        assert_eq!(page.effective_rotation(&resolver).unwrap(), 270);
+        page.set_rotation(540).unwrap();
+        assert_eq!(page.effective_rotation(&resolver).unwrap(), 180);
+        // overrides the rotation of the page tree
+        page.set_rotation(360).unwrap();
+        assert_eq!(page.rotate, Some(0));
+        assert_eq!(page.effective_rotation(&resolver).unwrap(), 0);
+
+        assert!(page.set_rotation(45).is_err());
+        assert_eq!(page.rotate, Some(0));
+    }
+
+    #[test]
+    fn set_crop_box() {
+        let data = document("", "/MediaBox [0 0 612 792]", &[""], &[]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let mut page = (*file.get_page(0).unwrap()).clone();
+        // without /CropBox, the media box is used
+        assert_eq!(page.crop_box().unwrap(), Rectangle::new(0., 0., 612., 792.));
+
+        let crop_box = page.set_crop_box(Rectangle::new(36., 36., 576., 756.)).unwrap();
+        assert_eq!(crop_box, Rectangle::new(36., 36., 576., 756.));
+        assert_eq!(page.crop_box().unwrap(), crop_box);
+
+        // clamped to the media box
+        let crop_box = page.set_crop_box(Rectangle::new(-10., 100., 700., 900.)).unwrap();
+        assert_eq!(crop_box, Rectangle::new(0., 100., 612., 792.));
+        assert_eq!(page.crop_box, Some(crop_box));
+
+        assert!(page.set_crop_box(Rectangle::new(700., 0., 800., 100.)).is_err());
+        assert_eq!(page.crop_box, Some(crop_box));
+    }
+
+    #[test]
+    fn text_stamp() {
+        use crate::build::{CatalogBuilder, Importer, PageBuilder};
+        use crate::writer::PdfWriter;
+
+        // leaves a transformation behind
+        let content = "1 0 0 1 100 100 cm BT /F1 12 Tf 72 700 Td (Hello) Tj ET";
+        let data = document("", "/Resources << /Font << /F1 5 0 R /F3 6 0 R /F4 7 0 R >> >>", &["/MediaBox [0 0 612 792] /Contents 4 0 R"], &[
+            &format!("<< /Length {} >>\nstream\n{}\nendstream", content.len(), content),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Times-Roman >>",
+            // uses the built-in encoding, not WinAnsi
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>",
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>",
+        ]);
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let mut page = (*file.get_page(0).unwrap()).clone();
+
+        page.add_text_stamp("DRAFT", (200., 400.), StandardFont::Helvetica, 48.).unwrap();
+        let parts = &page.contents.as_ref().unwrap().parts;
+        assert_eq!(parts.len(), 3);
+        assert_eq!(&*parts[0].data(&resolver).unwrap(), b"q\n");
+        assert_eq!(&*parts[1].data(&resolver).unwrap(), content.as_bytes());
+        let stamp = String::from_utf8(parts[2].data(&resolver).unwrap().to_vec()).unwrap();
+        assert_eq!(stamp, "Q\nq\nBT\n/F2 48 Tf\n200 400 Td\n(DRAFT) Tj\nET\nQ\n");
+        // the inherited resources are copied to the page
+        let fonts = &page.resources.as_ref().unwrap().fonts;
+        assert_eq!(fonts.len(), 4);
+        assert_eq!(fonts["F2"].name.as_ref().unwrap().as_str(), "Helvetica");
+
+        // the font is only added once
+        page.add_text_stamp("caf\u{e9}", (0., 0.), StandardFont::Helvetica, 12.).unwrap();
+        assert_eq!(page.resources.as_ref().unwrap().fonts.len(), 4);
+        assert!(page.add_text_stamp("\u{4e2d}", (0., 0.), StandardFont::Helvetica, 12.).is_err());
+        // a WinAnsi font of the page is reused
+        page.add_text_stamp("x", (0., 200.), StandardFont::Courier, 12.).unwrap();
+        assert_eq!(page.resources.as_ref().unwrap().fonts.len(), 4);
+        let parts = &page.contents.as_ref().unwrap().parts;
+        assert!(parts.last().unwrap().data(&resolver).unwrap().windows(7).any(|w| w == b"/F4 12 "));
+
+        // the stamps are drawn without the transformation of the content, and q/Q stay balanced
+        let ops = page.contents.as_ref().unwrap().operations(&resolver).unwrap();
+        let (mut transformed, mut saved) = (false, vec![]);
+        for op in ops.iter() {
+            match *op {
+                Op::Save => saved.push(transformed),
+                Op::Restore => transformed = saved.pop().unwrap(),
+                Op::Transform { .. } => transformed = true,
+                Op::TextFont { ref name, .. } => assert_eq!(transformed, name == "F1"),
+                _ => {}
+            }
+        }
+        assert!(saved.is_empty());
+
+        let mut writer = PdfWriter::new(Vec::new()).unwrap();
+        let page = PageBuilder::clone_page(&page, &mut Importer::new(file.resolver(), &mut writer)).unwrap();
+        let catalog = CatalogBuilder::from_pages(vec![page]).build(&mut writer).unwrap();
+        let root = writer.write_object(&catalog).unwrap();
+        let file = FileOptions::uncached().load(writer.finish(root).unwrap()).unwrap();
+        let text = file.get_page(0).unwrap().extract_text(&file.resolver()).unwrap();
+        assert_eq!(text, "Hello\nDRAFT\ncaf\u{e9}\nx");
+    }
 }
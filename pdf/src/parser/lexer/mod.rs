@@ -12,6 +12,27 @@ mod str;
 pub use self::str::{StringLexer, HexStringLexer};
 
 
+/// What kind of token `Lexer::next_token` found.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Integer,
+    Real,
+    /// `/Name`, including the slash
+    Name,
+    /// `(string)`, including the parentheses
+    LiteralString,
+    /// `<hex>`, including the angle brackets
+    HexString,
+    ArrayStart,
+    ArrayEnd,
+    DictStart,
+    DictEnd,
+    /// Any other regular word, like `obj`, `R`, `true` or a content stream operator.
+    Keyword,
+    /// A delimiter that doesn't start a token on its own, like `{` or a stray `)`.
+    Other,
+}
+
 /// `Lexer` has functionality to jump around and traverse the PDF lexemes of a string in any direction.
 #[derive(Copy, Clone)]
 #[allow(dead_code)]
@@ -39,9 +60,10 @@ fn boundary(data: &[u8], pos: usize, condition: impl Fn(u8) -> bool) -> usize {
     }
 }
 
+/// The white-space characters of PDF: NUL, tab, line feed, form feed, carriage return and space.
 #[inline]
-fn is_whitespace(b: u8) -> bool {
-    matches!(b, 0 | b' ' | b'\r' | b'\n' | b'\t')
+pub fn is_whitespace(b: u8) -> bool {
+    matches!(b, 0 | b' ' | b'\r' | b'\n' | b'\t' | b'\x0c')
 }
 #[inline]
 fn not<T>(f: impl Fn(T) -> bool) -> impl Fn(T) -> bool {
@@ -71,6 +93,42 @@ impl<'a> Lexer<'a> {
         Ok(lexeme)
     }
 
+    /// Returns the next token and its kind. Unlike `next`, strings are returned as a single token.
+    pub fn next_token(&mut self) -> Result<(Substr<'a>, TokenKind)> {
+        let word = self.next()?;
+        let start = self.pos - word.len();
+        let kind = match word.as_slice() {
+            b"[" => TokenKind::ArrayStart,
+            b"]" => TokenKind::ArrayEnd,
+            b"<<" => TokenKind::DictStart,
+            b">>" => TokenKind::DictEnd,
+            b"(" => {
+                let mut string_lexer = StringLexer::new(self.get_remaining_slice());
+                for c in string_lexer.iter() {
+                    c?;
+                }
+                let len = string_lexer.get_offset();
+                self.offset_pos(len);
+                TokenKind::LiteralString
+            }
+            b"<" => {
+                let mut hex_lexer = HexStringLexer::new(self.get_remaining_slice());
+                for b in hex_lexer.iter() {
+                    b?;
+                }
+                let len = hex_lexer.get_offset();
+                self.offset_pos(len);
+                TokenKind::HexString
+            }
+            [b'/', ..] => TokenKind::Name,
+            _ if word.is_integer() => TokenKind::Integer,
+            _ if word.real_number().map(|r| r.len()) == Some(word.len()) => TokenKind::Real,
+            [b, ..] if self.is_delimiter(start) || !b.is_ascii_graphic() => TokenKind::Other,
+            _ => TokenKind::Keyword,
+        };
+        Ok((self.new_substr(start .. self.pos), kind))
+    }
+
     /// consume the whitespace sequence following the stream start
     pub fn next_stream(&mut self) -> Result<()> {
         let pos = self.skip_whitespace(self.pos)?;
@@ -394,49 +452,37 @@ impl<'a> Substr<'a> {
         std::str::from_utf8(self.slice)?.parse::<T>().map_err(|e| PdfError::Parse { source: e.into() })
     }
     pub fn is_integer(&self) -> bool {
-        if self.slice.len() == 0 {
-            return false;
-        }
-        let mut slice = self.slice;
-        if slice[0] == b'-' {
-            if slice.len() < 2 {
-                return false;
-            }
-            slice = &slice[1..];
+        match self.slice {
+            [b'+' | b'-', digits @ ..] | digits => !digits.is_empty() && is_int(digits)
         }
-        is_int(slice)
     }
     pub fn is_real_number(&self) -> bool {
         self.real_number().is_some()
     }
+    /// The prefix of this lexeme that is a real number, if it is one.
+    ///
+    /// PDF reals are a sign, digits and an optional fractional part: `.5`, `4.`, `+.002` and
+    /// `-0` are fine, exponents are not. Everything from a second dot on is ignored, so
+    /// `34.5.6` reads as `34.5`, like other readers do.
     pub fn real_number(&self) -> Option<Self> {
-        if self.slice.len() == 0 {
-            return None;
-        }
-        let mut slice = self.slice;
-        if slice[0] == b'-' {
-            if slice.len() < 2 {
-                return None;
-            }
-            slice = &slice[1..];
+        let slice = self.slice;
+        let sign = matches!(slice.first(), Some(b'+' | b'-')) as usize;
+        let int_digits = slice[sign..].iter().take_while(|b| b.is_ascii_digit()).count();
+        let mut end = sign + int_digits;
+        let mut frac_digits = 0;
+        if slice.get(end) == Some(&b'.') {
+            frac_digits = slice[end + 1..].iter().take_while(|b| b.is_ascii_digit()).count();
+            end += 1 + frac_digits;
         }
-        if let Some(i) = slice.iter().position(|&b| b == b'.') {
-            if !is_int(&slice[..i]) {
-                return None;
-            }
-            slice = &slice[i+1..];
+        if int_digits + frac_digits == 0 {
+            return None;
         }
-        if let Some(len) = slice.iter().position(|&b| !b.is_ascii_digit()) {
-            if len == 0 {
-                return None;
-            }
-            let end = self.slice.len() - slice.len() + len;
-            Some(Substr {
+        match slice.get(end) {
+            None | Some(b'.') => Some(Substr {
                 file_offset: self.file_offset,
-                slice: &self.slice[..end]
-            })
-        } else {
-            Some(*self)
+                slice: &slice[..end]
+            }),
+            _ => None
         }
     }
 
@@ -502,6 +548,37 @@ mod tests {
         assert_eq!(boundary(b"01234  7orld", 5, is_whitespace), 7);
         assert_eq!(boundary(b"01234  7orld", 7, is_whitespace), 7);
         assert_eq!(boundary(b"q\n", 1, is_whitespace), 2);
+        // form feed and NUL are white-space too
+        assert_eq!(boundary(b"a\x0c\x00b", 1, is_whitespace), 3);
+    }
+
+    #[test]
+    fn tokens() {
+        let mut lexer = Lexer::new(b"1 0 obj % comment\n<< /Type /Page /Kids [2 0 R] /V -.5 /S (a (b) \\) c) /H <41 42> /N null >>\nendobj");
+        let mut tokens = vec![];
+        loop {
+            match lexer.next_token() {
+                Ok((s, kind)) => tokens.push((s.to_string(), kind)),
+                Err(PdfError::EOF) => break,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        use TokenKind::*;
+        let expected = vec![
+            ("1", Integer), ("0", Integer), ("obj", Keyword),
+            ("<<", DictStart), ("/Type", Name), ("/Page", Name),
+            ("/Kids", Name), ("[", ArrayStart), ("2", Integer), ("0", Integer), ("R", Keyword), ("]", ArrayEnd),
+            ("/V", Name), ("-.5", Real),
+            ("/S", Name), (r"(a (b) \) c)", LiteralString),
+            ("/H", Name), ("<41 42>", HexString),
+            ("/N", Name), ("null", Keyword),
+            (">>", DictEnd), ("endobj", Keyword),
+        ];
+        let expected: Vec<_> = expected.into_iter().map(|(s, k)| (s.to_string(), k)).collect();
+        assert_eq!(tokens, expected);
+
+        let mut lexer = Lexer::new(b"{ 1 }");
+        assert_eq!(lexer.next_token().unwrap().1, TokenKind::Other);
     }
 
     #[test]
@@ -513,5 +590,28 @@ mod tests {
         assert!(Substr::new("-.45", 0).is_real_number());
         assert!(!Substr::new("123.45", 0).is_integer());
         assert!(Substr::new("123", 0).is_integer());
+        assert!(Substr::new("+7", 0).is_integer());
+        assert!(!Substr::new("-", 0).is_integer());
+    }
+
+    #[test]
+    fn loose_reals() {
+        let real = |s: &str| Substr::new(s, 0).real_number().map(|r| r.to::<f32>().unwrap());
+        assert_eq!(real(".5"), Some(0.5));
+        assert_eq!(real("4."), Some(4.));
+        assert_eq!(real("+.002"), Some(0.002));
+        assert_eq!(real("-0"), Some(0.));
+        assert_eq!(real("-.45"), Some(-0.45));
+        assert_eq!(real("34.5.6"), Some(34.5));
+        assert_eq!(real("1e5"), None);
+        assert_eq!(real("1.5E-3"), None);
+        assert_eq!(real("."), None);
+        assert_eq!(real("-"), None);
+        assert_eq!(real("+-1"), None);
+
+        use crate::primitive::Primitive;
+        assert_eq!(Primitive::parse(b"3000000001").unwrap().0, Primitive::Integer(3000000001));
+        assert!(Primitive::parse(b"99999999999999999999").is_err());
+        assert_eq!(Primitive::parse(b"+12").unwrap().0, Primitive::Integer(12));
     }
 }
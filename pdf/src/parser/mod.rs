@@ -16,7 +16,7 @@ use crate::crypt::Decoder;
 use bitflags::bitflags;
 use istring::{SmallBytes, SmallString, IBytes};
 
-const MAX_DEPTH: usize = 20;
+pub(crate) const MAX_DEPTH: usize = 20;
 
 
 bitflags! {
@@ -45,7 +45,7 @@ pub struct Context<'a> {
 impl<'a> Context<'a> {
     pub fn decrypt<'buf>(&self, data: &'buf mut [u8]) -> Result<&'buf [u8]> {
         if let Some(decoder) = self.decoder {
-            decoder.decrypt(self.id, data)
+            decoder.decrypt_string(self.id, data)
         } else {
             Ok(data)
         }
@@ -65,6 +65,14 @@ pub fn parse(data: &[u8], r: &impl Resolve, flags: ParseFlags) -> Result<Primiti
     parse_with_lexer(&mut Lexer::new(data), r, flags)
 }
 
+/// Integers that do not fit an `i64` are an error, rather than being read as inexact reals.
+fn parse_integer(lexeme: Substr) -> Result<Primitive> {
+    match lexeme.to::<i64>() {
+        Ok(i) => Ok(Primitive::Integer(i)),
+        Err(_) => bail!("integer {} is out of range", lexeme.to_string()),
+    }
+}
+
 /// Recursive. Can parse stream but only if its dictionary does not contain indirect references.
 /// Use `parse_stream` if this is not sufficient.
 pub fn parse_with_lexer(lexer: &mut Lexer, r: &impl Resolve, flags: ParseFlags) -> Result<Primitive> {
@@ -89,26 +97,45 @@ fn parse_dictionary_object(lexer: &mut Lexer, r: &impl Resolve, ctx: Option<&Con
     Ok(dict)
 }
 
-fn parse_stream_object(dict: Dictionary, lexer: &mut Lexer, r: &impl Resolve, ctx: &Context) -> Result<PdfStream> {
+fn parse_stream_object(mut dict: Dictionary, lexer: &mut Lexer, r: &impl Resolve, ctx: &Context) -> Result<PdfStream> {
     t!(lexer.next_stream());
+    let start = lexer.get_pos();
 
+    // An indirect length often follows the stream, and may be missing or wrong.
     let length = match dict.get("Length") {
-        Some(&Primitive::Integer(n)) if n >= 0 => n as usize,
-        Some(&Primitive::Reference(reference)) => t!(t!(r.resolve_flags(reference, ParseFlags::INTEGER, 1)).as_usize()),
-        Some(other) => err!(PdfError::UnexpectedPrimitive { expected: "unsigned Integer or Reference", found: other.get_debug_name() }),
+        Some(&Primitive::Integer(n)) if n >= 0 => Ok(n as usize),
+        Some(&Primitive::Reference(reference)) => r.resolve_flags(reference, ParseFlags::INTEGER, 1).and_then(|p| p.as_usize()),
+        Some(other) => err!(PdfError::UnexpectedPrimitive { expected: "unsigned Integer or Reference", found: other.type_name() }),
         None => err!(PdfError::MissingEntry { typ: "<Stream>", field: "Length".into() }),
     };
 
-    let stream_substr = lexer.read_n(length);
-
-    if stream_substr.len() != length {
-        err!(PdfError::EOF)
-    }
-
-    // Finish
-    t!(lexer.next_expect("endstream"));
+    // The length is only trusted if `endstream` follows.
+    let declared = length.and_then(|length| {
+        let substr = lexer.read_n(length);
+        if substr.len() != length {
+            return Err(PdfError::EOF);
+        }
+        lexer.next_expect("endstream")?;
+        Ok(substr)
+    });
+    let stream_substr = match declared {
+        Ok(substr) => substr,
+        Err(e) if r.options().allow_wrong_length => {
+            lexer.set_pos(start);
+            let found = find_endstream(lexer.get_remaining_slice())
+                .ok_or_else(|| PdfError::NotFound { word: "endstream".into() })?;
+            warn!("stream {} {}: wrong /Length ({:?}), endstream follows after {} bytes", ctx.id.id, ctx.id.gen, e, found);
+            let substr = lexer.read_n(found);
+            t!(lexer.next_expect("endstream"));
+            substr
+        }
+        Err(e) => return Err(e),
+    };
+    // readers of the dictionary get the length that was actually used
+    dict.insert("Length", Primitive::Integer(stream_substr.len() as i64));
 
     Ok(PdfStream {
+        keep_uncompressed: false,
         inner: StreamInner::InFile {
             id: ctx.id,
             file_range: stream_substr.file_range(),
@@ -117,6 +144,20 @@ fn parse_stream_object(dict: Dictionary, lexer: &mut Lexer, r: &impl Resolve, ct
     })
 }
 
+/// Length of the stream data in `data`, which starts right after the `stream` keyword.
+/// The end of line before `endstream` is not part of the data.
+fn find_endstream(data: &[u8]) -> Option<usize> {
+    let end = data.windows(9).position(|w| w == b"endstream")?;
+    let data = &data[..end];
+    Some(if data.ends_with(b"\r\n") {
+        end - 2
+    } else if data.ends_with(b"\n") || data.ends_with(b"\r") {
+        end - 1
+    } else {
+        end
+    })
+}
+
 #[inline]
 fn check(flags: ParseFlags, allowed: ParseFlags) -> Result<(), PdfError> {
     if !flags.intersects(allowed) {
@@ -163,10 +204,17 @@ fn _parse_with_lexer_ctx(lexer: &mut Lexer, r: &impl Resolve, ctx: Option<&Conte
         // First backup position
         let pos_bk = lexer.get_pos();
 
-        let second_lexeme = t!(lexer.next());
-        if second_lexeme.is_integer() {
-            let third_lexeme = t!(lexer.next());
-            if third_lexeme.equals(b"R") {
+        // an integer may be the last token of the input
+        let second_lexeme = match lexer.next() {
+            Err(PdfError::EOF) => None,
+            r => Some(t!(r)),
+        };
+        if let Some(second_lexeme) = second_lexeme.filter(|l| l.is_integer()) {
+            let third_lexeme = match lexer.next() {
+                Err(PdfError::EOF) => None,
+                r => Some(t!(r)),
+            };
+            if third_lexeme.is_some_and(|l| l.equals(b"R")) {
                 // It is indeed a reference to an indirect object
                 check(flags, ParseFlags::REF)?;
                 Primitive::Reference (PlainRef {
@@ -177,18 +225,18 @@ fn _parse_with_lexer_ctx(lexer: &mut Lexer, r: &impl Resolve, ctx: Option<&Conte
                 check(flags, ParseFlags::INTEGER)?;
                 // We are probably in an array of numbers - it's not a reference anyway
                 lexer.set_pos(pos_bk); // (roll back the lexer first)
-                Primitive::Integer(t!(first_lexeme.to::<i32>()))
+                parse_integer(first_lexeme)?
             }
         } else {
             check(flags, ParseFlags::INTEGER)?;
             // It is but a number
             lexer.set_pos(pos_bk); // (roll back the lexer first)
-            Primitive::Integer(t!(first_lexeme.to::<i32>()))
+            parse_integer(first_lexeme)?
         }
     } else if let Some(s) = first_lexeme.real_number() {
         check(flags, ParseFlags::NUMBER)?;
         // Real Number
-        Primitive::Number (t!(s.to::<f32>(), s.to_string()))
+        Primitive::Number (t!(s.to::<f64>(), s.to_string()))
     } else if first_lexeme.starts_with(b"/") {
         check(flags, ParseFlags::NAME)?;
         // Name
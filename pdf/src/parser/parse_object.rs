@@ -1,7 +1,7 @@
 // Considering whether to impl Object and IndirectObject here.
 //
 
-use crate::parser::{lexer::*, MAX_DEPTH};
+use crate::parser::lexer::*;
 use crate::error::*;
 use crate::primitive::{Primitive, PdfStream};
 use crate::parser::{parse_with_lexer_ctx, parse_stream_with_lexer, Context, ParseFlags};
@@ -23,7 +23,7 @@ pub fn parse_indirect_object(lexer: &mut Lexer, r: &impl Resolve, decoder: Optio
         decoder,
         id,
     };
-    let obj = t!(parse_with_lexer_ctx(lexer, r, Some(&ctx), flags, MAX_DEPTH));
+    let obj = t!(parse_with_lexer_ctx(lexer, r, Some(&ctx), flags, r.options().max_depth));
 
     if r.options().allow_missing_endobj {
         let pos = lexer.get_pos();
@@ -36,7 +36,8 @@ fn parse_xref_section_from_stream(first_id: u32, mut num_entries: usize, width:
             0 => XRef::Free {next_obj_nr: field1 as ObjNr, gen_nr: field2 as GenNr},
             1 => XRef::Raw {pos: field1 as usize, gen_nr: field2 as GenNr},
             2 => XRef::Stream {stream_id: field1 as ObjNr, index: field2 as usize},
-            _ => return Err(PdfError::XRefStreamType {found: _type}), // TODO: Should actually just be seen as a reference to the null object
+            // other types are reserved and shall be treated as a reference to the null object
+            _ => XRef::Invalid,
         };
         entries.push(entry);
     }
@@ -136,3 +137,68 @@ pub fn read_xref_and_trailer_at(lexer: &mut Lexer, resolve: &impl Resolve) -> Re
         parse_xref_stream_and_trailer(lexer, resolve)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xref::XRefTable;
+    use crate::enc::{flate_decode, LZWFlateParams};
+
+    #[test]
+    fn xref_stream_sections() {
+        // /W [1 2 1] /Index [0 3 10 2], rows encoded with the PNG Up predictor
+        let zlib = [
+            0x78, 0x9c, 0x63, 0x62, 0x60, 0x60, 0xf8, 0xcf, 0xc4, 0xc8, 0xc0, 0xcf, 0x08, 0x24, 0x7e, 0x33,
+            0x30, 0xfd, 0x67, 0xfc, 0xc6, 0xc0, 0xc4, 0xf8, 0x9f, 0x8b, 0x11, 0x00, 0x35, 0x5d, 0x05, 0x18
+        ];
+        let params = LZWFlateParams { predictor: 12, columns: 4, .. LZWFlateParams::default() };
+        let data = flate_decode(&zlib, &params).unwrap();
+        let mut data = &data[..];
+        let width = [1, 2, 1];
+
+        let mut table = XRefTable::new(12);
+        for &(first_id, n) in &[(0, 3), (10, 2)] {
+            let section = parse_xref_section_from_stream(first_id, n, &width, &mut data, &NoResolve).unwrap();
+            assert_eq!(section.entries.len(), n);
+            table.add_entries_from(section).unwrap();
+        }
+        assert!(data.is_empty());
+        assert!(matches!(table.get(0).unwrap(), XRef::Free { next_obj_nr: 0, gen_nr: 255 }));
+        assert!(matches!(table.get(1).unwrap(), XRef::Raw { pos: 15, gen_nr: 0 }));
+        assert!(matches!(table.get(2).unwrap(), XRef::Stream { stream_id: 10, index: 0 }));
+        assert!(matches!(table.get(5).unwrap(), XRef::Invalid));
+        assert!(matches!(table.get(10).unwrap(), XRef::Raw { pos: 256, gen_nr: 0 }));
+        assert!(matches!(table.get(11).unwrap(), XRef::Stream { stream_id: 10, index: 1 }));
+
+        // reserved types are treated as null references, a missing type field means type 1
+        let section = parse_xref_section_from_stream(0, 2, &[1, 1, 0], &mut &[7, 1, 1, 2][..], &NoResolve).unwrap();
+        assert!(matches!(section.entries[0], XRef::Invalid));
+        let section = parse_xref_section_from_stream(0, 1, &[0, 1, 1], &mut &[9, 0][..], &NoResolve).unwrap();
+        assert!(matches!(section.entries[0], XRef::Raw { pos: 9, gen_nr: 0 }));
+    }
+
+    #[test]
+    fn xref_table() {
+        // mixed line endings, and no newline after the trailer
+        let data = b"xref\r\n0 2\r\n0000000003 65535 f\r\n0000000017 00000 n\r\n\
+            5 2\n0000000000 00001 f \n0000081234 00002 n \n\
+            trailer\r<< /Size 7 /Root 1 0 R >>";
+        let mut lexer = Lexer::new(data);
+        let (sections, trailer) = read_xref_and_trailer_at(&mut lexer, &NoResolve).unwrap();
+        assert_eq!(trailer.get("Size"), Some(&Primitive::Integer(7)));
+        assert_eq!(sections.iter().map(|s| (s.first_id, s.entries.len())).collect::<Vec<_>>(), [(0, 2), (5, 2)]);
+
+        let mut table = XRefTable::new(7);
+        for section in sections {
+            table.add_entries_from(section).unwrap();
+        }
+        assert!(matches!(table.get(0).unwrap(), XRef::Free { next_obj_nr: 3, gen_nr: 65535 }));
+        assert!(matches!(table.get(1).unwrap(), XRef::Raw { pos: 17, gen_nr: 0 }));
+        assert!(matches!(table.get(2).unwrap(), XRef::Invalid));
+        assert!(matches!(table.get(5).unwrap(), XRef::Free { next_obj_nr: 0, gen_nr: 1 }));
+        assert!(matches!(table.get(6).unwrap(), XRef::Raw { pos: 81234, gen_nr: 2 }));
+
+        let truncated = b"xref\n0 3\n0000000000 65535 f\n0000000017 00000 n\ntrailer\n<< >>";
+        assert!(read_xref_and_trailer_at(&mut Lexer::new(truncated), &NoResolve).is_err());
+    }
+}
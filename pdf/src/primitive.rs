@@ -1,11 +1,12 @@
 use crate::error::*;
 use crate::object::{PlainRef, Resolve, Object, NoResolve, ObjectWrite, Updater, DeepClone, Cloner};
+use crate::parser::{Lexer, ParseFlags, parse_with_lexer};
 
 use std::sync::Arc;
 use std::{str, fmt, io};
 use std::ops::{Index, Range};
 use std::ops::Deref;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::borrow::{Borrow, Cow};
 use indexmap::IndexMap;
 use itertools::Itertools;
@@ -15,8 +16,8 @@ use datasize::DataSize;
 #[derive(Clone, Debug, PartialEq)]
 pub enum Primitive {
     Null,
-    Integer (i32),
-    Number (f32),
+    Integer (i64),
+    Number (f64),
     Boolean (bool),
     String (PdfString),
     Stream (PdfStream),
@@ -58,11 +59,69 @@ impl fmt::Display for Primitive {
     }
 }
 impl Primitive {
+    /// Renders the primitive over multiple lines, for reading it while debugging.
+    ///
+    /// Every entry of a dictionary gets its own line. Arrays stay on one line unless they
+    /// contain dictionaries or arrays. Nested lines are indented by `indent` more spaces than
+    /// the line they are in. Use `serialize` to get the bytes for a file.
+    pub fn pretty(&self, indent: usize) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, indent, 0);
+        out
+    }
+    fn write_pretty(&self, out: &mut String, indent: usize, level: usize) {
+        use std::fmt::Write;
+        let pad = |out: &mut String, level: usize| out.extend(std::iter::repeat_n(' ', indent * level));
+        match self {
+            Primitive::Dictionary(ref dict) => {
+                out.push_str("<<\n");
+                for (key, value) in dict.iter() {
+                    pad(out, level + 1);
+                    let _ = write!(out, "{} ", key);
+                    value.write_pretty(out, indent, level + 1);
+                    out.push('\n');
+                }
+                pad(out, level);
+                out.push_str(">>");
+            }
+            Primitive::Stream(ref stream) => {
+                Primitive::Dictionary(stream.info.clone()).write_pretty(out, indent, level);
+                match stream.inner {
+                    StreamInner::InFile { ref file_range, .. } => { let _ = write!(out, " stream ({} bytes in file)", file_range.len()); }
+                    StreamInner::Pending { ref data } => { let _ = write!(out, " stream ({} bytes)", data.len()); }
+                }
+            }
+            Primitive::Array(ref arr) if arr.iter().any(|p| matches!(p, Primitive::Array(_) | Primitive::Dictionary(_) | Primitive::Stream(_))) => {
+                out.push_str("[\n");
+                for p in arr {
+                    pad(out, level + 1);
+                    p.write_pretty(out, indent, level + 1);
+                    out.push('\n');
+                }
+                pad(out, level);
+                out.push(']');
+            }
+            Primitive::Array(ref arr) => {
+                out.push('[');
+                for (i, p) in arr.iter().enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    p.write_pretty(out, indent, level);
+                }
+                out.push(']');
+            }
+            Primitive::Reference(r) => { let _ = write!(out, "{} {} R", r.id, r.gen); }
+            Primitive::String(ref s) => { let _ = write!(out, "{:?}", s); }
+            p => { let _ = write!(out, "{}", p); }
+        }
+    }
+
     pub fn serialize(&self, out: &mut impl io::Write) -> Result<()> {
         match self {
             Primitive::Null => write!(out, "null")?,
             Primitive::Integer(i) => write!(out, "{}", i)?,
-            Primitive::Number(n) => write!(out, "{}", n)?,
+            Primitive::Number(n) => write_real(out, *n)?,
             Primitive::Boolean(b) => write!(out, "{}", b)?,
             Primitive::String(ref s) => s.serialize(out)?,
             Primitive::Stream(ref s) => s.serialize(out)?,
@@ -73,6 +132,15 @@ impl Primitive {
         }
         Ok(())
     }
+    /// Parses one primitive from the start of `input`, and returns it with the number of bytes consumed.
+    ///
+    /// This is the inverse of `serialize`, for everything except streams.
+    /// References are not resolved.
+    pub fn parse(input: &[u8]) -> Result<(Primitive, usize)> {
+        let mut lexer = Lexer::new(input);
+        let p = parse_with_lexer(&mut lexer, &NoResolve, ParseFlags::ANY)?;
+        Ok((p, lexer.get_pos()))
+    }
     pub fn array<O, T, I, U>(i: I, update: &mut U) -> Result<Primitive>
         where O: ObjectWrite, I: Iterator<Item=T>,
         T: Borrow<O>, U: Updater
@@ -98,20 +166,60 @@ fn serialize_list(arr: &[Primitive], out: &mut impl io::Write) -> Result<()> {
     Ok(())
 }
 
+/// Writes `value` the way PDF expects reals: in plain decimal, without an exponent.
+///
+/// At most 17 fractional digits are written, which is enough for the shortest exact
+/// representation of an `f64` of magnitude 0.001 or more. A value that is exactly an `f32`,
+/// like the numbers of `Rectangle` or `Matrix`, is written as before reals were stored as
+/// `f64`: with at most 6 fractional digits, so `0.1f32` becomes `0.1` and not `0.10000000149`.
+/// Trailing zeros are left out. Infinity and NaN can't be represented and result in an error.
+pub fn write_real(out: &mut impl io::Write, value: f64) -> Result<()> {
+    if !value.is_finite() {
+        bail!("{} can't be written as a PDF real", value);
+    }
+    let s = match value as f32 {
+        single if single as f64 == value => plain_decimal(single, 6),
+        _ => plain_decimal(value, 17),
+    };
+    out.write_all(s.as_bytes())?;
+    Ok(())
+}
+
+/// `value` without an exponent, and with at most `max_fraction` fractional digits.
+fn plain_decimal<F: fmt::Display>(value: F, max_fraction: usize) -> String {
+    // `Display` never uses an exponent, but may write a long fraction
+    let mut s = value.to_string();
+    if let Some(dot) = s.find('.') {
+        if s.len() - dot - 1 > max_fraction {
+            s = format!("{:.*}", max_fraction, value);
+        }
+        let trimmed = s.trim_end_matches('0').trim_end_matches('.').len();
+        s.truncate(trimmed);
+    }
+    if s == "-0" {
+        s = "0".into();
+    }
+    s
+}
+
+/// Writes `/` and the name. Bytes that are not regular characters are written as `#XX`.
 pub fn serialize_name(s: &str, out: &mut impl io::Write) -> Result<()> {
     write!(out, "/")?;
-    for b in s.chars() {
+    for &b in s.as_bytes() {
         match b {
-            '\\' | '(' | ')' => write!(out, r"\")?,
-            c if c > '~' => panic!("only ASCII"),
-            _ => ()
+            b'#' | b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%' => write!(out, "#{:02X}", b)?,
+            b'!' ..= b'~' => out.write_all(&[b])?,
+            _ => write!(out, "#{:02X}", b)?,
         }
-        write!(out, "{}", b)?;
     }
     Ok(())
 }
 
 /// Primitive Dictionary type.
+///
+/// Entries keep the order in which they were inserted, and are written in that order.
+/// `remove` moves the last entry into the place of the removed one; use `remove_ordered` to
+/// keep the order of the others.
 #[derive(Default, Clone, PartialEq)]
 pub struct Dictionary {
     dict: IndexMap<Name, Primitive>
@@ -136,7 +244,12 @@ impl Dictionary {
         self.dict.iter()
     }
     pub fn remove(&mut self, key: &str) -> Option<Primitive> {
-        self.dict.remove(key)
+        self.dict.swap_remove(key)
+    }
+    /// like remove, but keeps the order of the remaining entries. This takes time linear in
+    /// the number of entries.
+    pub fn remove_ordered(&mut self, key: &str) -> Option<Primitive> {
+        self.dict.shift_remove(key)
     }
     /// like remove, but takes the name of the calling type and returns `PdfError::MissingEntry` if the entry is not found
     pub fn require(&mut self, typ: &'static str, key: &str) -> Result<Primitive> {
@@ -147,6 +260,20 @@ impl Dictionary {
             }
         )
     }
+    /// Converts the value of `key` to `T`, or returns `None` if the key is not present.
+    pub fn get_typed<T: Object>(&self, key: &str, resolve: &impl Resolve) -> Result<Option<T>> {
+        match self.get(key) {
+            Some(p) => T::from_primitive(p.clone(), resolve).map(Some),
+            None => Ok(None)
+        }
+    }
+    /// like `get_typed`, but returns `PdfError::MissingEntry` if the entry is not found
+    pub fn require_typed<T: Object>(&self, typ: &'static str, key: &str, resolve: &impl Resolve) -> Result<T> {
+        match self.get(key) {
+            Some(p) => T::from_primitive(p.clone(), resolve),
+            None => Err(PdfError::MissingEntry { typ, field: key.into() })
+        }
+    }
     /// assert that the given key/value pair is in the dictionary (`required=true`),
     /// or the key is not present at all (`required=false`)
     pub fn expect(&self, typ: &'static str, key: &str, value: &str, required: bool) -> Result<()> {
@@ -170,6 +297,21 @@ impl Dictionary {
     pub fn append(&mut self, other: Dictionary) {
         self.dict.extend(other.dict);
     }
+    /// Inserts all entries of `other`, replacing existing ones.
+    ///
+    /// Values are replaced as a whole, nested dictionaries are not merged.
+    /// Replaced entries keep their position, new entries are added at the end.
+    pub fn merge(&mut self, other: &Dictionary) {
+        for (key, value) in other.iter() {
+            self.dict.insert(key.clone(), value.clone());
+        }
+    }
+    /// Inserts the entries of `other` whose keys are missing, like inherited page attributes.
+    pub fn merge_missing(&mut self, other: &Dictionary) {
+        for (key, value) in other.iter() {
+            self.dict.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+    }
 }
 impl DataSize for Dictionary {
     const IS_DYNAMIC: bool = true;
@@ -250,6 +392,8 @@ impl<'a> IntoIterator for &'a Dictionary {
 pub struct PdfStream {
     pub info: Dictionary,
     pub (crate) inner: StreamInner,
+    /// Not to be compressed by `PdfWriter::compress_streams`
+    pub (crate) keep_uncompressed: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, DataSize)]
@@ -262,7 +406,7 @@ impl Object for PdfStream {
         match p {
             Primitive::Stream (stream) => Ok(stream),
             Primitive::Reference (r) => PdfStream::from_primitive(resolve.resolve(r)?, resolve),
-            p => Err(PdfError::UnexpectedPrimitive {expected: "Stream", found: p.get_debug_name()})
+            p => Err(PdfError::UnexpectedPrimitive {expected: "Stream", found: p.type_name()})
         }
     }
 }
@@ -272,18 +416,34 @@ impl ObjectWrite for PdfStream {
     }
 }
 impl PdfStream {
+    /// A stream with the already encoded `data`. `/Length` is set when it is written.
+    pub fn new(info: Dictionary, data: impl Into<Arc<[u8]>>) -> Self {
+        PdfStream { info, inner: StreamInner::Pending { data: data.into() }, keep_uncompressed: false }
+    }
+    /// Excludes the stream from `PdfWriter::compress_streams`.
+    pub fn keep_uncompressed(mut self) -> Self {
+        self.keep_uncompressed = true;
+        self
+    }
+    /// Writes the stream. `/Length` is always the length of the data, whatever `info` says.
     pub fn serialize(&self, out: &mut impl io::Write) -> Result<()> {
-        self.info.serialize(out)?;
-
-        writeln!(out, "stream")?;
-        match self.inner {
+        let data = match self.inner {
             StreamInner::InFile { .. } => {
                 unimplemented!()
             }
-            StreamInner::Pending { ref data } => {
-                out.write_all(data)?;
-            }
+            StreamInner::Pending { ref data } => data,
+        };
+        let length = Primitive::Integer(data.len() as _);
+        if self.info.get("Length") == Some(&length) {
+            self.info.serialize(out)?;
+        } else {
+            let mut info = self.info.clone();
+            info.insert("Length", length);
+            info.serialize(out)?;
         }
+
+        writeln!(out, "stream")?;
+        out.write_all(data)?;
         writeln!(out, "\nendstream")?;
         Ok(())
     }
@@ -301,7 +461,8 @@ impl DeepClone for PdfStream {
             StreamInner::Pending { ref data } => data.clone()
         };
         Ok(PdfStream {
-            info: self.info.deep_clone(cloner)?, inner: StreamInner::Pending { data }
+            info: self.info.deep_clone(cloner)?, inner: StreamInner::Pending { data },
+            keep_uncompressed: self.keep_uncompressed,
         })
     }
 }
@@ -406,7 +567,7 @@ impl Object for PdfString {
         match p {
             Primitive::String (string) => Ok(string),
             Primitive::Reference(id) => PdfString::from_primitive(r.resolve(id)?, &NoResolve),
-            _ => unexpected_primitive!(String, p.get_debug_name()),
+            _ => unexpected_primitive!(String, p.type_name()),
         }
     }
 }
@@ -418,7 +579,8 @@ impl ObjectWrite for PdfString {
 
 impl PdfString {
     pub fn serialize(&self, out: &mut impl io::Write) -> Result<()> {
-        if self.data.iter().any(|&b| b >= 0x80) {
+        // anything that would not survive a literal string unchanged is written as hex
+        if self.data.iter().any(|&b| !matches!(b, b' ' ..= b'~' | b'\n' | b'\t')) {
             write!(out, "<")?;
             for &b in self.data.as_slice() {
                 write!(out, "{:02x}", b)?;
@@ -426,10 +588,10 @@ impl PdfString {
             write!(out, ">")?;
         } else {
             write!(out, r"(")?;
-            for &b in self.data.as_slice() {
-                match b {
-                    b'\\' | b'(' | b')' => write!(out, r"\")?,
-                    _ => ()
+            let escape = paren_escapes(&self.data);
+            for (&b, esc) in self.data.iter().zip(escape) {
+                if b == b'\\' || esc {
+                    write!(out, r"\")?;
                 }
                 out.write_all(&[b])?;
             }
@@ -438,6 +600,24 @@ impl PdfString {
         Ok(())
     }
 }
+/// Balanced parentheses may appear unescaped in a literal string.
+/// Returns for each byte whether it is a parenthesis that needs a backslash.
+fn paren_escapes(data: &[u8]) -> Vec<bool> {
+    let mut escape = vec![false; data.len()];
+    let mut open = vec![];
+    for (i, &b) in data.iter().enumerate() {
+        match b {
+            b'(' => open.push(i),
+            b')' if open.pop().is_none() => escape[i] = true,
+            _ => ()
+        }
+    }
+    for i in open {
+        escape[i] = true;
+    }
+    escape
+}
+
 impl AsRef<[u8]> for PdfString {
     fn as_ref(&self) -> &[u8] {
         self.as_bytes()
@@ -450,12 +630,44 @@ impl PdfString {
             data
         }
     }
+    /// Encode a text string. ASCII is stored as-is, anything else as UTF-16BE with a byte order mark.
+    pub fn from_text(text: &str) -> PdfString {
+        if text.is_ascii() {
+            return PdfString::from(text);
+        }
+        let mut data = vec![0xfe, 0xff];
+        for unit in text.encode_utf16() {
+            data.extend_from_slice(&unit.to_be_bytes());
+        }
+        PdfString { data: data.into() }
+    }
     pub fn as_bytes(&self) -> &[u8] {
         &self.data
     }
     pub fn into_bytes(self) -> IBytes {
         self.data
     }
+    /// Like `to_string_lossy`, but borrows when the data is valid UTF-8.
+    pub fn as_str_lossy(&self) -> Cow<'_, str> {
+        if self.data.starts_with(&[0xfe, 0xff]) {
+            Cow::Owned(crate::font::utf16be_to_string_lossy(&self.data[2..]))
+        } else {
+            String::from_utf8_lossy(&self.data)
+        }
+    }
+    /// Decodes a text string, like `/Title` or an outline title.
+    ///
+    /// These are UTF-16BE or UTF-8 with a byte order mark, or PDFDocEncoding otherwise.
+    /// Invalid data is replaced with U+FFFD.
+    pub fn to_text(&self) -> String {
+        if let Some(data) = self.data.strip_prefix(&[0xfe, 0xff]) {
+            crate::font::utf16be_to_string_lossy(data)
+        } else if let Some(data) = self.data.strip_prefix(&[0xef, 0xbb, 0xbf]) {
+            String::from_utf8_lossy(data).into()
+        } else {
+            crate::text::pdfdoc_to_utf8(&self.data)
+        }
+    }
     /// without encoding information the PdfString cannot be decoded into a String
     /// therefore only lossy decoding is possible replacing unknown characters.
     /// For decoding correctly see
@@ -487,13 +699,26 @@ impl<'a> From<&'a str> for PdfString {
         PdfString { data: value.into() }
     }
 }
+/// The bytes are kept exactly, use this for binary strings like `/ID` entries.
+///
+/// (`Vec<u8>` itself reads and writes an array of integers.)
+impl From<Vec<u8>> for PdfString {
+    fn from(value: Vec<u8>) -> Self {
+        PdfString { data: value.into() }
+    }
+}
+impl From<PdfString> for Vec<u8> {
+    fn from(value: PdfString) -> Self {
+        value.data.as_slice().to_vec()
+    }
+}
 
 // TODO:
 // Noticed some inconsistency here.. I think to_* and as_* should not take Resolve, and not accept
 // Reference. Only from_primitive() for the respective type resolves References.
 impl Primitive {
     /// For debugging / error messages: get the name of the variant
-    pub fn get_debug_name(&self) -> &'static str {
+    pub fn type_name(&self) -> &'static str {
         match *self {
             Primitive::Null => "Null",
             Primitive::Integer (..) => "Integer",
@@ -507,6 +732,10 @@ impl Primitive {
             Primitive::Name (..) => "Name",
         }
     }
+    #[deprecated(note="use `type_name`")]
+    pub fn get_debug_name(&self) -> &'static str {
+        self.type_name()
+    }
     /// resolve the primitive if it is a refernce, otherwise do nothing
     pub fn resolve(self, r: &impl Resolve) -> Result<Primitive> {
         match self {
@@ -515,91 +744,116 @@ impl Primitive {
         }
     }
     pub fn as_integer(&self) -> Result<i32> {
-        match *self {
-            Primitive::Integer(n) => Ok(n),
-            ref p => unexpected_primitive!(Integer, p.get_debug_name())
-        }
+        self.as_int("i32")
     }
     pub fn as_u8(&self) -> Result<u8> {
-        match *self {
-            Primitive::Integer(n) if (0..256).contains(&n) => Ok(n as u8),
-            Primitive::Integer(_) => bail!("invalid integer"),
-            ref p => unexpected_primitive!(Integer, p.get_debug_name())
-        }
+        self.as_int("u8")
+    }
+    pub fn as_u16(&self) -> Result<u16> {
+        self.as_int("u16")
     }
     pub fn as_u32(&self) -> Result<u32> {
-        match *self {
-            Primitive::Integer(n) if n >= 0 => Ok(n as u32),
-            Primitive::Integer(_) => bail!("negative integer"),
-            ref p => unexpected_primitive!(Integer, p.get_debug_name())
-        }
+        self.as_int("u32")
     }
     pub fn as_usize(&self) -> Result<usize> {
+        self.as_int("usize")
+    }
+    pub fn as_i64(&self) -> Result<i64> {
+        self.as_int("i64")
+    }
+    pub fn as_u64(&self) -> Result<u64> {
+        self.as_int("u64")
+    }
+    fn as_int<T: TryFrom<i64>>(&self, typ: &'static str) -> Result<T> {
         match *self {
-            Primitive::Integer(n) if n >= 0 => Ok(n as usize),
-            Primitive::Integer(_) => bail!("negative integer"),
-            ref p => unexpected_primitive!(Integer, p.get_debug_name())
+            Primitive::Integer(n) => T::try_from(n).map_err(|_| PdfError::IntegerOutOfRange { value: n, typ }),
+            ref p => unexpected_primitive!(Integer, p.type_name())
         }
     }
     pub fn as_number(&self) -> Result<f32> {
         match *self {
             Primitive::Integer(n) => Ok(n as f32),
+            Primitive::Number(f) => Ok(f as f32),
+            ref p => unexpected_primitive!(Number, p.type_name())
+        }
+    }
+    /// Like `as_number`, but without losing precision.
+    pub fn as_f64(&self) -> Result<f64> {
+        match *self {
+            Primitive::Integer(n) => Ok(n as f64),
             Primitive::Number(f) => Ok(f),
-            ref p => unexpected_primitive!(Number, p.get_debug_name())
+            ref p => unexpected_primitive!(Number, p.type_name())
         }
     }
     pub fn as_bool(&self) -> Result<bool> {
         match *self {
             Primitive::Boolean (b) => Ok(b),
-            ref p => unexpected_primitive!(Number, p.get_debug_name())
+            ref p => unexpected_primitive!(Boolean, p.type_name())
         }
     }
     pub fn as_name(&self) -> Result<&str> {
         match self {
             Primitive::Name(ref name) => Ok(name.as_str()),
-            p => unexpected_primitive!(Name, p.get_debug_name())
+            p => unexpected_primitive!(Name, p.type_name())
         }
     }
     pub fn as_string(&self) -> Result<&PdfString> {
         match self {
             Primitive::String(ref data) => Ok(data),
-            p => unexpected_primitive!(String, p.get_debug_name())
+            p => unexpected_primitive!(String, p.type_name())
         }
     }
     pub fn as_array(&self) -> Result<&[Primitive]> {
         match self {
             Primitive::Array(ref v) => Ok(v),
-            p => unexpected_primitive!(Array, p.get_debug_name())
+            p => unexpected_primitive!(Array, p.type_name())
         }
     }
+    /// Element `index` of an array.
+    pub fn get_element(&self, index: usize) -> Result<&Primitive> {
+        let arr = self.as_array()?;
+        arr.get(index).ok_or(PdfError::ArrayIndex { index, len: arr.len() })
+    }
+    /// Element `index` of an array, which has to be an integer.
+    pub fn get_integer(&self, index: usize) -> Result<i32> {
+        Ok(t!(self.get_element(index)?.as_integer(), index))
+    }
+    /// Element `index` of an array, which has to be a number.
+    pub fn get_number(&self, index: usize) -> Result<f32> {
+        Ok(t!(self.get_element(index)?.as_number(), index))
+    }
+    /// Element `index` of an array, which has to be a name.
+    pub fn get_name(&self, index: usize) -> Result<&str> {
+        Ok(t!(self.get_element(index)?.as_name(), index))
+    }
     pub fn into_reference(self) -> Result<PlainRef> {
         match self {
             Primitive::Reference(id) => Ok(id),
-            p => unexpected_primitive!(Reference, p.get_debug_name())
+            p => unexpected_primitive!(Reference, p.type_name())
         }
     }
     pub fn into_array(self) -> Result<Vec<Primitive>> {
         match self {
             Primitive::Array(v) => Ok(v),
-            p => unexpected_primitive!(Array, p.get_debug_name())
+            p => unexpected_primitive!(Array, p.type_name())
         }
     }
     pub fn into_dictionary(self) -> Result<Dictionary> {
         match self {
             Primitive::Dictionary(dict) => Ok(dict),
-            p => unexpected_primitive!(Dictionary, p.get_debug_name())
+            p => unexpected_primitive!(Dictionary, p.type_name())
         }
     }
     pub fn into_name(self) -> Result<Name> {
         match self {
             Primitive::Name(name) => Ok(Name(name)),
-            p => unexpected_primitive!(Name, p.get_debug_name())
+            p => unexpected_primitive!(Name, p.type_name())
         }
     }
     pub fn into_string(self) -> Result<PdfString> {
         match self {
             Primitive::String(data) => Ok(data),
-            p => unexpected_primitive!(String, p.get_debug_name())
+            p => unexpected_primitive!(String, p.type_name())
         }
     }
     pub fn to_string_lossy(&self) -> Result<String> {
@@ -613,18 +867,28 @@ impl Primitive {
     pub fn into_stream(self, _r: &impl Resolve) -> Result<PdfStream> {
         match self {
             Primitive::Stream (s) => Ok(s),
-            p => unexpected_primitive!(Stream, p.get_debug_name())
+            p => unexpected_primitive!(Stream, p.type_name())
         }
     }
 }
 
 impl From<i32> for Primitive {
     fn from(x: i32) -> Primitive {
+        Primitive::Integer(x.into())
+    }
+}
+impl From<i64> for Primitive {
+    fn from(x: i64) -> Primitive {
         Primitive::Integer(x)
     }
 }
 impl From<f32> for Primitive {
     fn from(x: f32) -> Primitive {
+        Primitive::Number(x as f64)
+    }
+}
+impl From<f64> for Primitive {
+    fn from(x: f64) -> Primitive {
         Primitive::Number(x)
     }
 }
@@ -683,7 +947,7 @@ impl<'a> TryInto<Name> for &'a Primitive {
             Primitive::Name(s) => Ok(Name(s.clone())),
             p => Err(PdfError::UnexpectedPrimitive {
                 expected: "Name",
-                found: p.get_debug_name()
+                found: p.type_name()
             })
         }
     }
@@ -702,7 +966,7 @@ impl<'a> TryInto<&'a [u8]> for &'a Primitive {
             Primitive::String(ref s) => Ok(s.as_bytes()),
             ref p => Err(PdfError::UnexpectedPrimitive {
                 expected: "Name or String",
-                found: p.get_debug_name()
+                found: p.type_name()
             })
         }
     }
@@ -715,7 +979,7 @@ impl<'a> TryInto<Cow<'a, str>> for &'a Primitive {
             Primitive::String(ref s) => Ok(Cow::Owned(s.to_string_lossy())),
             ref p => Err(PdfError::UnexpectedPrimitive {
                 expected: "Name or String",
-                found: p.get_debug_name()
+                found: p.type_name()
             })
         }
     }
@@ -728,7 +992,7 @@ impl<'a> TryInto<String> for &'a Primitive {
             Primitive::String(ref s) => Ok(s.to_string_lossy()),
             ref p => Err(PdfError::UnexpectedPrimitive {
                 expected: "Name or String",
-                found: p.get_debug_name()
+                found: p.type_name()
             })
         }
     }
@@ -761,51 +1025,50 @@ pub enum TimeRel {
 }
 datasize::non_dynamic_const_heap_size!(Date, std::mem::size_of::<Date>());
 
+/// Reads `D:YYYYMMDDHHmmSSOHH'mm`. Everything after the year is optional, missing parts
+/// are the earliest value (January 1st, midnight, UTC). The `D:` is optional, too.
 impl Object for Date {
     fn from_primitive(p: Primitive, r: &impl Resolve) -> Result<Self> {
         match p.resolve(r)? {
             Primitive::String (PdfString {data}) => {
                 let s = str::from_utf8(&data)?;
-                if s.starts_with("D:") {
-                    let year = match s.get(2..6) {
-                        Some(year) => {
-                            str::parse::<u16>(year)?
-                        }
-                        None => bail!("Missing obligatory year in date")
-                    };
-                    
-                    let (time, rel, zone) = match s.find(['+', '-', 'Z']) {
-                        Some(p) => {
-                            let rel = match &s[p..p+1] {
-                                "-" => TimeRel::Earlier,
-                                "+" => TimeRel::Later,
-                                "Z" => TimeRel::Universal,
-                                _ => unreachable!()
-                            };
-                            (&s[..p], rel, &s[p+1..])
-                        }
-                        None => (s, TimeRel::Universal, "")
-                    };
-
-                    let month = parse_or(time, 6..8, 1);
-                    let day = parse_or(time, 8..10, 1);
-                    let hour = parse_or(time, 10..12, 0);
-                    let minute = parse_or(time, 12..14, 0);
-                    let second = parse_or(time, 14..16, 0);
-                    let tz_hour = parse_or(zone, 0..2, 0);
-                    let tz_minute = parse_or(zone, 3..5, 0);
-                    
-                    Ok(Date {
-                        year, month, day,
-                        hour, minute, second,
-                        tz_hour, tz_minute,
-                        rel
-                    })
-                } else {
-                    bail!("Failed parsing date");
-                }
+                let s = s.strip_prefix("D:").unwrap_or(s);
+                let year = match s.get(0..4) {
+                    Some(year) if year.bytes().all(|b| b.is_ascii_digit()) => {
+                        str::parse::<u16>(year)?
+                    }
+                    _ => bail!("Missing obligatory year in date {:?}", s)
+                };
+
+                let (time, rel, zone) = match s.find(['+', '-', 'Z']) {
+                    Some(p) => {
+                        let rel = match &s[p..p+1] {
+                            "-" => TimeRel::Earlier,
+                            "+" => TimeRel::Later,
+                            "Z" => TimeRel::Universal,
+                            _ => unreachable!()
+                        };
+                        (&s[..p], rel, &s[p+1..])
+                    }
+                    None => (s, TimeRel::Universal, "")
+                };
+
+                let month = parse_or(time, 4..6, 1);
+                let day = parse_or(time, 6..8, 1);
+                let hour = parse_or(time, 8..10, 0);
+                let minute = parse_or(time, 10..12, 0);
+                let second = parse_or(time, 12..14, 0);
+                let tz_hour = parse_or(zone, 0..2, 0);
+                let tz_minute = parse_or(zone, 3..5, 0);
+
+                Ok(Date {
+                    year, month, day,
+                    hour, minute, second,
+                    tz_hour, tz_minute,
+                    rel
+                })
             }
-            p => unexpected_primitive!(String, p.get_debug_name()),
+            p => unexpected_primitive!(String, p.type_name()),
         }
     }
 }
@@ -817,23 +1080,25 @@ impl ObjectWrite for Date {
             hour, minute, second,
             tz_hour, tz_minute, rel,
         } = *self;
-        if year > 9999 || day > 99 || hour > 23 || minute >= 60 || second >= 60 || tz_hour >= 24 || tz_minute >= 60 {
+        if year > 9999 || !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute >= 60 || second >= 60 || tz_hour >= 24 || tz_minute >= 60 {
             bail!("not a valid date");
         }
-        let o = match rel {
-            TimeRel::Earlier => "-",
-            TimeRel::Later => "+",
-            TimeRel::Universal => "Z"
+        let zone = match rel {
+            TimeRel::Universal if tz_hour == 0 && tz_minute == 0 => "Z".into(),
+            TimeRel::Earlier => format!("-{tz_hour:02}'{tz_minute:02}"),
+            _ => format!("+{tz_hour:02}'{tz_minute:02}"),
         };
-        
-        let s = format!("D:{year:04}{month:02}{day:02}{hour:02}{minute:02}{second:02}{o}{tz_hour:02}'{tz_minute:02}");
+
+        let s = format!("D:{year:04}{month:02}{day:02}{hour:02}{minute:02}{second:02}{zone}");
         Ok(Primitive::String(PdfString { data: s.into() }))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{primitive::{PdfString, TimeRel}, object::{NoResolve, Object}};
+    use crate::{primitive::{Dictionary, PdfStream, PdfString, Primitive, TimeRel, serialize_name}, object::{NoResolve, Object, PlainRef}};
+    use crate::error::PdfError;
+    use std::borrow::Cow;
 
     use super::Date;
     #[test]
@@ -876,6 +1141,278 @@ mod tests {
         assert!(s.to_string().is_err()); // FIXME verify it is a PdfError::Utf16Decode
     }
 
+    #[test]
+    fn text_string() {
+        let text = |data: &[u8]| PdfString::new(data.into()).to_text();
+        assert_eq!(text(b"caf\xe9 \x80"), "caf\u{e9} \u{2022}");
+        assert_eq!(text(b"\xfe\xff\x00\xe4"), "\u{e4}");
+        assert_eq!(text(b"\xef\xbb\xbfmit\xc3\xa4"), "mit\u{e4}");
+    }
+
+    #[test]
+    fn pdfstring_serialize() {
+        let ser = |data: &[u8]| {
+            let mut out = vec![];
+            PdfString::new(data.into()).serialize(&mut out).unwrap();
+            out
+        };
+        assert_eq!(ser(b"Hello"), b"(Hello)");
+        assert_eq!(ser(b"Hel\rlo"), b"<48656c0d6c6f>");
+        assert_eq!(ser(&[0, 1, 0xff]), b"<0001ff>");
+
+        let s = PdfString::new([b'a', 0xff].as_slice().into());
+        assert_eq!(s.as_bytes(), &[b'a', 0xff]);
+        assert_eq!(s.as_str_lossy(), "a\u{FFFD}");
+        assert!(matches!(PdfString::from("abc").as_str_lossy(), Cow::Borrowed("abc")));
+    }
+
+    #[test]
+    fn binary_string() {
+        let bytes = vec![0, b'(', 0xff, b'a', 0x80, b'\\', 0];
+        let mut out = vec![];
+        Primitive::String(PdfString::from(bytes.clone())).serialize(&mut out).unwrap();
+        assert_eq!(out, b"<0028ff61805c00>".to_vec());
+
+        let p = crate::parser::parse(&out, &NoResolve, crate::parser::ParseFlags::STRING).unwrap();
+        let s = PdfString::from_primitive(p, &NoResolve).unwrap();
+        assert_eq!(Vec::<u8>::from(s), bytes);
+    }
+
+    #[test]
+    fn balanced_parens() {
+        let ser = |s: &str| {
+            let mut out = vec![];
+            PdfString::from(s).serialize(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(ser("(a)"), "((a))");
+        assert_eq!(ser("(a(b))"), "((a(b)))");
+        assert_eq!(ser("a)"), r"(a\))");
+        assert_eq!(ser("(a"), r"(\(a)");
+        assert_eq!(ser(")("), r"(\)\()");
+    }
+
+    #[test]
+    fn as_f64() {
+        assert_eq!(Primitive::Integer(16777217).as_f64().unwrap(), 16777217.0);
+        assert_eq!(Primitive::Number(0.5).as_f64().unwrap(), 0.5);
+        let err = Primitive::Boolean(true).as_f64().unwrap_err();
+        assert!(err.to_string().contains("Boolean"));
+    }
+
+    #[test]
+    fn name_escapes() {
+        assert_eq!(Primitive::parse(b"/Pa#69d").unwrap().0, Primitive::name("Paid"));
+        assert_eq!(Primitive::parse(b"/A#20B").unwrap().0, Primitive::name("A B"));
+
+        let mut out = vec![];
+        serialize_name("A B#(c)/ä", &mut out).unwrap();
+        assert_eq!(out, b"/A#20B#23#28c#29#2F#C3#A4");
+        assert_eq!(Primitive::parse(&out).unwrap().0, Primitive::name("A B#(c)/ä"));
+    }
+
+    #[test]
+    fn pretty() {
+        let (p, _) = Primitive::parse(b"<< /Type /Page /MediaBox [0 0 612 792] /Resources << /Font << /F1 5 0 R >> >> /Annots [<< /S (x) >>] >>").unwrap();
+        let expected = "\
+<<
+  /Type /Page
+  /MediaBox [0 0 612 792]
+  /Resources <<
+    /Font <<
+      /F1 5 0 R
+    >>
+  >>
+  /Annots [
+    <<
+      /S \"x\"
+    >>
+  ]
+>>";
+        assert_eq!(p.pretty(2), expected);
+        assert_eq!(Primitive::Integer(3).pretty(2), "3");
+    }
+
+    #[test]
+    fn merge() {
+        let dict = |s: &[u8]| Primitive::parse(s).unwrap().0.into_dictionary().unwrap();
+        let base = dict(b"<< /A 1 /B << /X 1 /Y 2 >> /C 3 >>");
+        let other = dict(b"<< /B << /X 9 >> /D 4 /A 5 >>");
+
+        let mut merged = base.clone();
+        merged.merge(&other);
+        assert_eq!(merged, dict(b"<< /A 5 /B << /X 9 >> /C 3 /D 4 >>"));
+        assert_eq!(merged.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>(), ["A", "B", "C", "D"]);
+
+        let mut merged = base.clone();
+        merged.merge_missing(&other);
+        assert_eq!(merged, dict(b"<< /A 1 /B << /X 1 /Y 2 >> /C 3 /D 4 >>"));
+    }
+
+    #[test]
+    fn array_elements() {
+        let (w, _) = Primitive::parse(b"[1 2.5 /Name]").unwrap();
+        assert_eq!(w.get_integer(0).unwrap(), 1);
+        assert_eq!(w.get_number(1).unwrap(), 2.5);
+        assert_eq!(w.get_name(2).unwrap(), "Name");
+
+        assert!(matches!(w.get_integer(3), Err(PdfError::ArrayIndex { index: 3, len: 3 })));
+        let err = w.get_integer(2).unwrap_err();
+        assert!(matches!(err, PdfError::Try { .. }), "{:?}", err);
+        assert!(format!("{:?}", err).contains("index"), "{:?}", err);
+        assert!(Primitive::Integer(0).get_integer(0).is_err());
+    }
+
+    #[test]
+    fn dictionary_order() {
+        let mut dict = Dictionary::new();
+        for key in ["Type", "Kids", "Count", "Parent", "MediaBox"].iter() {
+            dict.insert(*key, Primitive::Null);
+        }
+        dict.remove_ordered("Count");
+        let ser = |dict: &Dictionary| {
+            let mut out = vec![];
+            Primitive::Dictionary(dict.clone()).serialize(&mut out).unwrap();
+            out
+        };
+        let first = ser(&dict);
+        assert_eq!(first, ser(&dict));
+        assert_eq!(first, b"<<\n/Type null\n/Kids null\n/Parent null\n/MediaBox null\n>>\n");
+
+        // the last entry takes the place of the removed one
+        dict.remove("Type");
+        assert_eq!(ser(&dict), b"<<\n/MediaBox null\n/Kids null\n/Parent null\n>>\n");
+    }
+
+    #[test]
+    fn typed_get() {
+        let mut dict = Dictionary::new();
+        dict.insert("Count", 3);
+        dict.insert("Type", Primitive::name("Pages"));
+
+        assert_eq!(dict.get_typed::<u32>("Count", &NoResolve).unwrap(), Some(3));
+        assert_eq!(dict.get_typed::<u32>("Parent", &NoResolve).unwrap(), None);
+        assert!(dict.get_typed::<u32>("Type", &NoResolve).is_err());
+
+        assert_eq!(dict.require_typed::<u32>("Pages", "Count", &NoResolve).unwrap(), 3);
+        assert!(matches!(
+            dict.require_typed::<u32>("Pages", "Kids", &NoResolve),
+            Err(PdfError::MissingEntry { typ: "Pages", ref field }) if field == "Kids"
+        ));
+    }
+
+    #[test]
+    fn type_name() {
+        let cases = vec![
+            (Primitive::Null, "Null"),
+            (Primitive::Integer(1), "Integer"),
+            (Primitive::Number(1.5), "Number"),
+            (Primitive::Boolean(false), "Boolean"),
+            (Primitive::String(PdfString::from("a")), "String"),
+            (Primitive::Stream(PdfStream::new(Dictionary::new(), vec![])), "Stream"),
+            (Primitive::Dictionary(Dictionary::new()), "Dictionary"),
+            (Primitive::Array(vec![]), "Array"),
+            (Primitive::Reference(PlainRef { id: 1, gen: 0 }), "Reference"),
+            (Primitive::name("a"), "Name"),
+        ];
+        for (p, name) in cases {
+            assert_eq!(p.type_name(), name);
+        }
+        let err = Primitive::Array(vec![]).as_name().unwrap_err();
+        assert!(matches!(err, PdfError::UnexpectedPrimitive { expected: "Name", found: "Array" }));
+    }
+
+    #[test]
+    fn real() {
+        let ser = |f: f32| {
+            let mut out = vec![];
+            Primitive::from(f).serialize(&mut out).map(|_| String::from_utf8(out).unwrap())
+        };
+        assert_eq!(ser(1e20).unwrap(), "100000000000000000000");
+        assert_eq!(ser(0.5).unwrap(), "0.5");
+        assert_eq!(ser(-2.0).unwrap(), "-2");
+        assert_eq!(ser(1.0 / 3.0).unwrap(), "0.333333");
+        assert_eq!(ser(1e-9).unwrap(), "0");
+        assert_eq!(ser(-1e-9).unwrap(), "0");
+        assert!(ser(f32::INFINITY).is_err());
+        assert!(ser(f32::NAN).is_err());
+        // only the output is rounded
+        assert_eq!(ser(0.1).unwrap(), "0.1");
+        assert_eq!(Primitive::from(1e-7f32), Primitive::Number(1e-7f32 as f64));
+
+        // an f64 is written exactly
+        let mut out = vec![];
+        Primitive::Number(0.1 + 0.2).serialize(&mut out).unwrap();
+        assert_eq!(out, b"0.30000000000000004");
+    }
+
+    /// xorshift, so the generated primitives are the same on every run
+    struct Rng(u32);
+    impl Rng {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+        fn below(&mut self, n: u32) -> u32 {
+            self.next() % n
+        }
+        fn name(&mut self) -> String {
+            (0 .. 1 + self.below(8)).map(|_| (b'A' + self.below(26) as u8) as char).collect()
+        }
+        fn primitive(&mut self, depth: usize) -> Primitive {
+            let kinds = if depth == 0 { 7 } else { 9 };
+            match self.below(kinds) {
+                0 => Primitive::Null,
+                1 => Primitive::Integer(self.next() as i32 as i64),
+                // integral reals are written like integers and read back as such
+                2 => Primitive::Number(((self.next() as i16) as f64 * 2. + 1.) / 4.),
+                3 => Primitive::Boolean(self.below(2) == 0),
+                4 => Primitive::String(PdfString::from((0 .. self.below(10)).map(|_| self.next() as u8).collect::<Vec<u8>>())),
+                5 => Primitive::Reference(PlainRef { id: self.below(1000) as u64, gen: self.below(3) as u64 }),
+                6 => Primitive::name(self.name()),
+                7 => Primitive::Array((0 .. self.below(5)).map(|_| self.primitive(depth - 1)).collect()),
+                _ => {
+                    let mut dict = Dictionary::new();
+                    for _ in 0 .. self.below(5) {
+                        let key = self.name();
+                        dict.insert(key, self.primitive(depth - 1));
+                    }
+                    Primitive::Dictionary(dict)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parse_roundtrip() {
+        let mut rng = Rng(0x2545_f491);
+        for _ in 0 .. 500 {
+            let p = rng.primitive(3);
+            let mut out = vec![];
+            p.serialize(&mut out).unwrap();
+            let (parsed, len) = Primitive::parse(&out).unwrap();
+            assert_eq!(parsed, p, "{}", String::from_utf8_lossy(&out));
+            assert!(out[len..].iter().all(|b| b.is_ascii_whitespace()));
+        }
+    }
+
+    #[test]
+    fn parse_consumed() {
+        assert_eq!(Primitive::parse(b"42").unwrap(), (Primitive::Integer(42), 2));
+        assert_eq!(Primitive::parse(b"1 2").unwrap(), (Primitive::Integer(1), 1));
+        assert_eq!(Primitive::parse(b"12 0 R /Next").unwrap(), (Primitive::Reference(PlainRef { id: 12, gen: 0 }), 6));
+        assert_eq!(Primitive::parse(b"[true false null] x").unwrap().1, 17);
+
+        let deep = format!("{}{}", "[".repeat(100), "]".repeat(100));
+        let mut err = Primitive::parse(deep.as_bytes()).unwrap_err();
+        while let PdfError::Try { source, .. } = err {
+            err = *source;
+        }
+        assert!(matches!(err, PdfError::MaxDepth), "{:?}", err);
+    }
+
     #[test]
     fn date() {
         let p = PdfString::from("D:199812231952-08'00");
@@ -894,4 +1431,28 @@ mod tests {
         };
         assert_eq!(d.unwrap(), d2);
     }
+
+    #[test]
+    fn date_truncated() {
+        use crate::object::{ObjectWrite, NoUpdate};
+        let date = |s: &str| Date::from_primitive(PdfString::from(s).into(), &NoResolve).unwrap();
+        let parts = |d: Date| (d.year, d.month, d.day, d.hour, d.minute, d.second);
+        assert_eq!(parts(date("D:2020")), (2020, 1, 1, 0, 0, 0));
+        assert_eq!(parts(date("D:202003")), (2020, 3, 1, 0, 0, 0));
+        assert_eq!(parts(date("D:20200315")), (2020, 3, 15, 0, 0, 0));
+        assert_eq!(parts(date("D:2020031512")), (2020, 3, 15, 12, 0, 0));
+        assert_eq!(parts(date("D:202003151230")), (2020, 3, 15, 12, 30, 0));
+        assert_eq!(parts(date("D:20200315123045Z")), (2020, 3, 15, 12, 30, 45));
+        assert_eq!(parts(date("20200315")), (2020, 3, 15, 0, 0, 0));
+        assert!(Date::from_primitive(PdfString::from("D:20").into(), &NoResolve).is_err());
+
+        let d = date("D:2020031512+05'30");
+        assert_eq!((d.rel, d.tz_hour, d.tz_minute), (TimeRel::Later, 5, 30));
+
+        let write = |d: Date| d.to_primitive(&mut NoUpdate).unwrap().to_string().unwrap();
+        assert_eq!(write(date("D:2020")), "D:20200101000000Z");
+        assert_eq!(write(d.clone()), "D:20200315120000+05'30");
+        assert_eq!(date(&write(d.clone())), d);
+        assert!(Date { month: 13, .. d }.to_primitive(&mut NoUpdate).is_err());
+    }
 }
@@ -1,42 +1,125 @@
+//! Reading files with a missing or broken cross-reference table.
+use crate::error::*;
+use crate::object::*;
+use crate::parser::{Lexer, parse_with_lexer, ParseFlags, is_whitespace};
+use crate::primitive::{Primitive, Dictionary};
+use crate::xref::{XRef, XRefTable};
 
-fn build_xref_table() {
-    warn!("can't read xref table: {:?}", e);
-    let start_offset = t!(backend.locate_start_offset());
-    let mut lexer = Lexer::new(t!(backend.read(..)));
-    let mut objects = Vec::new();
-
-    (|| -> Result<()> { loop {
-        let offset = lexer.get_pos();
-        let w1 = t!(lexer.next());
-        let w2 = t!(lexer.next());
-        let w3 = t!(lexer.next_expect("obj"));
-        try_opt!(lexer.seek_substr("endobj"));
-
-        objects.push((t!(w1.to::<ObjNr>()), t!(w2.to::<GenNr>()), offset));
-    }})();
-
-    objects.sort_unstable();
-    let mut first_id = objects.first().map(|&(n, _, _)| n).unwrap_or(0);
-    let mut last_id = objects.last().map(|&(n, _, _)| n).unwrap_or(0);
-    let mut xref = XRefTable::new(1 + last_id - first_id);
-    for &(obj_nr, gen_nr, offset) in objects.iter() {
-        for n in first_id + 1 .. obj_nr {
-            xref.push(XRef::Free { next_obj_nr: obj_nr, gen_nr: 0 });
-        }
-        if obj_nr == last_id {
-            warn!("duplicate obj_nr {}", obj_nr);
+/// Finds every `N G obj` in `data`, with the offset of `N`.
+///
+/// Object numbers larger than the length of `data` can't be genuine, as every object takes
+/// up several bytes. They are skipped, so that a match in stream data can't make the xref
+/// table huge.
+fn find_objects(data: &[u8]) -> Vec<(ObjNr, GenNr, usize)> {
+    let mut objects = vec![];
+    for end in data.windows(3).enumerate().filter(|(_, w)| w == b"obj").map(|(i, _)| i) {
+        // `obj` has to be followed by a whitespace or delimiter
+        if let Some(&b) = data.get(end + 3) {
+            if !is_whitespace(b) && !b"()<>[]{}/%".contains(&b) {
+                continue;
+            }
+        }
+        // walk back over ` G ` and `N`
+        let before = |pos: usize, f: fn(u8) -> bool| pos - data[..pos].iter().rev().take_while(|&&b| f(b)).count();
+        let gen_end = before(end, is_whitespace);
+        let gen_start = before(gen_end, |b| b.is_ascii_digit());
+        let id_end = before(gen_start, is_whitespace);
+        let id_start = before(id_end, |b| b.is_ascii_digit());
+        if gen_end == end || gen_start == gen_end || id_end == gen_start || id_start == id_end {
+            continue;
+        }
+        if id_start > 0 && !is_whitespace(data[id_start - 1]) {
             continue;
         }
-        xref.push(XRef::Raw {
-            pos: offset - start_offset,
-            gen_nr
-        });
-        last_id = obj_nr;
+        let number = |range: std::ops::Range<usize>| std::str::from_utf8(&data[range]).ok()?.parse().ok();
+        if let (Some(id), Some(gen)) = (number(id_start .. id_end), number(gen_start .. gen_end)) {
+            if id as usize <= data.len() {
+                objects.push((id, gen, id_start));
+            }
+        }
     }
+    objects
+}
+
+/// Builds the xref table by looking for `N G obj` in the whole file.
+///
+/// If an object is defined more than once, the last definition wins, like it does with
+/// incremental updates. Offsets are relative to the start of `data`.
+pub fn rebuild_xref(data: &[u8]) -> XRefTable {
+    xref_table(&find_objects(data))
+}
 
-    return t!(Err(e));
+fn xref_table(objects: &[(ObjNr, GenNr, usize)]) -> XRefTable {
+    let size = objects.iter().map(|&(id, _, _)| id + 1).max().unwrap_or(1);
+    let mut refs = XRefTable::new(size);
+    refs.set(0, XRef::Free { next_obj_nr: 0, gen_nr: 0xffff });
+    for &(id, gen_nr, pos) in objects {
+        if id > 0 {
+            refs.set(id, XRef::Raw { pos, gen_nr });
+        }
+    }
+    refs
 }
 
-fn build_catalog() {
-    
+/// Rebuilds the xref table and finds a trailer.
+///
+/// This is the last `trailer` dictionary in the file, or, if there is none with `/Root`,
+/// a new one pointing to the last object with `/Type /Catalog`.
+pub fn rebuild_xref_and_trailer(data: &[u8]) -> Result<(XRefTable, Dictionary)> {
+    let objects = find_objects(data);
+    let refs = xref_table(&objects);
+    let size = Primitive::Integer(refs.len() as i64 - 1);
+
+    if let Some(pos) = data.windows(7).rposition(|w| w == b"trailer") {
+        let mut lexer = Lexer::new(&data[pos + 7 ..]);
+        if let Ok(Primitive::Dictionary(mut trailer)) = parse_with_lexer(&mut lexer, &NoResolve, ParseFlags::DICT) {
+            if trailer.get("Root").is_some() {
+                trailer.insert("Size", size);
+                return Ok((refs, trailer));
+            }
+        }
+    }
+
+    let catalog = objects.into_iter().rev().find(|&(_, _, pos)| {
+        let mut lexer = Lexer::new(&data[pos ..]);
+        let _ = (lexer.next(), lexer.next(), lexer.next());
+        match parse_with_lexer(&mut lexer, &NoResolve, ParseFlags::DICT) {
+            Ok(Primitive::Dictionary(dict)) => dict.get("Type") == Some(&Primitive::name("Catalog")),
+            _ => false
+        }
+    });
+    match catalog {
+        Some((id, gen, _)) => {
+            let mut trailer = Dictionary::new();
+            trailer.insert("Size", size);
+            trailer.insert("Root", Primitive::Reference(PlainRef { id, gen }));
+            Ok((refs, trailer))
+        }
+        None => bail!("no trailer and no catalog found")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebuild() {
+        let data = b"%PDF-1.7\n1 0 obj\n<< /Type /Catalog >>\nendobj\n2 0 obj 7 endobj\n%endobj\n2 1 obj\n8\nendobj";
+        let refs = rebuild_xref(data);
+        assert!(matches!(refs.get(1).unwrap(), XRef::Raw { pos: 9, gen_nr: 0 }));
+        assert!(matches!(refs.get(2).unwrap(), XRef::Raw { gen_nr: 1, .. }));
+
+        let (_, trailer) = rebuild_xref_and_trailer(data).unwrap();
+        assert_eq!(trailer["Root"], Primitive::Reference(PlainRef { id: 1, gen: 0 }));
+        assert_eq!(trailer["Size"], Primitive::Integer(3));
+    }
+
+    #[test]
+    fn huge_object_number() {
+        let data = b"%PDF-1.7\n1 0 obj\n<< /Type /Catalog >>\nendobj\nstream 4000000000 0 obj endstream";
+        let (_, trailer) = rebuild_xref_and_trailer(data).unwrap();
+        assert_eq!(trailer["Root"], Primitive::Reference(PlainRef { id: 1, gen: 0 }));
+        assert_eq!(trailer["Size"], Primitive::Integer(2));
+    }
 }
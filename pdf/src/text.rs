@@ -0,0 +1,74 @@
+//! Text strings in PDFDocEncoding.
+//!
+//! Text strings like `/Title` are either UTF-16BE with a byte order mark, or PDFDocEncoding.
+//! PDFDocEncoding is Latin-1, except for 0x18–0x1F and 0x80–0xA0.
+//! See `PdfString::to_text` for decoding any text string.
+use crate::error::*;
+
+/// Characters for 0x18–0x1F
+const CODES_18: [char; 8] = ['\u{02D8}', '\u{02C7}', '\u{02C6}', '\u{02D9}', '\u{02DD}', '\u{02DB}', '\u{02DA}', '\u{02DC}'];
+
+/// Characters for 0x80–0xA0. 0x9F is not defined.
+const CODES_80: [char; 33] = [
+    '\u{2022}', '\u{2020}', '\u{2021}', '\u{2026}', '\u{2014}', '\u{2013}', '\u{0192}', '\u{2044}',
+    '\u{2039}', '\u{203A}', '\u{2212}', '\u{2030}', '\u{201E}', '\u{201C}', '\u{201D}', '\u{2018}',
+    '\u{2019}', '\u{201A}', '\u{2122}', '\u{FB01}', '\u{FB02}', '\u{0141}', '\u{0152}', '\u{0160}',
+    '\u{0178}', '\u{017D}', '\u{0131}', '\u{0142}', '\u{0153}', '\u{0161}', '\u{017E}', '\u{FFFD}',
+    '\u{20AC}',
+];
+
+/// Decodes one byte. Undefined codes (0x7F, 0x9F and 0xAD) become U+FFFD.
+pub fn pdfdoc_char(b: u8) -> char {
+    match b {
+        0x18 ..= 0x1F => CODES_18[b as usize - 0x18],
+        0x80 ..= 0xA0 => CODES_80[b as usize - 0x80],
+        0x7F | 0xAD => '\u{FFFD}',
+        _ => b as char,
+    }
+}
+
+/// Decodes PDFDocEncoding. This can't fail, undefined codes become U+FFFD.
+pub fn pdfdoc_to_utf8(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| pdfdoc_char(b)).collect()
+}
+
+/// Encodes `s` in PDFDocEncoding. Fails if a character can't be represented.
+pub fn utf8_to_pdfdoc(s: &str) -> Result<Vec<u8>> {
+    s.chars().map(|c| {
+        let code = match c as u32 {
+            n @ (0x00 ..= 0x17 | 0x20 ..= 0x7E | 0xA1 ..= 0xAC | 0xAE ..= 0xFF) => Some(n as u8),
+            _ => CODES_18.iter().position(|&d| d == c).map(|i| 0x18 + i as u8)
+                .or_else(|| CODES_80.iter().position(|&d| d == c && c != '\u{FFFD}').map(|i| 0x80 + i as u8)),
+        };
+        match code {
+            Some(code) => Ok(code),
+            None => bail!("{:?} can't be represented in PDFDocEncoding", c),
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn special_codes() {
+        let s = "\u{2022} A\u{2026} \u{2014} \u{20AC}\u{02D8} \u{FB01}";
+        let bytes = utf8_to_pdfdoc(s).unwrap();
+        assert_eq!(bytes, b"\x80 A\x83 \x84 \xa0\x18 \x93");
+        assert_eq!(pdfdoc_to_utf8(&bytes), s);
+
+        // Latin-1
+        assert_eq!(pdfdoc_to_utf8(b"caf\xe9"), "caf\u{e9}");
+        assert_eq!(utf8_to_pdfdoc("caf\u{e9}").unwrap(), b"caf\xe9");
+
+        for b in 0 ..= 255u8 {
+            let c = pdfdoc_char(b);
+            if c != '\u{FFFD}' {
+                assert_eq!(utf8_to_pdfdoc(&c.to_string()).unwrap(), [b]);
+            }
+        }
+        assert!(utf8_to_pdfdoc("\u{FFFD}").is_err());
+        assert!(utf8_to_pdfdoc("\u{4e2d}").is_err());
+    }
+}
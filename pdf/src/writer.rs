@@ -0,0 +1,760 @@
+//! Writes a PDF file object by object.
+//!
+//! Unlike `Storage::save`, nothing is kept in memory once it has been written.
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::backend::Backend;
+use crate::error::*;
+use crate::file::{PromisedRef, Storage, NoCache, NoLog};
+use crate::object::*;
+use crate::enc::flate_encode;
+use crate::primitive::{Primitive, Dictionary, PdfStream, StreamInner};
+use crate::xref::{XRef, XRefTable};
+
+/// Number of objects in each object stream
+const OBJECTS_PER_STREAM: usize = 100;
+
+/// Hands out object numbers, starting at 1, and remembers which objects have been written.
+///
+/// A number can be reserved before the object itself is known, so that other objects can
+/// refer to it.
+pub struct ObjectAllocator {
+    /// Number of the first object
+    first: ObjNr,
+    filled: Vec<bool>,
+}
+impl Default for ObjectAllocator {
+    fn default() -> Self {
+        ObjectAllocator::starting_at(1)
+    }
+}
+impl ObjectAllocator {
+    pub fn new() -> Self {
+        ObjectAllocator::default()
+    }
+    /// Hands out numbers from `first` on, e.g. for an update of a file with `/Size first`.
+    pub fn starting_at(first: ObjNr) -> Self {
+        ObjectAllocator { first: first.max(1), filled: vec![] }
+    }
+    /// Number of allocated objects, excluding object 0.
+    pub fn len(&self) -> usize {
+        self.filled.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.filled.is_empty()
+    }
+    pub fn reserve(&mut self) -> PlainRef {
+        self.filled.push(false);
+        PlainRef { id: self.first + self.filled.len() as ObjNr - 1, gen: 0 }
+    }
+    /// Marks `r` as written. Fails if `r` was not reserved.
+    pub fn fill(&mut self, r: PlainRef) -> Result<()> {
+        match r.id.checked_sub(self.first).and_then(|i| self.filled.get_mut(i as usize)) {
+            Some(filled) if r.gen == 0 => {
+                *filled = true;
+                Ok(())
+            }
+            _ => Err(PdfError::NullRef { obj_nr: r.id })
+        }
+    }
+    pub fn is_filled(&self, r: PlainRef) -> bool {
+        r.id.checked_sub(self.first).and_then(|i| self.filled.get(i as usize)) == Some(&true)
+    }
+    /// Fails with the first number that was reserved, but not filled.
+    pub fn check_filled(&self) -> Result<()> {
+        match self.filled.iter().position(|&f| !f) {
+            Some(n) => bail!("object {} was reserved, but never written", self.first + n as ObjNr),
+            None => Ok(())
+        }
+    }
+}
+
+/// Writes objects into `W` as they are created.
+///
+/// Every object gets the next free object number. After the last object, `finish` writes
+/// the xref table and the trailer.
+///
+/// With `append_to`, the objects are written as an incremental update of an existing file.
+pub struct PdfWriter<W> {
+    out: W,
+    /// Number of bytes written so far
+    pos: usize,
+    alloc: ObjectAllocator,
+    /// Offset of object `first + n` at index `n`. Only valid once it has been filled.
+    offsets: Vec<usize>,
+    /// The file that is being updated
+    original: Option<Original>,
+    compress_streams: bool,
+    object_streams: Option<ObjectStreams>,
+}
+
+/// What an incremental update needs to know about the original file.
+struct Original {
+    /// The trailer, without the entries that belong to its xref section
+    trailer: Dictionary,
+    /// Position of its xref section
+    xref_pos: usize,
+    /// Replaced objects and their new position
+    replaced: BTreeMap<ObjNr, (GenNr, usize)>,
+    /// Version in the `%PDF` header
+    header_version: String,
+    /// Reference to the catalog and its latest copy
+    catalog: Option<(PlainRef, Dictionary)>,
+}
+
+/// Objects that go into object streams, see `PdfWriter::object_streams`.
+#[derive(Default)]
+struct ObjectStreams {
+    /// Serialized objects for the next object stream
+    pending: Vec<(ObjNr, Vec<u8>)>,
+    /// Object stream and index of the objects in the object streams written so far
+    packed: BTreeMap<ObjNr, (ObjNr, usize)>,
+}
+impl<W: Write> PdfWriter<W> {
+    /// Writes the header for PDF 1.7.
+    pub fn new(out: W) -> Result<Self> {
+        Self::with_version(out, "1.7")
+    }
+    /// Writes the header for the given version, e.g. `"1.4"`.
+    pub fn with_version(out: W, version: &str) -> Result<Self> {
+        let mut writer = PdfWriter {
+            out,
+            pos: 0,
+            alloc: ObjectAllocator::new(),
+            offsets: vec![],
+            original: None,
+            compress_streams: false,
+            object_streams: None,
+        };
+        writer.write_all(format!("%PDF-{}\n", version).as_bytes())?;
+        // marks the file as binary
+        writer.write_all(b"%\xe2\xe3\xcf\xd3\n")?;
+        Ok(writer)
+    }
+    /// Copies `original` into `out`, to append an incremental update to it.
+    ///
+    /// New objects get numbers from the original `/Size` on. Objects of the original can
+    /// be replaced with `fill` or `Updater::update`. `finish_with_trailer` keeps the entries
+    /// of the original trailer, like `/Root` and `/Info`, and links to its xref with `/Prev`.
+    /// The original bytes are not changed, so existing signatures stay valid.
+    ///
+    /// With `object_streams`, a file older than PDF 1.5 gets a new copy of its catalog with
+    /// `/Version 1.5`, since that is where cross-reference streams were introduced.
+    pub fn append_to(out: W, original: &[u8]) -> Result<Self> {
+        let start = t!(original.locate_start_offset());
+        let xref_pos = t!(original.locate_xref_offset());
+        // xref streams need a resolver for their data
+        let mut storage = t!(Storage::with_cache(original, ParseOptions::strict(), NoCache, NoCache, NoLog));
+        let mut trailer = t!(storage.load_storage_and_trailer());
+        if trailer.get("Encrypt").is_some() {
+            bail!("can't append to an encrypted file");
+        }
+        let size = t!(t!(trailer.require("trailer", "Size")).as_u32());
+        let header_version = original[start + 5 ..].iter()
+            .take_while(|b| b.is_ascii_digit() || **b == b'.')
+            .map(|&b| b as char)
+            .collect();
+        let catalog = match trailer.get("Root") {
+            Some(&Primitive::Reference(r)) => Some((r, t!(t!(storage.resolver().resolve(r)).into_dictionary()))),
+            _ => None,
+        };
+        for key in ["Size", "Prev", "XRefStm", "Type", "W", "Index", "Length", "Filter", "DecodeParms"] {
+            trailer.remove(key);
+        }
+
+        let mut writer = PdfWriter {
+            out,
+            // offsets are relative to the `%PDF` header
+            pos: 0,
+            alloc: ObjectAllocator::starting_at(size as ObjNr),
+            offsets: vec![],
+            original: Some(Original { trailer, xref_pos, replaced: BTreeMap::new(), header_version, catalog }),
+            compress_streams: false,
+            object_streams: None,
+        };
+        writer.write_all(original)?;
+        if !original.ends_with(b"\n") {
+            writer.write_all(b"\n")?;
+        }
+        writer.pos -= start;
+        Ok(writer)
+    }
+    /// Compresses streams without `/Filter` with `/FlateDecode`, unless they are marked
+    /// with `keep_uncompressed`.
+    pub fn compress_streams(mut self, compress: bool) -> Self {
+        self.compress_streams = compress;
+        self
+    }
+    /// Packs objects into compressed object streams (PDF 1.5), and writes a cross-reference
+    /// stream instead of an xref table.
+    ///
+    /// Streams and objects with a generation other than 0 are written as usual.
+    pub fn object_streams(mut self, enabled: bool) -> Self {
+        self.object_streams = if enabled { Some(ObjectStreams::default()) } else { None };
+        self
+    }
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.out.write_all(data)?;
+        self.pos += data.len();
+        Ok(())
+    }
+
+    /// Reserves an object number, to be written later with `fill`.
+    pub fn reserve(&mut self) -> PlainRef {
+        self.offsets.push(0);
+        self.alloc.reserve()
+    }
+    /// Writes the object `r`, which has to be reserved first.
+    ///
+    /// If `r` was written already, the new copy replaces it.
+    ///
+    /// When appending to a file, `r` can also be an object of the original file.
+    pub fn fill(&mut self, r: PlainRef, primitive: &Primitive) -> Result<()> {
+        let is_original = self.original.is_some() && r.id > 0 && r.id < self.alloc.first;
+        if !is_original {
+            self.alloc.fill(r)?;
+        }
+        if let Some(Original { catalog: Some((root, ref mut catalog)), .. }) = self.original {
+            match *primitive {
+                Primitive::Dictionary(ref dict) if root == r => *catalog = dict.clone(),
+                _ => {}
+            }
+        }
+        if let Some(ref mut streams) = self.object_streams {
+            // a new copy replaces the old one, wherever it went
+            streams.pending.retain(|&(id, _)| id != r.id);
+            streams.packed.remove(&r.id);
+            if r.gen == 0 && !matches!(*primitive, Primitive::Stream(_)) {
+                let mut buf = Vec::new();
+                primitive.serialize(&mut buf)?;
+                streams.pending.push((r.id, buf));
+                let full = streams.pending.len() >= OBJECTS_PER_STREAM;
+                if let Some(ref mut original) = self.original {
+                    original.replaced.remove(&r.id);
+                }
+                if full {
+                    self.write_object_stream()?;
+                }
+                return Ok(());
+            }
+        }
+        match self.original {
+            Some(ref mut original) if is_original => {
+                original.replaced.insert(r.id, (r.gen, self.pos));
+            }
+            _ => {
+                self.offsets[(r.id - self.alloc.first) as usize] = self.pos;
+            }
+        }
+        let compressed = match *primitive {
+            Primitive::Stream(ref s) if self.compress_streams => compress(s),
+            _ => None,
+        };
+        let mut buf = Vec::new();
+        writeln!(buf, "{} {} obj", r.id, r.gen)?;
+        compressed.as_ref().unwrap_or(primitive).serialize(&mut buf)?;
+        writeln!(buf, "\nendobj")?;
+        self.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Writes the pending objects into an object stream.
+    fn write_object_stream(&mut self) -> Result<()> {
+        let pending = match self.object_streams {
+            Some(ref mut streams) if !streams.pending.is_empty() => std::mem::take(&mut streams.pending),
+            _ => return Ok(()),
+        };
+        let mut header = Vec::new();
+        let mut body = Vec::new();
+        for (id, data) in pending.iter() {
+            write!(header, "{} {} ", id, body.len())?;
+            body.extend_from_slice(data);
+            body.push(b'\n');
+        }
+        let mut info = Dictionary::new();
+        info.insert("Type", Primitive::name("ObjStm"));
+        info.insert("N", Primitive::Integer(pending.len() as i64));
+        info.insert("First", Primitive::Integer(header.len() as i64));
+        info.insert("Filter", Primitive::name("FlateDecode"));
+        header.extend_from_slice(&body);
+
+        let r = self.reserve();
+        self.fill(r, &Primitive::Stream(PdfStream::new(info, flate_encode(&header))))?;
+        let streams = self.object_streams.as_mut().unwrap();
+        for (index, &(id, _)) in pending.iter().enumerate() {
+            streams.packed.insert(id, (r.id, index));
+        }
+        Ok(())
+    }
+
+    /// Writes `obj` as a new object and returns a reference to it.
+    pub fn write_object<T: ObjectWrite>(&mut self, obj: &T) -> Result<Ref<T>> {
+        // reserve first, so objects created by `to_primitive` get higher numbers
+        let r = self.reserve();
+        let primitive = obj.to_primitive(self)?;
+        self.fill(r, &primitive)?;
+        Ok(Ref::new(r))
+    }
+
+    /// Writes the xref table and a trailer with `/Size` and `/Root`.
+    pub fn finish<T>(self, root: Ref<T>) -> Result<W> {
+        let mut trailer = Dictionary::new();
+        trailer.insert("Root", Primitive::Reference(root.get_inner()));
+        self.finish_with_trailer(trailer)
+    }
+
+    /// Like `finish`, but with additional trailer entries like `/Info` or `/ID`.
+    /// `/Size` is set by the writer, and `/Prev` when appending to a file. With
+    /// `object_streams`, so are the entries of the xref stream, like `/W` and `/Index`.
+    pub fn finish_with_trailer(mut self, mut trailer: Dictionary) -> Result<W> {
+        if self.object_streams.is_some() {
+            return self.finish_with_xref_stream(trailer);
+        }
+        self.alloc.check_filled()?;
+        let xref_pos = self.pos;
+        let first = self.alloc.first;
+        let mut buf = Vec::new();
+        writeln!(buf, "xref")?;
+        match self.original.take() {
+            Some(original) => {
+                // one subsection for each run of consecutive replaced objects
+                let replaced: Vec<_> = original.replaced.iter().map(|(&id, &entry)| (id, entry)).collect();
+                for run in replaced.chunk_by(|a, b| a.0 + 1 == b.0) {
+                    writeln!(buf, "{} {}", run[0].0, run.len())?;
+                    for &(_, (gen, pos)) in run {
+                        write!(buf, "{:010} {:05} n\r\n", pos, gen)?;
+                    }
+                }
+                if !self.offsets.is_empty() {
+                    writeln!(buf, "{} {}", first, self.offsets.len())?;
+                }
+                trailer = original.update_trailer(trailer);
+            }
+            None => {
+                writeln!(buf, "0 {}", self.offsets.len() + 1)?;
+                // every entry is exactly 20 bytes long
+                write!(buf, "0000000000 65535 f\r\n")?;
+            }
+        }
+        for pos in self.offsets.iter() {
+            write!(buf, "{:010} 00000 n\r\n", pos)?;
+        }
+
+        trailer.insert("Size", Primitive::Integer((first as usize + self.offsets.len()) as i64));
+        writeln!(buf, "trailer")?;
+        Primitive::Dictionary(trailer).serialize(&mut buf)?;
+        write!(buf, "startxref\n{}\n%%EOF\n", xref_pos)?;
+        self.write_all(&buf)?;
+
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    /// `finish_with_trailer` for `object_streams`.
+    fn finish_with_xref_stream(mut self, trailer: Dictionary) -> Result<W> {
+        if let Some((root, catalog)) = self.original.as_ref().and_then(Original::catalog_for_xref_stream) {
+            self.fill(root, &Primitive::Dictionary(catalog))?;
+        }
+        self.write_object_stream()?;
+        let xref_ref = self.reserve();
+        self.alloc.fill(xref_ref)?;
+        self.alloc.check_filled()?;
+        let xref_pos = self.pos;
+        *self.offsets.last_mut().unwrap() = xref_pos;
+
+        let first = self.alloc.first;
+        let size = first as usize + self.offsets.len();
+        let packed = self.object_streams.take().unwrap().packed;
+        let entry = |id: ObjNr, pos: usize, gen_nr: GenNr| match packed.get(&id) {
+            Some(&(stream_id, index)) => XRef::Stream { stream_id, index },
+            None => XRef::Raw { pos, gen_nr },
+        };
+        let mut refs = XRefTable::new(size as ObjNr);
+        let mut sections: Vec<(ObjNr, usize)> = vec![];
+        let trailer = match self.original.take() {
+            Some(original) => {
+                let mut ids: Vec<_> = original.replaced.keys().chain(packed.keys())
+                    .cloned().filter(|&id| id < first).collect();
+                ids.sort_unstable();
+                for &id in ids.iter() {
+                    let (gen, pos) = original.replaced.get(&id).cloned().unwrap_or((0, 0));
+                    refs.set(id, entry(id, pos, gen));
+                }
+                for run in ids.chunk_by(|a, b| a + 1 == *b) {
+                    sections.push((run[0], run.len()));
+                }
+                original.update_trailer(trailer)
+            }
+            None => {
+                refs.set(0, XRef::Free { next_obj_nr: 0, gen_nr: 0xffff });
+                sections.push((0, 1));
+                trailer
+            }
+        };
+        for (i, &pos) in self.offsets.iter().enumerate() {
+            let id = first + i as ObjNr;
+            refs.set(id, entry(id, pos, 0));
+        }
+        match sections.last_mut() {
+            Some(last) if last.0 + last.1 as ObjNr == first => last.1 += self.offsets.len(),
+            _ => sections.push((first, self.offsets.len())),
+        }
+
+        let mut stream = t!(refs.write_stream_sections(size, &sections)).to_pdf_stream(&mut NoUpdate)?;
+        // like `/Size` of an xref table, the entries of the xref stream are set by the writer
+        for (k, v) in trailer.iter() {
+            if !stream.info.contains_key(k) {
+                stream.info.insert(k.clone(), v.clone());
+            }
+        }
+        let mut buf = Vec::new();
+        writeln!(buf, "{} 0 obj", xref_ref.id)?;
+        stream.serialize(&mut buf)?;
+        write!(buf, "endobj\nstartxref\n{}\n%%EOF\n", xref_pos)?;
+        self.write_all(&buf)?;
+
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+impl Original {
+    /// The trailer of the update: the original entries, updated with `trailer`.
+    fn update_trailer(self, trailer: Dictionary) -> Dictionary {
+        let mut new_trailer = self.trailer;
+        new_trailer.append(trailer);
+        new_trailer.insert("Prev", Primitive::Integer(self.xref_pos as i64));
+        new_trailer
+    }
+    /// A copy of the catalog with `/Version 1.5`, if neither the header nor the catalog
+    /// says the file is at least that new.
+    fn catalog_for_xref_stream(&self) -> Option<(PlainRef, Dictionary)> {
+        let (root, ref catalog) = *self.catalog.as_ref()?;
+        let version = catalog.get("Version").and_then(|v| v.as_name().ok()).unwrap_or("");
+        // versions are a digit, a dot and a digit, so they compare like strings
+        if self.header_version.as_str().max(version) >= "1.5" {
+            return None;
+        }
+        let mut catalog = catalog.clone();
+        catalog.insert("Version", Primitive::name("1.5"));
+        Some((root, catalog))
+    }
+}
+/// The `/FlateDecode` compressed copy of `stream`, if it should be compressed.
+fn compress(stream: &PdfStream) -> Option<Primitive> {
+    let data = match stream.inner {
+        StreamInner::Pending { ref data } if !stream.keep_uncompressed => data,
+        _ => return None,
+    };
+    match stream.info.get("Filter") {
+        None | Some(Primitive::Null) => {}
+        Some(Primitive::Array(filters)) if filters.is_empty() => {}
+        Some(_) => return None,
+    }
+    let mut info = stream.info.clone();
+    info.remove("DecodeParms");
+    info.insert("Filter", Primitive::name("FlateDecode"));
+    Some(Primitive::Stream(PdfStream::new(info, flate_encode(data))))
+}
+
+impl<W: Write> Updater for PdfWriter<W> {
+    fn create<T: ObjectWrite>(&mut self, obj: T) -> Result<RcRef<T>> {
+        let r = self.write_object(&obj)?;
+        Ok(RcRef::new(r.get_inner(), Shared::new(obj)))
+    }
+    /// Writes `obj` again. The newer copy replaces the old one in the xref table.
+    ///
+    /// When appending to a file, this replaces objects of the original file.
+    fn update<T: ObjectWrite>(&mut self, old: PlainRef, obj: T) -> Result<RcRef<T>> {
+        let primitive = obj.to_primitive(self)?;
+        self.fill(old, &primitive)?;
+        Ok(RcRef::new(old, Shared::new(obj)))
+    }
+    fn promise<T: Object>(&mut self) -> PromisedRef<T> {
+        PromisedRef::new(self.reserve())
+    }
+    fn fulfill<T: ObjectWrite>(&mut self, promise: PromisedRef<T>, obj: T) -> Result<RcRef<T>> {
+        self.update(promise.get_inner(), obj)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::{CatalogBuilder, PageBuilder};
+    use crate::file::FileOptions;
+
+    #[test]
+    fn write_file() {
+        let mut writer = PdfWriter::new(Vec::new()).unwrap();
+        let mut page = PageBuilder::default();
+        page.size(200., 100.);
+        let catalog = CatalogBuilder::from_pages(vec![page]).build(&mut writer).unwrap();
+        let root = writer.write_object(&catalog).unwrap();
+        let data = writer.finish(root).unwrap();
+
+        assert!(data.starts_with(b"%PDF-1.7\n"));
+        assert!(data.ends_with(b"%%EOF\n"));
+
+        let file = FileOptions::uncached().load(data).unwrap();
+        assert_eq!(file.num_pages(), 1);
+        assert_eq!(file.get_page(0).unwrap().media_box().unwrap().width(), 200.);
+    }
+
+    #[test]
+    fn xref_offsets() {
+        let mut writer = PdfWriter::with_version(Vec::new(), "1.4").unwrap();
+        let a = writer.write_object(&Primitive::Integer(1)).unwrap();
+        let b = writer.write_object(&Primitive::name("B")).unwrap();
+        assert_eq!((a.get_inner().id, b.get_inner().id), (1, 2));
+        let data = writer.finish(b).unwrap();
+
+        let find = |s: &[u8]| data.windows(s.len()).position(|w| w == s).unwrap();
+        let xref = find(b"xref\n0 3\n");
+        let entries = &data[xref + 9 ..];
+        assert_eq!(&entries[.. 20], b"0000000000 65535 f\r\n");
+        assert_eq!(&entries[20 .. 40], format!("{:010} 00000 n\r\n", find(b"1 0 obj")).as_bytes());
+        assert_eq!(&entries[40 .. 60], format!("{:010} 00000 n\r\n", find(b"2 0 obj\n/B")).as_bytes());
+        assert!(data.ends_with(format!("startxref\n{}\n%%EOF\n", xref).as_bytes()));
+
+        let mut writer = PdfWriter::new(Vec::new()).unwrap();
+        let _ = writer.promise::<i32>();
+        assert!(writer.finish(Ref::<i32>::from_id(1)).is_err());
+    }
+
+    #[test]
+    fn forward_references() {
+        let mut writer = PdfWriter::new(Vec::new()).unwrap();
+        let a = writer.reserve();
+        let b = writer.reserve();
+        let node = |name: &str, other: PlainRef| {
+            let mut dict = Dictionary::new();
+            dict.insert("Name", Primitive::name(name));
+            dict.insert("Other", Primitive::Reference(other));
+            Primitive::Dictionary(dict)
+        };
+        writer.fill(b, &node("B", a)).unwrap();
+        assert!(writer.fill(PlainRef { id: 3, gen: 0 }, &Primitive::Null).is_err());
+
+        let mut unfinished = PdfWriter::new(Vec::new()).unwrap();
+        unfinished.reserve();
+        let err = unfinished.finish(Ref::<Dictionary>::new(a)).unwrap_err();
+        assert!(err.to_string().contains("object 1"), "{}", err);
+
+        writer.fill(a, &node("A", b)).unwrap();
+        let catalog = CatalogBuilder::from_pages(vec![]).build(&mut writer).unwrap();
+        let root = writer.write_object(&catalog).unwrap();
+        let data = writer.finish(root).unwrap();
+
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        let a_dict = resolver.resolve(a).unwrap().into_dictionary().unwrap();
+        let b_dict = resolver.resolve(b).unwrap().into_dictionary().unwrap();
+        assert_eq!(a_dict["Other"], Primitive::Reference(b));
+        assert_eq!(b_dict["Other"], Primitive::Reference(a));
+        assert_eq!(b_dict["Name"], Primitive::name("B"));
+    }
+
+    #[test]
+    fn append_update() {
+        let mut writer = PdfWriter::new(Vec::new()).unwrap();
+        let title = writer.write_object(&Primitive::String("old".into())).unwrap();
+        let catalog = CatalogBuilder::from_pages(vec![]).build(&mut writer).unwrap();
+        let root = writer.write_object(&catalog).unwrap();
+        let mut info = Dictionary::new();
+        info.insert("Title", Primitive::Reference(title.get_inner()));
+        let info = writer.write_object(&info).unwrap();
+        let mut trailer = Dictionary::new();
+        trailer.insert("Root", Primitive::Reference(root.get_inner()));
+        trailer.insert("Info", Primitive::Reference(info.get_inner()));
+        let original = writer.finish_with_trailer(trailer).unwrap();
+        let original_file = FileOptions::uncached().load(original.clone()).unwrap();
+        let size = original_file.trailer.size;
+
+        let mut writer = PdfWriter::append_to(Vec::new(), &original).unwrap();
+        writer.fill(title.get_inner(), &Primitive::String("new".into())).unwrap();
+        let extra = writer.write_object(&Primitive::Integer(42)).unwrap();
+        assert_eq!(extra.get_inner().id, size as ObjNr);
+        let data = writer.finish_with_trailer(Dictionary::new()).unwrap();
+
+        // the original is unchanged
+        assert_eq!(&data[.. original.len()], &original[..]);
+        let update = std::str::from_utf8(&data[original.len() ..]).unwrap();
+        let prev = original.windows(9).rposition(|w| w == b"startxref").unwrap();
+        let prev: String = original[prev + 10 ..].iter().take_while(|b| b.is_ascii_digit()).map(|&b| b as char).collect();
+        assert!(update.contains(&format!("/Prev {}", prev)), "{}", update);
+        assert!(update.contains(&format!("xref\n{} 1\n", title.get_inner().id)), "{}", update);
+
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        assert_eq!(resolver.resolve(title.get_inner()).unwrap(), Primitive::String("new".into()));
+        assert_eq!(resolver.resolve(extra.get_inner()).unwrap(), Primitive::Integer(42));
+        assert_eq!(file.trailer.size, size + 1);
+        assert!(file.trailer.info_dict.is_some());
+        assert_eq!(file.num_pages(), 0);
+    }
+
+    #[test]
+    fn stream_length() {
+        use crate::primitive::PdfStream;
+        let data = b"binary \x00\xff\nendstream data".to_vec();
+        let mut info = Dictionary::new();
+        // a wrong length is replaced
+        info.insert("Length", Primitive::Integer(3));
+        let mut writer = PdfWriter::new(Vec::new()).unwrap();
+        let a = writer.write_object(&Primitive::Stream(PdfStream::new(info, data.clone()))).unwrap();
+        let b = writer.write_object(&Stream::new((), data.clone())).unwrap();
+        let catalog = CatalogBuilder::from_pages(vec![]).build(&mut writer).unwrap();
+        let root = writer.write_object(&catalog).unwrap();
+        let out = writer.finish(root).unwrap();
+
+        let file = FileOptions::uncached().load(out).unwrap();
+        let resolver = file.resolver();
+        for r in [a.get_inner(), b.get_inner()] {
+            let stream = PdfStream::from_primitive(resolver.resolve(r).unwrap(), &resolver).unwrap();
+            assert_eq!(stream.info["Length"], Primitive::Integer(data.len() as i64));
+            assert_eq!(&*stream.raw_data(&resolver).unwrap(), &data[..]);
+        }
+    }
+
+    #[test]
+    fn compressed_streams() {
+        let text = b"BT /F1 12 Tf 72 720 Td (Hello) Tj ET\n".repeat(20);
+        let mut writer = PdfWriter::new(Vec::new()).unwrap().compress_streams(true);
+        let a = writer.write_object(&Stream::new((), text.clone())).unwrap();
+        let b = writer.write_object(&Stream::new((), text.clone()).keep_uncompressed()).unwrap();
+        // already filtered
+        let hex: Vec<u8> = text.iter().flat_map(|b| format!("{:02x}", b).into_bytes()).collect();
+        let c = writer.write_object(&Stream::from_compressed((), hex, vec![crate::enc::StreamFilter::ASCIIHexDecode])).unwrap();
+        let catalog = CatalogBuilder::from_pages(vec![]).build(&mut writer).unwrap();
+        let root = writer.write_object(&catalog).unwrap();
+        let out = writer.finish(root).unwrap();
+
+        let file = FileOptions::uncached().load(out).unwrap();
+        let resolver = file.resolver();
+        let filter = |r: Ref<Stream<()>>| {
+            let s = PdfStream::from_primitive(resolver.resolve(r.get_inner()).unwrap(), &resolver).unwrap();
+            (s.info.get("Filter").cloned(), s.raw_data(&resolver).unwrap().len())
+        };
+        let (a_filter, a_len) = filter(a);
+        assert_eq!(a_filter, Some(Primitive::name("FlateDecode")));
+        assert!(a_len < text.len() / 4);
+        assert_eq!(filter(b), (None, text.len()));
+        assert_eq!(filter(c), (Some(Primitive::name("ASCIIHexDecode")), 2 * text.len()));
+        for r in [a, b, c] {
+            let stream = resolver.get(r).unwrap();
+            assert_eq!(&*Stream::data(&*stream, &resolver).unwrap(), &text[..]);
+        }
+    }
+
+    #[test]
+    fn object_streams() {
+        let write = |writer: PdfWriter<Vec<u8>>| {
+            let mut writer = writer;
+            let numbers: Vec<_> = (0 .. 150).map(|i| writer.write_object(&Primitive::Integer(i)).unwrap()).collect();
+            let content = writer.write_object(&Stream::new((), b"0 0 m 10 10 l S".to_vec())).unwrap();
+            let pages = (0 .. 2).map(|_| {
+                let mut page = PageBuilder::default();
+                page.size(300., 400.);
+                page
+            }).collect();
+            let catalog = CatalogBuilder::from_pages(pages).build(&mut writer).unwrap();
+            let root = writer.write_object(&catalog).unwrap();
+            (writer.finish(root).unwrap(), numbers, content)
+        };
+        let (plain, _, _) = write(PdfWriter::new(Vec::new()).unwrap());
+        let (packed, numbers, content) = write(PdfWriter::new(Vec::new()).unwrap().object_streams(true));
+        assert!(packed.len() < plain.len() / 2);
+        assert!(!packed.windows(6).any(|w| w == b"\nxref\n"));
+
+        let file = FileOptions::uncached().load(packed.clone()).unwrap();
+        let resolver = file.resolver();
+        for (i, r) in numbers.iter().enumerate() {
+            assert_eq!(resolver.resolve(r.get_inner()).unwrap(), Primitive::Integer(i as i64));
+        }
+        assert_eq!(&*Stream::data(&*resolver.get(content).unwrap(), &resolver).unwrap(), b"0 0 m 10 10 l S");
+        assert_eq!(file.num_pages(), 2);
+        assert_eq!(file.get_page(1).unwrap().media_box().unwrap().height(), 400.);
+
+        // an update with an object stream
+        let mut writer = PdfWriter::append_to(Vec::new(), &packed).unwrap().object_streams(true);
+        writer.fill(numbers[3].get_inner(), &Primitive::name("three")).unwrap();
+        let extra = writer.write_object(&Primitive::Integer(-1)).unwrap();
+        let data = writer.finish_with_trailer(Dictionary::new()).unwrap();
+        assert_eq!(&data[.. packed.len()], &packed[..]);
+
+        let file = FileOptions::uncached().load(data).unwrap();
+        let resolver = file.resolver();
+        assert_eq!(resolver.resolve(numbers[3].get_inner()).unwrap(), Primitive::name("three"));
+        assert_eq!(resolver.resolve(numbers[4].get_inner()).unwrap(), Primitive::Integer(4));
+        assert_eq!(resolver.resolve(extra.get_inner()).unwrap(), Primitive::Integer(-1));
+        assert_eq!(file.num_pages(), 2);
+        assert_eq!(file.get_root().version.as_ref().map(|v| v.as_str()), Some("1.7"));
+    }
+
+    #[test]
+    fn trailer_size() {
+        for object_streams in [false, true] {
+            let mut writer = PdfWriter::new(Vec::new()).unwrap().object_streams(object_streams);
+            let catalog = CatalogBuilder::from_pages(vec![PageBuilder::default()]).build(&mut writer).unwrap();
+            let root = writer.write_object(&catalog).unwrap();
+            let mut trailer = Dictionary::new();
+            trailer.insert("Root", Primitive::Reference(root.get_inner()));
+            trailer.insert("Size", Primitive::Integer(1));
+            trailer.insert("Type", Primitive::name("Catalog"));
+            trailer.insert("Length", Primitive::Integer(0));
+            let data = writer.finish_with_trailer(trailer).unwrap();
+
+            let file = FileOptions::uncached().load(data).unwrap();
+            assert!(file.trailer.size > 1);
+            assert_eq!(file.num_pages(), 1);
+        }
+    }
+
+    #[test]
+    fn append_xref_stream_version() {
+        let mut writer = PdfWriter::with_version(Vec::new(), "1.4").unwrap();
+        let mut catalog = CatalogBuilder::from_pages(vec![PageBuilder::default()]).build(&mut writer).unwrap();
+        catalog.version = None;
+        let root = writer.write_object(&catalog).unwrap();
+        let original = writer.finish(root).unwrap();
+
+        // a plain xref table is fine for PDF 1.4
+        let mut writer = PdfWriter::append_to(Vec::new(), &original).unwrap();
+        writer.write_object(&Primitive::Integer(1)).unwrap();
+        let data = writer.finish_with_trailer(Dictionary::new()).unwrap();
+        assert_eq!(FileOptions::uncached().load(data).unwrap().get_root().version, None);
+
+        let mut writer = PdfWriter::append_to(Vec::new(), &original).unwrap().object_streams(true);
+        writer.write_object(&Primitive::Integer(1)).unwrap();
+        let data = writer.finish_with_trailer(Dictionary::new()).unwrap();
+        let file = FileOptions::uncached().load(data).unwrap();
+        assert_eq!(file.get_root().version.as_ref().map(|v| v.as_str()), Some("1.5"));
+        assert_eq!(file.num_pages(), 1);
+
+        // a replaced catalog keeps its changes
+        let mut writer = PdfWriter::append_to(Vec::new(), &original).unwrap().object_streams(true);
+        let mut dict = catalog.to_primitive(&mut writer).unwrap().into_dictionary().unwrap();
+        dict.insert("Lang", Primitive::String("de".into()));
+        writer.fill(root.get_inner(), &Primitive::Dictionary(dict)).unwrap();
+        let data = writer.finish_with_trailer(Dictionary::new()).unwrap();
+        let file = FileOptions::uncached().load(data).unwrap();
+        let catalog = file.resolver().resolve(root.get_inner()).unwrap().into_dictionary().unwrap();
+        assert_eq!(catalog["Version"], Primitive::name("1.5"));
+        assert_eq!(catalog["Lang"], Primitive::String("de".into()));
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn prev_offset() {
+        let original = Original {
+            trailer: Dictionary::new(),
+            xref_pos: u32::MAX as usize + 2,
+            replaced: BTreeMap::new(),
+            header_version: "1.7".into(),
+            catalog: None,
+        };
+        let trailer = original.update_trailer(Dictionary::new());
+        assert_eq!(trailer["Prev"], Primitive::Integer(u32::MAX as i64 + 2));
+    }
+}
@@ -125,13 +125,30 @@ impl XRefTable {
         Ok(())
     }
 
+    /// Add the entries of a section that was superseded by the sections already in the table,
+    /// i.e. one found through `/Prev`. Only entries that are still unspecified are filled in.
+    pub fn add_older_entries_from(&mut self, section: XRefSection) {
+        for (i, &entry) in section.entries() {
+            if let Some(dst @ XRef::Invalid) = self.entries.get_mut(i) {
+                *dst = entry;
+            }
+        }
+    }
+
     pub fn write_stream(&self, size: usize) -> Result<Stream<XRefInfo>> {
+        self.write_stream_sections(size, &[(0, size)])
+    }
+    /// Like `write_stream`, but only with the entries of the subsections, given as
+    /// `(first object number, number of entries)`.
+    pub fn write_stream_sections(&self, size: usize, sections: &[(ObjNr, usize)]) -> Result<Stream<XRefInfo>> {
         let (max_a, max_b) = self.max_field_widths();
         let a_w = byte_len(max_a);
         let b_w = byte_len(max_b);
 
-        let mut data = Vec::with_capacity((1 + a_w + b_w) * size);
-        for &x in self.entries.iter().take(size) {
+        let num_entries = sections.iter().map(|&(_, n)| n).sum::<usize>();
+        let mut data = Vec::with_capacity((1 + a_w + b_w) * num_entries);
+        let entries = sections.iter().flat_map(|&(start, n)| self.entries.iter().skip(start as usize).take(n));
+        for &x in entries {
             let (t, a, b) = match x {
                 XRef::Free { next_obj_nr, gen_nr } => (0, next_obj_nr, gen_nr),
                 XRef::Raw { pos, gen_nr } => (1, pos as u64, gen_nr),
@@ -144,7 +161,7 @@ impl XRefTable {
         }
         let info = XRefInfo {
             size: size as u32,
-            index: vec![0, size as u32],
+            index: sections.iter().flat_map(|&(start, n)| [start as u32, n as u32]).collect(),
             prev: None,
             w: vec![1, a_w, b_w],
         };
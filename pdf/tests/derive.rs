@@ -0,0 +1,73 @@
+use pdf::object::*;
+use pdf::primitive::{Dictionary, Primitive};
+use pdf::error::PdfError;
+use pdf_derive::{Object, ObjectWrite};
+
+#[derive(Object, ObjectWrite, Debug)]
+#[pdf(Type = "Page")]
+struct MyPage {
+    #[pdf(key = "MediaBox")]
+    media_box: Rectangle,
+
+    #[pdf(key = "Rotate", default = "0")]
+    rotate: i32,
+
+    #[pdf(key = "UserUnit")]
+    user_unit: Option<f32>,
+
+    // no key attribute, so the key is `extra`
+    extra: Option<i32>,
+}
+
+fn page_dict() -> Dictionary {
+    let mut dict = Dictionary::new();
+    dict.insert("Type", Primitive::name("Page"));
+    dict.insert("MediaBox", Primitive::Array(vec![0.into(), 0.into(), 612.into(), 792.into()]));
+    dict.insert("extra", 7);
+    dict
+}
+
+#[test]
+fn derived_struct() {
+    let page = MyPage::from_primitive(page_dict().into(), &NoResolve).unwrap();
+    assert_eq!(page.media_box.width(), 612.);
+    assert_eq!(page.rotate, 0);
+    assert_eq!(page.user_unit, None);
+    assert_eq!(page.extra, Some(7));
+
+    let p = page.to_primitive(&mut NoUpdate).unwrap();
+    let dict = p.into_dictionary().unwrap();
+    assert_eq!(dict["Type"], Primitive::name("Page"));
+    assert_eq!(dict["extra"], Primitive::Integer(7));
+}
+
+#[test]
+fn none_fields_are_omitted() {
+    let mut page = MyPage::from_primitive(page_dict().into(), &NoResolve).unwrap();
+    page.extra = None;
+    let dict = page.to_primitive(&mut NoUpdate).unwrap().into_dictionary().unwrap();
+    assert!(dict.get("UserUnit").is_none());
+    assert!(dict.get("extra").is_none());
+    assert_eq!(dict.len(), 3);
+
+    page.user_unit = Some(2.0);
+    let dict = page.to_primitive(&mut NoUpdate).unwrap().into_dictionary().unwrap();
+    assert_eq!(dict["UserUnit"], Primitive::Number(2.0));
+}
+
+#[test]
+fn derived_struct_errors() {
+    let mut dict = page_dict();
+    dict.insert("Type", Primitive::name("Pages"));
+    assert!(matches!(
+        MyPage::from_primitive(dict.into(), &NoResolve),
+        Err(PdfError::KeyValueMismatch { .. })
+    ));
+
+    let mut dict = page_dict();
+    dict.remove("MediaBox");
+    assert!(matches!(
+        MyPage::from_primitive(dict.into(), &NoResolve),
+        Err(PdfError::MissingEntry { typ: "MyPage", ref field }) if field == "MediaBox"
+    ));
+}
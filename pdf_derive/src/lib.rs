@@ -42,6 +42,12 @@
 //! a few of those in the PDF specs - one or many). However, as stated, it accepts absense of the
 //! entry, so **required** fields of type array aren't yet facilitated for.
 //!
+//! When writing, fields that serialize to `null` (like an `Option<T>` that is `None`) are left out
+//! of the dictionary instead of being written as `/Key null`.
+//!
+//! The dictionary key of a field is set with the `key` attribute. Without it, the name of the field
+//! is used as the key.
+//!
 //! Lastly, for each field, it's possible to define a default value by setting the `default`
 //! attribute to a string that can parse as Rust code.
 //!
@@ -155,8 +161,15 @@ impl FieldAttrs {
             indirect: false,
         }
     }
-    fn key(&self) -> &LitStr {
-        self.key.as_ref().expect("no 'key' in field attributes")
+    /// The dictionary key of the field. Defaults to the name of the field.
+    fn key(&self, field: &Option<Ident>) -> LitStr {
+        match self.key {
+            Some(ref key) => key.clone(),
+            None => {
+                let name = field.as_ref().expect("fields need a name or a 'key' attribute");
+                LitStr::new(&name.to_string(), name.span())
+            }
+        }
     }
     fn default(&self) -> Option<Expr> {
         self.default.as_ref().map(|s| parse_str(&s.value()).expect("can't parse `default` as EXPR"))
@@ -364,7 +377,7 @@ fn impl_object_for_enum(ast: &DeriveInput, data: &DataEnum) -> SynStream {
                                 _ => Err(pdf::error::PdfError::UnknownVariant { id: stringify!(#id), name: i.to_string() })
                             }
                         }
-                        _ => Err(pdf::error::PdfError::UnexpectedPrimitive { expected: "Integer", found: p.get_debug_name() }),
+                        _ => Err(pdf::error::PdfError::UnexpectedPrimitive { expected: "Integer", found: p.type_name() }),
                     }
                 }
             }
@@ -400,7 +413,7 @@ fn impl_object_for_enum(ast: &DeriveInput, data: &DataEnum) -> SynStream {
                                 #( #parts, )*
                             }
                         }
-                        _ => Err(pdf::error::PdfError::UnexpectedPrimitive { expected: "Name", found: p.get_debug_name() }),
+                        _ => Err(pdf::error::PdfError::UnexpectedPrimitive { expected: "Name", found: p.type_name() }),
                     }
                 }
             }
@@ -420,7 +433,7 @@ fn impl_objectwrite_for_enum(ast: &DeriveInput, data: &DataEnum) -> SynStream {
             if let Some((_, ref expr)) = var.discriminant {
                 let var_ident = &var.ident;
                 quote! {
-                    #id::#var_ident => Ok(Primitive::Integer(#expr))
+                    #id::#var_ident => Ok(pdf::primitive::Primitive::Integer(#expr))
                 }
             } else {
                 panic!()
@@ -429,7 +442,7 @@ fn impl_objectwrite_for_enum(ast: &DeriveInput, data: &DataEnum) -> SynStream {
 
         quote! {
             impl #impl_generics pdf::object::ObjectWrite for #id #ty_generics #where_clause {
-                fn to_primitive(&self, update: &mut impl pdf::object::Updater) -> Result<Primitive> {
+                fn to_primitive(&self, update: &mut impl pdf::object::Updater) -> pdf::error::Result<pdf::primitive::Primitive> {
                     match *self {
                         #( #parts, )*
                     }
@@ -456,12 +469,12 @@ fn impl_objectwrite_for_enum(ast: &DeriveInput, data: &DataEnum) -> SynStream {
 
         quote! {
             impl #impl_generics pdf::object::ObjectWrite for #id #ty_generics #where_clause {
-                fn to_primitive(&self, update: &mut impl pdf::object::Updater) -> Result<Primitive> {
+                fn to_primitive(&self, update: &mut impl pdf::object::Updater) -> pdf::error::Result<pdf::primitive::Primitive> {
                     let name = match *self {
                         #( #ser_code, )*
                     };
                     
-                    Ok(Primitive::Name(name.into()))
+                    Ok(pdf::primitive::Primitive::Name(name.into()))
                 }
             }
         }
@@ -498,7 +511,7 @@ fn impl_deepclone_for_enum(ast: &DeriveInput, data: &DataEnum) -> SynStream {
 
     quote! {
         impl #impl_generics pdf::object::DeepClone for #id #ty_generics #where_clause {
-            fn deep_clone(&self, cloner: &mut impl pdf::object::Cloner) -> Result<Self> {
+            fn deep_clone(&self, cloner: &mut impl pdf::object::Cloner) -> pdf::error::Result<Self> {
                 match *self {
                     #( #parts, )*
                 }
@@ -603,7 +616,7 @@ fn impl_object_for_struct(ast: &DeriveInput, fields: &Fields) -> SynStream {
             };
         }
 
-        let key = attrs.key();
+        let key = attrs.key(name);
 
         let ty = field.ty.clone();
         if let Some(ref default) = attrs.default() {
@@ -641,7 +654,7 @@ fn impl_object_for_struct(ast: &DeriveInput, fields: &Fields) -> SynStream {
                                 Ok(obj) => obj,
                                 Err(_) => return Err(pdf::error::PdfError::MissingEntry {
                                     typ: #typ,
-                                    field: String::from(stringify!(#name)),
+                                    field: String::from(#key),
                                 })
                             },
                     }
@@ -713,7 +726,7 @@ fn impl_objectwrite_for_struct(ast: &DeriveInput, fields: &Fields) -> SynStream
         if attrs.skip | attrs.other {
             quote!()
         } else {
-            let key = attrs.key();
+            let key = attrs.key(field);
             let tr = if attrs.indirect {
                 quote! {
                     match val {
@@ -759,12 +772,12 @@ fn impl_objectwrite_for_struct(ast: &DeriveInput, fields: &Fields) -> SynStream
 
     quote! {
         impl #impl_generics pdf::object::ObjectWrite for #id #ty_generics #where_clause {
-            fn to_primitive(&self, update: &mut impl pdf::object::Updater) -> Result<pdf::primitive::Primitive> {
+            fn to_primitive(&self, update: &mut impl pdf::object::Updater) -> pdf::error::Result<pdf::primitive::Primitive> {
                 pdf::object::ToDict::to_dict(self, update).map(pdf::primitive::Primitive::Dictionary)
             }
         }
         impl #impl_generics pdf::object::ToDict for #id #ty_generics #where_clause {
-            fn to_dict(&self, updater: &mut impl pdf::object::Updater) -> Result<pdf::primitive::Dictionary> {
+            fn to_dict(&self, updater: &mut impl pdf::object::Updater) -> pdf::error::Result<pdf::primitive::Dictionary> {
                 #init_dict
                 #pdf_type
                 #( #checks_code )*
@@ -802,7 +815,7 @@ fn impl_deepclone_for_struct(ast: &DeriveInput, fields: &Fields) -> SynStream {
 
     quote! {
         impl #impl_generics pdf::object::DeepClone for #id #ty_generics #where_clause {
-            fn deep_clone(&self, cloner: &mut impl pdf::object::Cloner) -> Result<Self> {
+            fn deep_clone(&self, cloner: &mut impl pdf::object::Cloner) -> pdf::error::Result<Self> {
                 Ok(#id {
                     #( #field_parts )*
                 })